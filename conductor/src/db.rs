@@ -84,6 +84,19 @@ impl Database {
         Ok(())
     }
 
+    /// Rust code stored for a task, if it has been ported
+    pub async fn get_task_code(&self, id: &str) -> Result<Option<String>> {
+        let row: Option<(Option<String>,)> = sqlx::query_as(
+            "SELECT code_rust FROM tasks WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch task code")?;
+
+        Ok(row.and_then(|(code,)| code))
+    }
+
     pub async fn get_task_state(&self, id: &str) -> Result<Option<TaskState>> {
         let row: Option<(String,)> = sqlx::query_as(
             "SELECT state FROM tasks WHERE id = ?"
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use kernel_schema::AtomicUnit;
+use kernel_schema::{AtomicUnit, UnitKind};
 use async_trait::async_trait;
 
 #[async_trait]
@@ -15,10 +15,24 @@ impl LlmClient for MockLlmClient {
         // Simulated transpilation delay
         tokio::time::sleep(std::time::Duration::from_millis(500)).await;
         
-        // Mock output: just a comment wrapping the C code for now
+        // Mock output: an empty item of the right shape for the unit kind
+        let name: String = unit.id
+            .rsplit(':')
+            .next()
+            .unwrap_or(&unit.id)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        let item = match unit.kind {
+            UnitKind::Function => format!("fn {}() {{\n    println!(\"Simulated Rust version of {}\");\n}}", name, unit.id),
+            UnitKind::TypeDefinition => format!("pub struct {};", name),
+            UnitKind::GlobalData => format!("pub static {}: () = ();", name.to_uppercase()),
+            UnitKind::FunctionMacro => format!("macro_rules! {} {{\n    () => {{}};\n}}", name),
+            UnitKind::InlineAsm => format!("// global_asm! block from {}", unit.id),
+        };
         let rust_code = format!(
-            "// Transpiled from C function: {}\n// Dependencies: {:?}\n\nfn {}() {{\n    println!(\"Simulated Rust version of {}\");\n}}",
-            unit.id, unit.dependencies, unit.id, unit.id
+            "// Transpiled from C {}: {}\n// Dependencies: {:?}\n\n{}",
+            unit.kind, unit.id, unit.dependency_ids(), item
         );
         
         Ok(rust_code)
@@ -3,46 +3,39 @@ mod llm;
 mod verifier;
 
 use anyhow::{Context, Result};
-use kernel_schema::AtomicUnit;
-use serde::{Deserialize, Serialize};
+use clap::Parser;
+use kernel_schema::{AtomicUnit, BuildOrder, DecompositionPlan, DecompositionStep, UnitsFile};
 use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::path::PathBuf;
 use db::{Database, TaskState};
 use llm::{LlmClient, MockLlmClient};
 use verifier::Verifier;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildOrderBatch {
-    pub units: Vec<String>,
-    pub is_super_node: bool,
-    pub scc_size: Option<usize>,
-    pub refactoring_difficulty: Option<String>,
-}
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Dispatch Atomic Units to the LLM in build order")]
+struct Args {
+    /// Path to the units file (.json, .jsonl or .cbor)
+    #[arg(short, long, default_value = "units.json")]
+    units: PathBuf,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildOrder {
-    pub metadata: BuildMetadata,
-    pub batches: Vec<BuildOrderBatch>,
-}
+    /// Path to the build order (.json, .jsonl or .cbor)
+    #[arg(short, long, default_value = "build_order.json")]
+    build_order: PathBuf,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildMetadata {
-    pub total_units: usize,
-    pub total_batches: usize,
-    pub super_nodes: usize,
-    pub largest_super_node: usize,
-    pub average_batch_size: f64,
+    /// Path to the SQLite blackboard
+    #[arg(long, default_value = "blackboard.db")]
+    db: PathBuf,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
     println!("Conductor: LLM Orchestration Engine");
     
     // Paths
-    let db_path = Path::new("blackboard.db");
-    let build_order_path = Path::new("build_order.json");
-    let units_path = Path::new("units.json");
+    let db_path = args.db.as_path();
+    let build_order_path = args.build_order.as_path();
+    let units_path = args.units.as_path();
 
     // Initialize database
     let db = Database::new(db_path).await
@@ -51,21 +44,27 @@ async fn main() -> Result<()> {
 
     // Load units
     println!("Conductor: Loading units from {:?}", units_path);
-    let units_json = fs::read_to_string(units_path)
-        .context("Failed to read units.json")?;
-    let units_vec: Vec<AtomicUnit> = serde_json::from_str(&units_json)
-        .context("Failed to parse units.json")?;
-    let units_map: HashMap<String, AtomicUnit> = units_vec
+    let units_file = UnitsFile::load(units_path)
+        .with_context(|| format!("Failed to load units file {:?}", units_path))?;
+    let units_map: HashMap<String, AtomicUnit> = units_file
+        .inline_units()
         .into_iter()
         .map(|u| (u.id.clone(), u))
         .collect();
 
     // Load build order
     println!("Conductor: Loading build order from {:?}", build_order_path);
-    let build_order_json = fs::read_to_string(build_order_path)
-        .context("Failed to read build_order.json")?;
-    let build_order: BuildOrder = serde_json::from_str(&build_order_json)
-        .context("Failed to parse build_order.json")?;
+    let build_order = BuildOrder::load(build_order_path)
+        .with_context(|| format!("Failed to load build order {:?}", build_order_path))?;
+
+    // Fail fast on a build order that does not match the units
+    let errors = build_order.validate(&units_file.units);
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("Conductor: Invalid build order: {}", error);
+        }
+        anyhow::bail!("build_order.json failed validation with {} error(s)", errors.len());
+    }
 
     let llm = MockLlmClient;
 
@@ -73,6 +72,12 @@ async fn main() -> Result<()> {
 
     for (i, batch) in build_order.batches.iter().enumerate() {
         println!("Conductor: [Batch {}/{}] Processing {} units", i + 1, build_order.batches.len(), batch.units.len());
+
+        // Super nodes are ported one member at a time, against stubs of the members not yet ported
+        if let Some(plan) = &batch.decomposition {
+            run_decomposition(&db, &llm, &units_map, plan).await?;
+            continue;
+        }
         
         let mut futures = Vec::new();
         for unit_id in &batch.units {
@@ -102,6 +107,65 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+async fn run_decomposition(
+    db: &Database,
+    llm: &impl LlmClient,
+    units_map: &HashMap<String, AtomicUnit>,
+    plan: &DecompositionPlan,
+) -> Result<()> {
+    for step in &plan.steps {
+        match step {
+            DecompositionStep::Port { unit: unit_id, stubs } => {
+                let Some(unit) = units_map.get(unit_id) else {
+                    eprintln!("Warning: Unit ID {} found in build order but not in units.json", unit_id);
+                    continue;
+                };
+                // The stubs' declarations go to the LLM as context, like headers
+                let mut unit = unit.clone();
+                unit.required_headers.extend(
+                    plan.stubs.iter().filter(|stub| stubs.contains(&stub.unit)).map(|stub| stub.declaration.clone()),
+                );
+                db.create_task(unit_id, unit_id).await?;
+                db.update_task_state(unit_id, TaskState::InProgress, None, None).await?;
+                if let Err(e) = process_unit(db, llm, &unit).await {
+                    eprintln!("Error processing unit: {:?}", e);
+                }
+            }
+            DecompositionStep::ReplaceStub { unit, reverify } => replace_stub(db, unit, reverify).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Re-verify the units that were ported against a stub of `unit`, now that the
+/// real unit has been ported in its place
+async fn replace_stub(db: &Database, unit: &str, reverify: &[String]) -> Result<()> {
+    let replaced = matches!(db.get_task_state(unit).await?, Some(TaskState::Completed));
+    println!("Conductor:  - Replacing stub of {}; re-verifying {} unit(s)", unit, reverify.len());
+    for id in reverify {
+        // Pending until re-verified: a port checked only against a stub is not done
+        let code = db.get_task_code(id).await?;
+        db.update_task_state(id, TaskState::Pending, code.as_deref(), None).await?;
+        let result = match &code {
+            _ if !replaced => Err(anyhow::anyhow!("Ported against a stub of {}, which failed to port", unit)),
+            None => Err(anyhow::anyhow!("No ported code to re-verify against {}", unit)),
+            Some(code) => Verifier::verify(code, id),
+        };
+        match result {
+            Ok(()) => {
+                println!("Conductor:  - Re-verified {} against {}", id, unit);
+                db.update_task_state(id, TaskState::Completed, code.as_deref(), None).await?;
+            }
+            Err(e) => {
+                let err_msg = e.to_string();
+                eprintln!("Conductor:  - Re-verification failed for {}: {}", id, err_msg);
+                db.update_task_state(id, TaskState::Failed, code.as_deref(), Some(&err_msg)).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn process_unit(db: &Database, llm: &impl LlmClient, unit: &AtomicUnit) -> Result<()> {
     println!("Conductor:  - Processing {}", unit.id);
     
@@ -8,7 +8,12 @@ pub struct Verifier;
 impl Verifier {
     pub fn verify(code_rust: &str, unit_id: &str) -> Result<()> {
         let temp_dir = env::temp_dir();
-        let file_path = temp_dir.join(format!("{}.rs", unit_id));
+        // Unit IDs such as `type:proc` are not valid file or crate names
+        let stem: String = unit_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        let file_path = temp_dir.join(format!("{}.rs", stem));
         
         fs::write(&file_path, code_rust)
             .with_context(|| format!("Failed to write temp Rust file {:?}", file_path))?;
@@ -19,7 +24,7 @@ impl Verifier {
             .arg("--emit")
             .arg("metadata") // Just check if it compiles, don't build full binary
             .arg("-o")
-            .arg(temp_dir.join(format!("{}.rmeta", unit_id)))
+            .arg(temp_dir.join(format!("{}.rmeta", stem)))
             .arg(&file_path)
             .output()
             .context("Failed to execute rustc")?;
@@ -0,0 +1,312 @@
+use crate::{AtomicUnit, DependencyKind};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Units the conductor may process together
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct BuildOrderBatch {
+    pub units: Vec<String>,
+    pub is_super_node: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scc_size: Option<usize>,
+    /// Low, Medium, High or Very High, from the number of edges the mapper
+    /// must break to make the super node acyclic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refactoring_difficulty: Option<String>,
+    /// Longest-path depth of the batch's units in the dependency DAG (0 = no
+    /// dependencies); batches on the same level do not depend on each other
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<usize>,
+    /// Expected tokens (prompt and response, retries included) to port the batch's units
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_tokens: Option<u64>,
+    /// For super nodes: the order to port members in against stubs of the rest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decomposition: Option<DecompositionPlan>,
+}
+
+/// build_order.json: the contract between the mapper and the conductor
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct BuildOrder {
+    pub metadata: BuildMetadata,
+    pub batches: Vec<BuildOrderBatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct BuildMetadata {
+    pub total_units: usize,
+    pub total_batches: usize,
+    pub super_nodes: usize,
+    pub largest_super_node: usize,
+    pub average_batch_size: f64,
+    /// Dependency kinds the mapper turned into edges (empty means all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edge_kinds: Vec<DependencyKind>,
+    /// Roots the build order was restricted to (empty means every unit)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<String>,
+    /// Changed units the build order re-verifies, with their dependents (empty means a full run)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
+    /// `(from, to)` dependency edges removed by overrides, e.g. because the port
+    /// breaks them with a callback; validation does not enforce them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_edges: Vec<(String, String)>,
+    /// Expected token spend and duration of the whole plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<PlanCost>,
+}
+
+/// What running a build order is expected to cost, estimated from unit sizes
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct PlanCost {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    /// Units the conductor is assumed to port at once
+    pub workers: usize,
+    /// Cap on a batch's estimated tokens, if batches were packed by cost
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_budget: Option<u64>,
+    /// Wall-clock length of the plan, in tokens one worker processes: batches run
+    /// one after another, each taking as long as its busiest worker
+    pub makespan_tokens: u64,
+}
+
+/// One entry of cycle_analysis.json
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct CycleAnalysis {
+    pub super_node: Vec<String>,
+    pub size: usize,
+    /// `(from, to)` dependency edges suggested for breaking the cycle
+    pub weak_edges: Vec<(String, String)>,
+    pub refactoring_suggestions: Vec<String>,
+    /// Edges whose removal makes the super node acyclic, with the order that results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback_arc_set: Option<FeedbackArcSet>,
+}
+
+/// How a feedback arc set was computed
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FasMethod {
+    /// Minimum total weight, found by exhaustive dynamic programming
+    Exact,
+    /// Eades–Lin–Smyth greedy approximation, for super nodes too large to solve exactly
+    EadesLinSmyth,
+}
+
+/// A dependency edge to break (stub or invert) so the rest of a super node can be ported in order
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct FeedbackArc {
+    /// The dependent unit, ported before `to` exists
+    pub from: String,
+    pub to: String,
+    pub call_sites: usize,
+}
+
+/// Edges that make a super node acyclic when removed
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct FeedbackArcSet {
+    pub method: FasMethod,
+    /// Removed edges, cheapest first
+    pub arcs: Vec<FeedbackArc>,
+    /// Call sites across all removed edges (the quantity minimized)
+    pub total_call_sites: usize,
+    /// Port order inside the super node once `arcs` are removed: dependencies first
+    pub order: Vec<String>,
+}
+
+/// A forward declaration standing in for a super node member until it is ported
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct Stub {
+    pub unit: String,
+    /// C declaration to port against: a prototype, `struct x;` or `extern`
+    /// declaration, or the full definition for members with no forward form (macros, enums)
+    pub declaration: String,
+    /// Members ported against this stub, in port order
+    pub used_by: Vec<String>,
+}
+
+/// One step of a [`DecompositionPlan`]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DecompositionStep {
+    /// Port `unit`, with the stubs of `stubs` standing in for members not yet ported
+    Port { unit: String, stubs: Vec<String> },
+    /// `unit` has been ported: drop its stub and re-verify the members ported against it
+    ReplaceStub { unit: String, reverify: Vec<String> },
+}
+
+/// How to port a super node one member at a time: each feedback arc becomes a
+/// stub of its target, which the real port of that target later replaces
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct DecompositionPlan {
+    pub stubs: Vec<Stub>,
+    pub steps: Vec<DecompositionStep>,
+}
+
+impl DecompositionPlan {
+    /// Units in the order the plan ports them
+    pub fn ported_units(&self) -> impl Iterator<Item = &str> {
+        self.steps.iter().filter_map(|step| match step {
+            DecompositionStep::Port { unit, .. } => Some(unit.as_str()),
+            DecompositionStep::ReplaceStub { .. } => None,
+        })
+    }
+
+    /// True if `dependency` is ported before `unit`, or stubbed when `unit` is ported
+    fn ports_before(&self, dependency: &str, unit: &str) -> bool {
+        for step in &self.steps {
+            if let DecompositionStep::Port { unit: ported, stubs } = step {
+                if ported == dependency {
+                    return true;
+                }
+                if ported == unit {
+                    return stubs.iter().any(|stub| stub == dependency);
+                }
+            }
+        }
+        // A unit missing from the plan is a PlanMismatch
+        true
+    }
+}
+
+/// A way a build order can disagree with its units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A batch names a unit that is not in units.json
+    UnknownUnit { unit: String, batch: usize },
+    /// A unit is scheduled more than once
+    DuplicateUnit { unit: String, first_batch: usize, batch: usize },
+    /// A unit is scheduled before (or alongside, outside a super node) one of its dependencies
+    OrderViolation {
+        unit: String,
+        dependency: String,
+        unit_batch: usize,
+        dependency_batch: usize,
+    },
+    /// A super node's decomposition plan does not port each of the batch's units exactly once
+    PlanMismatch { batch: usize },
+    /// A decomposition plan ports a unit before a member it depends on, with no stub for it
+    UnstubbedDependency { unit: String, dependency: String, batch: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::UnknownUnit { unit, batch } => {
+                write!(f, "batch {}: unit '{}' does not exist in units.json", batch, unit)
+            }
+            ValidationError::DuplicateUnit { unit, first_batch, batch } => {
+                write!(f, "batch {}: unit '{}' was already scheduled in batch {}", batch, unit, first_batch)
+            }
+            ValidationError::OrderViolation { unit, dependency, unit_batch, dependency_batch } => write!(
+                f,
+                "batch {}: unit '{}' depends on '{}', which is scheduled in batch {}",
+                unit_batch, unit, dependency, dependency_batch
+            ),
+            ValidationError::PlanMismatch { batch } => {
+                write!(f, "batch {}: decomposition plan does not port each unit of the batch exactly once", batch)
+            }
+            ValidationError::UnstubbedDependency { unit, dependency, batch } => write!(
+                f,
+                "batch {}: plan ports '{}' before '{}', which it depends on, without a stub",
+                batch, unit, dependency
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl BuildOrder {
+    /// Check this order against `units`: every scheduled unit exists, none is
+    /// scheduled twice, and every dependency on another scheduled unit lands in an
+    /// earlier batch (or the same batch, when that batch is a super node).
+    ///
+    /// A super node's decomposition plan must port each of its units once, and
+    /// only after the members it depends on unless the plan stubs them.
+    ///
+    /// Only dependencies of the kinds recorded in `metadata.edge_kinds` are checked,
+    /// and not those listed in `metadata.removed_edges`.
+    /// Units that are not scheduled at all are not an error; a targeted plan may
+    /// cover a subset of units.json.
+    pub fn validate(&self, units: &[AtomicUnit]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let by_id: HashMap<&str, &AtomicUnit> = units.iter().map(|u| (u.id.as_str(), u)).collect();
+
+        let mut batch_of: HashMap<&str, usize> = HashMap::new();
+        for (batch_idx, batch) in self.batches.iter().enumerate() {
+            for unit in &batch.units {
+                if !by_id.contains_key(unit.as_str()) {
+                    errors.push(ValidationError::UnknownUnit { unit: unit.clone(), batch: batch_idx });
+                }
+                match batch_of.get(unit.as_str()) {
+                    Some(&first_batch) => errors.push(ValidationError::DuplicateUnit {
+                        unit: unit.clone(),
+                        first_batch,
+                        batch: batch_idx,
+                    }),
+                    None => {
+                        batch_of.insert(unit, batch_idx);
+                    }
+                }
+            }
+        }
+
+        for (batch_idx, batch) in self.batches.iter().enumerate() {
+            let Some(plan) = &batch.decomposition else { continue };
+            let mut ported: Vec<&str> = plan.ported_units().collect();
+            let mut units: Vec<&str> = batch.units.iter().map(String::as_str).collect();
+            ported.sort_unstable();
+            units.sort_unstable();
+            if ported != units {
+                errors.push(ValidationError::PlanMismatch { batch: batch_idx });
+            }
+        }
+
+        let removed = |from: &str, to: &str| self.metadata.removed_edges.iter().any(|(f, t)| f == from && t == to);
+        let checked = |kind: DependencyKind| self.metadata.edge_kinds.is_empty() || self.metadata.edge_kinds.contains(&kind);
+
+        for (batch_idx, batch) in self.batches.iter().enumerate() {
+            for unit_id in &batch.units {
+                let Some(unit) = by_id.get(unit_id.as_str()) else { continue };
+                let mut reported = HashSet::new();
+                for dep in unit.dependencies.iter().filter(|d| checked(d.kind)) {
+                    let Some((dep_id, dep_batch)) =
+                        dep.resolve_with(|id| batch_of.get_key_value(id).map(|(&id, &batch)| (id, batch)))
+                    else {
+                        continue;
+                    };
+                    if dep_id == unit_id || !reported.insert(dep_id) || removed(unit_id, dep_id) {
+                        continue;
+                    }
+                    if let Some(plan) = &batch.decomposition
+                        && dep_batch == batch_idx
+                        && !plan.ports_before(dep_id, unit_id)
+                    {
+                        errors.push(ValidationError::UnstubbedDependency {
+                            unit: unit_id.clone(),
+                            dependency: dep_id.to_string(),
+                            batch: batch_idx,
+                        });
+                    }
+                    let ok = dep_batch < batch_idx || (dep_batch == batch_idx && batch.is_super_node);
+                    if !ok {
+                        errors.push(ValidationError::OrderViolation {
+                            unit: unit_id.clone(),
+                            dependency: dep_id.to_string(),
+                            unit_batch: batch_idx,
+                            dependency_batch: dep_batch,
+                        });
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{SymbolKind, UnitKind};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a unit depends on its target
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    /// `foo(...)` where `foo` is a function
+    DirectCall,
+    /// Call through a function pointer or expression (`p->fn(...)`, `handler()`),
+    /// or taking a function's address (`handler = foo`), which lets it be called that way
+    IndirectCall,
+    /// Mentions a struct, union, enum or typedef
+    TypeUse,
+    /// Reads a file-scope variable
+    GlobalRead,
+    /// Assigns to (or increments) a file-scope variable
+    GlobalWrite,
+    /// Expands a macro, object-like or function-like
+    MacroUse,
+}
+
+impl DependencyKind {
+    pub const ALL: [DependencyKind; 6] = [
+        DependencyKind::DirectCall,
+        DependencyKind::IndirectCall,
+        DependencyKind::TypeUse,
+        DependencyKind::GlobalRead,
+        DependencyKind::GlobalWrite,
+        DependencyKind::MacroUse,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DependencyKind::DirectCall => "direct_call",
+            DependencyKind::IndirectCall => "indirect_call",
+            DependencyKind::TypeUse => "type_use",
+            DependencyKind::GlobalRead => "global_read",
+            DependencyKind::GlobalWrite => "global_write",
+            DependencyKind::MacroUse => "macro_use",
+        }
+    }
+
+    /// True for the two call kinds
+    pub fn is_call(self) -> bool {
+        matches!(self, DependencyKind::DirectCall | DependencyKind::IndirectCall)
+    }
+
+    /// Kind of unit a dependency of this kind points at
+    pub fn target_unit_kind(self) -> UnitKind {
+        match self {
+            DependencyKind::DirectCall | DependencyKind::IndirectCall => UnitKind::Function,
+            DependencyKind::TypeUse => UnitKind::TypeDefinition,
+            DependencyKind::GlobalRead | DependencyKind::GlobalWrite => UnitKind::GlobalData,
+            DependencyKind::MacroUse => UnitKind::FunctionMacro,
+        }
+    }
+
+    /// Kind of symbol-table entry that declares what a dependency of this kind points at
+    pub fn symbol_kind(self) -> SymbolKind {
+        match self {
+            DependencyKind::DirectCall | DependencyKind::IndirectCall => SymbolKind::Prototype,
+            DependencyKind::TypeUse => SymbolKind::Type,
+            DependencyKind::GlobalRead | DependencyKind::GlobalWrite => SymbolKind::Global,
+            DependencyKind::MacroUse => SymbolKind::Macro,
+        }
+    }
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DependencyKind {
+    type Err = String;
+
+    /// Accepts the serialized names, with `-` or `_` as separator
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().replace('-', "_");
+        DependencyKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == normalized)
+            .ok_or_else(|| {
+                let known: Vec<&str> = DependencyKind::ALL.iter().map(|k| k.as_str()).collect();
+                format!("unknown dependency kind '{}' (expected one of: {})", s, known.join(", "))
+            })
+    }
+}
+
+/// A position in a source file (1-based line and column)
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A typed edge from a unit to something it needs
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Hash)]
+pub struct Dependency {
+    /// Unit ID or symbol name the dependency points at
+    pub target: String,
+    pub kind: DependencyKind,
+    /// Number of references in the unit (call sites, for calls)
+    pub call_sites: usize,
+    /// First reference in the unit, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+}
+
+impl Dependency {
+    pub fn new(target: impl Into<String>, kind: DependencyKind) -> Self {
+        Self {
+            target: target.into(),
+            kind,
+            call_sites: 1,
+            location: None,
+        }
+    }
+
+    /// Shorthand for a single direct call with no location
+    pub fn call(target: impl Into<String>) -> Self {
+        Self::new(target, DependencyKind::DirectCall)
+    }
+
+    /// ID of the unit this dependency points at: `type:proc` for a use of
+    /// `proc`, `global:ticks` for a read of `ticks`, the bare name for a call
+    pub fn unit_id(&self) -> Cow<'_, str> {
+        match self.kind.target_unit_kind() {
+            UnitKind::Function => Cow::Borrowed(&self.target),
+            kind => Cow::Owned(kind.unit_id(&self.target)),
+        }
+    }
+
+    /// Look up the unit this dependency points at, trying [`unit_id`](Self::unit_id)
+    /// and then the bare target (units files without type, data or macro units)
+    pub fn resolve_with<T>(&self, mut lookup: impl FnMut(&str) -> Option<T>) -> Option<T> {
+        lookup(&self.unit_id()).or_else(|| lookup(&self.target))
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{digest, AtomicUnit, DependencyKind, UnitKind, UnitsFile};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// Differences between two units files
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct UnitsDiff {
+    /// Units only in the new file
+    pub added: Vec<String>,
+    /// Units only in the old file
+    pub removed: Vec<String>,
+    /// Units whose ID changed but whose content hash did not
+    pub renamed: Vec<RenamedUnit>,
+    /// Units present in both files (under the same ID, or renamed) that changed
+    pub modified: Vec<ModifiedUnit>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct RenamedUnit {
+    pub from: String,
+    pub to: String,
+}
+
+/// A dependency, identified by what it points at and how
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DependencyKey {
+    pub target: String,
+    pub kind: DependencyKind,
+}
+
+impl fmt::Display for DependencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.target, self.kind)
+    }
+}
+
+/// How a unit changed between two files
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ModifiedUnit {
+    /// ID in the new file
+    pub id: String,
+    /// ID in the old file, when the unit was renamed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    /// The code differs in more than whitespace
+    pub code_changed: bool,
+    /// Old and new kind, when the kind changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind_changed: Option<(UnitKind, UnitKind)>,
+    pub dependencies_added: Vec<DependencyKey>,
+    pub dependencies_removed: Vec<DependencyKey>,
+    /// Context definitions (inlined from the symbol table) now needed
+    pub headers_added: Vec<String>,
+    /// Context definitions no longer needed, or whose text changed
+    pub headers_removed: Vec<String>,
+}
+
+impl UnitsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Units in the new file whose ported code no longer matches the C source:
+    /// every modified unit and every renamed one
+    pub fn stale_units(&self) -> Vec<&str> {
+        let mut stale: BTreeSet<&str> = self.modified.iter().map(|m| m.id.as_str()).collect();
+        stale.extend(self.renamed.iter().map(|r| r.to.as_str()));
+        stale.into_iter().collect()
+    }
+
+    /// Units in the new file that depended, in `old`, on a unit that has since
+    /// been removed: their ports still reference code that no longer exists
+    pub fn dependents_of_removed<'a>(&self, old: &UnitsFile, new: &'a UnitsFile) -> Vec<&'a str> {
+        let removed: BTreeSet<&str> = self.removed.iter().map(String::as_str).collect();
+        let renamed: HashMap<&str, &str> = self.renamed.iter().map(|r| (r.from.as_str(), r.to.as_str())).collect();
+        let renamed_from: HashMap<&str, &str> =
+            self.modified.iter().filter_map(|m| Some((m.renamed_from.as_deref()?, m.id.as_str()))).collect();
+        let in_new: HashMap<&str, &'a str> = new.units.iter().map(|unit| (unit.id.as_str(), unit.id.as_str())).collect();
+        let mut dependents = BTreeSet::new();
+        for unit in &old.units {
+            if removed.contains(unit.id.as_str())
+                || !unit.dependencies.iter().any(|dep| dep.resolve_with(|id| removed.get(id)).is_some())
+            {
+                continue;
+            }
+            let id = unit.id.as_str();
+            let new_id = renamed.get(id).or_else(|| renamed_from.get(id)).copied().unwrap_or(id);
+            if let Some(&new_id) = in_new.get(new_id) {
+                dependents.insert(new_id);
+            }
+        }
+        dependents.into_iter().collect()
+    }
+}
+
+/// Compare two units files.
+///
+/// Units are matched by ID first. Among the rest, an old and a new unit with the
+/// same [`content_hash`] are reported as a rename when the hash is unique on both
+/// sides. Dependencies are compared by target and kind, so a unit that merely
+/// moved within its file is unchanged. Headers are compared after inlining the
+/// symbol table, so editing `struct proc` modifies every unit that uses it.
+pub fn diff_units(old: &UnitsFile, new: &UnitsFile) -> UnitsDiff {
+    let old_units: BTreeMap<&str, AtomicUnit> = old.units.iter().map(|u| (u.id.as_str(), old.inline_unit(u))).collect();
+    let new_units: BTreeMap<&str, AtomicUnit> = new.units.iter().map(|u| (u.id.as_str(), new.inline_unit(u))).collect();
+
+    let mut diff = UnitsDiff::default();
+    let mut removed: Vec<&str> = old_units.keys().filter(|id| !new_units.contains_key(*id)).copied().collect();
+    let mut added: Vec<&str> = new_units.keys().filter(|id| !old_units.contains_key(*id)).copied().collect();
+
+    // Pair up renames by content hash, only where the match is unambiguous
+    let old_hashes = unique_hashes(&removed, &old_units);
+    let new_hashes = unique_hashes(&added, &new_units);
+    let mut pairs: Vec<(&str, &str)> = old_hashes
+        .iter()
+        .filter_map(|(hash, from)| new_hashes.get(hash).map(|to| (*from, *to)))
+        .collect();
+    pairs.sort_unstable();
+
+    removed.retain(|id| !pairs.iter().any(|(from, _)| from == id));
+    added.retain(|id| !pairs.iter().any(|(_, to)| to == id));
+
+    let mut compared: Vec<(&str, Option<&str>)> = old_units
+        .keys()
+        .filter(|id| new_units.contains_key(*id))
+        .map(|id| (*id, None))
+        .collect();
+    for (from, to) in &pairs {
+        diff.renamed.push(RenamedUnit { from: from.to_string(), to: to.to_string() });
+        compared.push((to, Some(from)));
+    }
+    compared.sort_unstable();
+
+    for (id, renamed_from) in compared {
+        let old_unit = &old_units[renamed_from.unwrap_or(id)];
+        if let Some(modified) = compare_unit(old_unit, &new_units[id], renamed_from) {
+            diff.modified.push(modified);
+        }
+    }
+
+    diff.added = added.into_iter().map(str::to_string).collect();
+    diff.removed = removed.into_iter().map(str::to_string).collect();
+    diff
+}
+
+/// Content hash -> ID, for hashes that only one of `ids` has
+fn unique_hashes<'a>(ids: &[&'a str], units: &BTreeMap<&str, AtomicUnit>) -> HashMap<String, &'a str> {
+    let mut by_hash: HashMap<String, Vec<&'a str>> = HashMap::new();
+    for id in ids {
+        by_hash.entry(content_hash(&units[id])).or_default().push(id);
+    }
+    by_hash
+        .into_iter()
+        .filter(|(_, ids)| ids.len() == 1)
+        .map(|(hash, ids)| (hash, ids[0]))
+        .collect()
+}
+
+fn compare_unit(old: &AtomicUnit, new: &AtomicUnit, renamed_from: Option<&str>) -> Option<ModifiedUnit> {
+    let dependency_keys = |unit: &AtomicUnit| -> BTreeSet<DependencyKey> {
+        unit.dependencies
+            .iter()
+            .map(|d| DependencyKey { target: d.target.clone(), kind: d.kind })
+            .collect()
+    };
+    let old_deps = dependency_keys(old);
+    let new_deps = dependency_keys(new);
+    let old_headers: BTreeSet<&String> = old.required_headers.iter().collect();
+    let new_headers: BTreeSet<&String> = new.required_headers.iter().collect();
+
+    let modified = ModifiedUnit {
+        id: new.id.clone(),
+        renamed_from: renamed_from.map(str::to_string),
+        // A renamed unit's code necessarily mentions the new name
+        code_changed: match renamed_from {
+            Some(_) => content_hash(old) != content_hash(new),
+            None => normalize_whitespace(&old.code) != normalize_whitespace(&new.code),
+        },
+        kind_changed: (old.kind != new.kind).then_some((old.kind, new.kind)),
+        dependencies_added: new_deps.difference(&old_deps).cloned().collect(),
+        dependencies_removed: old_deps.difference(&new_deps).cloned().collect(),
+        headers_added: new_headers.difference(&old_headers).map(|h| h.to_string()).collect(),
+        headers_removed: old_headers.difference(&new_headers).map(|h| h.to_string()).collect(),
+    };
+
+    let changed = modified.code_changed
+        || modified.kind_changed.is_some()
+        || !modified.dependencies_added.is_empty()
+        || !modified.dependencies_removed.is_empty()
+        || !modified.headers_added.is_empty()
+        || !modified.headers_removed.is_empty();
+    changed.then_some(modified)
+}
+
+/// Digest of a unit's code that survives renaming it: whitespace is collapsed
+/// and whole-word occurrences of the unit's own name are masked (asm blocks are
+/// named after their position, so moving one is a rename as well)
+pub fn content_hash(unit: &AtomicUnit) -> String {
+    let name = match unit.kind {
+        UnitKind::InlineAsm => "",
+        _ => unit.id.rsplit(':').next().unwrap_or(&unit.id),
+    };
+    let mut masked = String::with_capacity(unit.code.len());
+    for word in normalize_whitespace(&unit.code).split_inclusive(|c: char| !(c == '_' || c.is_ascii_alphanumeric())) {
+        let end = word.find(|c: char| !(c == '_' || c.is_ascii_alphanumeric())).unwrap_or(word.len());
+        if !name.is_empty() && &word[..end] == name {
+            masked.push('\u{0}');
+            masked.push_str(&word[end..]);
+        } else {
+            masked.push_str(word);
+        }
+    }
+    format!("{}:{}", unit.kind, digest(masked.as_bytes()))
+}
+
+fn normalize_whitespace(code: &str) -> String {
+    code.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl fmt::Display for UnitsDiff {
+    /// Human-readable report, one line per change
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+        for id in &self.added {
+            writeln!(f, "+ {}", id)?;
+        }
+        for id in &self.removed {
+            writeln!(f, "- {}", id)?;
+        }
+        for rename in &self.renamed {
+            writeln!(f, "R {} -> {}", rename.from, rename.to)?;
+        }
+        for unit in &self.modified {
+            match &unit.renamed_from {
+                Some(from) => writeln!(f, "M {} (was {})", unit.id, from)?,
+                None => writeln!(f, "M {}", unit.id)?,
+            }
+            if unit.code_changed {
+                writeln!(f, "    code changed")?;
+            }
+            if let Some((old, new)) = unit.kind_changed {
+                writeln!(f, "    kind: {} -> {}", old, new)?;
+            }
+            for dep in &unit.dependencies_added {
+                writeln!(f, "    + depends on {}", dep)?;
+            }
+            for dep in &unit.dependencies_removed {
+                writeln!(f, "    - depends on {}", dep)?;
+            }
+            for header in &unit.headers_added {
+                writeln!(f, "    + header {}", first_line(header))?;
+            }
+            for header in &unit.headers_removed {
+                writeln!(f, "    - header {}", first_line(header))?;
+            }
+        }
+        writeln!(
+            f,
+            "{} added, {} removed, {} renamed, {} modified",
+            self.added.len(),
+            self.removed.len(),
+            self.renamed.len(),
+            self.modified.len()
+        )
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("").trim()
+}
@@ -0,0 +1,424 @@
+//! On-disk encodings for the pipeline files.
+//!
+//! * `.json` — one pretty-printed document (the default)
+//! * `.jsonl` / `.ndjson` — JSON Lines, one record per line, readable and
+//!   writable without holding the whole file in memory
+//! * `.cbor` — compact binary encoding of the JSON document (`cbor` feature)
+//!
+//! A units JSON Lines file starts with a `header` record, followed by `symbol`
+//! and `unit` records. Each symbol is written once, before the first unit that
+//! references it, so a reader always has the definitions a unit needs by the
+//! time the unit arrives. Build orders use a `metadata` record followed by one
+//! `batch` record per batch.
+
+use crate::{
+    AtomicUnit, BuildMetadata, BuildOrder, BuildOrderBatch, GeneratorInfo, SchemaError, Symbol,
+    SymbolTable, UnitsFile, UNITS_SCHEMA_VERSION,
+};
+use crate::envelope::timestamp_now;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// File encoding, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Json,
+    JsonLines,
+    Cbor,
+}
+
+impl Encoding {
+    /// `.jsonl`/`.ndjson` and `.cbor` select those encodings; anything else is JSON
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match ext.as_deref() {
+            Some("jsonl") | Some("ndjson") => Encoding::JsonLines,
+            Some("cbor") => Encoding::Cbor,
+            _ => Encoding::Json,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::JsonLines => "jsonl",
+            Encoding::Cbor => "cbor",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Envelope fields of a [`UnitsFile`], without the symbols and units
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UnitsHeader {
+    pub schema_version: u32,
+    pub generator: GeneratorInfo,
+    pub source_root: String,
+    pub generated_at: u64,
+    pub config_digest: String,
+}
+
+impl UnitsHeader {
+    /// Header at the current schema version, stamped with the current time
+    /// (or `SOURCE_DATE_EPOCH` when set, for reproducible output).
+    pub fn new(generator: GeneratorInfo, source_root: impl Into<String>, config_digest: impl Into<String>) -> Self {
+        Self {
+            schema_version: UNITS_SCHEMA_VERSION,
+            generator,
+            source_root: source_root.into(),
+            generated_at: timestamp_now(),
+            config_digest: config_digest.into(),
+        }
+    }
+}
+
+/// One line of a units JSON Lines file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum UnitsRecord {
+    Header(UnitsHeader),
+    Symbol { id: String, symbol: Symbol },
+    Unit(AtomicUnit),
+}
+
+/// One line of a build order JSON Lines file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum BuildOrderRecord {
+    Metadata(BuildMetadata),
+    Batch(BuildOrderBatch),
+}
+
+/// Streaming writer for units JSON Lines files
+pub struct UnitsLinesWriter<W: Write> {
+    out: W,
+    written_symbols: HashSet<String>,
+    units: usize,
+}
+
+impl<W: Write> UnitsLinesWriter<W> {
+    /// Start a file by writing its header record
+    pub fn new(mut out: W, header: &UnitsHeader) -> io::Result<Self> {
+        write_line(&mut out, &UnitsRecord::Header(header.clone()))?;
+        Ok(Self {
+            out,
+            written_symbols: HashSet::new(),
+            units: 0,
+        })
+    }
+
+    /// Write `unit`, preceded by any symbols it references that have not been written yet
+    pub fn write_unit(&mut self, unit: &AtomicUnit, symbols: &SymbolTable) -> io::Result<()> {
+        for id in &unit.symbol_refs {
+            if self.written_symbols.contains(id) {
+                continue;
+            }
+            if let Some(symbol) = symbols.get(id) {
+                write_line(&mut self.out, &UnitsRecord::Symbol { id: id.clone(), symbol: symbol.clone() })?;
+                self.written_symbols.insert(id.clone());
+            }
+        }
+        write_line(&mut self.out, &UnitsRecord::Unit(unit.clone()))?;
+        self.units += 1;
+        Ok(())
+    }
+
+    /// Number of unit records written so far
+    pub fn units_written(&self) -> usize {
+        self.units
+    }
+
+    /// Flush and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Streaming reader for units JSON Lines files; iterates over the units.
+///
+/// Symbol records are collected into [`symbols`](Self::symbols) as they are
+/// read, so it holds every definition the units yielded so far refer to.
+pub struct UnitsLinesReader<R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+    header: UnitsHeader,
+    symbols: SymbolTable,
+}
+
+impl UnitsLinesReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, SchemaError> {
+        Self::new(BufReader::new(open_file(path)?))
+    }
+}
+
+impl<R: BufRead> UnitsLinesReader<R> {
+    /// Read the header record; units are read on demand
+    pub fn new(input: R) -> Result<Self, SchemaError> {
+        let mut lines = input.lines();
+        let mut line = 0;
+        match next_line_record(&mut lines, &mut line)? {
+            Some(UnitsRecord::Header(header)) => Self::with_header(lines, line, header),
+            Some(_) => Err(SchemaError::Invalid("line 1: the first record must be a header".to_string())),
+            None => Err(SchemaError::Invalid("empty units JSON Lines file".to_string())),
+        }
+    }
+
+    fn with_header(lines: io::Lines<R>, line: usize, header: UnitsHeader) -> Result<Self, SchemaError> {
+        // JSON Lines files were introduced at version 3; version 4 only added
+        // `kind` to units, which defaults to a function when absent
+        if header.schema_version > UNITS_SCHEMA_VERSION {
+            return Err(SchemaError::UnsupportedVersion {
+                found: header.schema_version,
+                supported: UNITS_SCHEMA_VERSION,
+            });
+        }
+        if header.schema_version < 3 {
+            return Err(SchemaError::Invalid(format!(
+                "JSON Lines units files start at version 3, found {}",
+                header.schema_version
+            )));
+        }
+        let header = UnitsHeader {
+            schema_version: UNITS_SCHEMA_VERSION,
+            ..header
+        };
+        Ok(Self {
+            lines,
+            line,
+            header,
+            symbols: SymbolTable::new(),
+        })
+    }
+
+    pub fn header(&self) -> &UnitsHeader {
+        &self.header
+    }
+
+    /// Symbols read so far
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Read the remaining units into a [`UnitsFile`]
+    pub fn into_units_file(mut self) -> Result<UnitsFile, SchemaError> {
+        let mut units = Vec::new();
+        for unit in &mut self {
+            units.push(unit?);
+        }
+        Ok(UnitsFile::from_parts(self.header, self.symbols, units))
+    }
+
+    fn error(&self, message: impl fmt::Display) -> SchemaError {
+        SchemaError::Invalid(format!("line {}: {}", self.line, message))
+    }
+}
+
+impl<R: BufRead> Iterator for UnitsLinesReader<R> {
+    type Item = Result<AtomicUnit, SchemaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match next_line_record(&mut self.lines, &mut self.line) {
+                Ok(Some(UnitsRecord::Unit(unit))) => return Some(Ok(unit)),
+                Ok(Some(UnitsRecord::Symbol { id, symbol })) => self.symbols.insert_with_id(id, symbol),
+                Ok(Some(UnitsRecord::Header(_))) => return Some(Err(self.error("unexpected second header"))),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl UnitsFile {
+    /// Envelope fields without the symbols and units
+    pub fn header(&self) -> UnitsHeader {
+        UnitsHeader {
+            schema_version: self.schema_version,
+            generator: self.generator.clone(),
+            source_root: self.source_root.clone(),
+            generated_at: self.generated_at,
+            config_digest: self.config_digest.clone(),
+        }
+    }
+
+    /// Read and migrate a units file in the encoding given by its extension
+    pub fn load(path: &Path) -> Result<Self, SchemaError> {
+        match Encoding::from_path(path) {
+            Encoding::Json => {
+                let value: Value = serde_json::from_reader(BufReader::new(open_file(path)?)).map_err(SchemaError::Json)?;
+                Self::from_value(value)
+            }
+            Encoding::JsonLines => UnitsLinesReader::open(path)?.into_units_file(),
+            Encoding::Cbor => Self::from_value(read_cbor(path)?),
+        }
+    }
+
+    /// Write this file in the encoding given by the extension of `path`
+    pub fn save(&self, path: &Path) -> Result<(), SchemaError> {
+        match Encoding::from_path(path) {
+            Encoding::Json => write_json(path, self),
+            Encoding::JsonLines => {
+                let io_error = |source| SchemaError::Io { path: path.to_path_buf(), source };
+                let mut writer = UnitsLinesWriter::new(create_file(path)?, &self.header()).map_err(io_error)?;
+                for unit in &self.units {
+                    writer.write_unit(unit, &self.symbols).map_err(io_error)?;
+                }
+                writer.finish().map_err(io_error)?;
+                Ok(())
+            }
+            Encoding::Cbor => write_cbor(path, self),
+        }
+    }
+}
+
+impl BuildOrder {
+    /// Read a build order in the encoding given by its extension
+    pub fn load(path: &Path) -> Result<Self, SchemaError> {
+        match Encoding::from_path(path) {
+            Encoding::Json => serde_json::from_reader(BufReader::new(open_file(path)?)).map_err(SchemaError::Json),
+            Encoding::JsonLines => {
+                let mut lines = BufReader::new(open_file(path)?).lines();
+                let mut line = 0;
+                let metadata = match next_line_record(&mut lines, &mut line)? {
+                    Some(BuildOrderRecord::Metadata(metadata)) => metadata,
+                    _ => return Err(SchemaError::Invalid("line 1: the first record must be metadata".to_string())),
+                };
+                let mut batches = Vec::new();
+                while let Some(record) = next_line_record(&mut lines, &mut line)? {
+                    match record {
+                        BuildOrderRecord::Batch(batch) => batches.push(batch),
+                        BuildOrderRecord::Metadata(_) => {
+                            return Err(SchemaError::Invalid(format!("line {}: unexpected second metadata record", line)))
+                        }
+                    }
+                }
+                Ok(BuildOrder { metadata, batches })
+            }
+            Encoding::Cbor => serde_json::from_value(read_cbor(path)?).map_err(SchemaError::Json),
+        }
+    }
+
+    /// Write this build order in the encoding given by the extension of `path`
+    pub fn save(&self, path: &Path) -> Result<(), SchemaError> {
+        match Encoding::from_path(path) {
+            Encoding::Json => write_json(path, self),
+            Encoding::JsonLines => {
+                let mut out = create_file(path)?;
+                let mut write = || -> io::Result<()> {
+                    write_line(&mut out, &BuildOrderRecord::Metadata(self.metadata.clone()))?;
+                    for batch in &self.batches {
+                        write_line(&mut out, &BuildOrderRecord::Batch(batch.clone()))?;
+                    }
+                    out.flush()
+                };
+                write().map_err(|source| SchemaError::Io { path: path.to_path_buf(), source })
+            }
+            Encoding::Cbor => write_cbor(path, self),
+        }
+    }
+}
+
+/// Read any pipeline document into a JSON value, whatever its encoding.
+///
+/// JSON Lines files are reassembled into the equivalent single document, so the
+/// result can be checked with [`validate_document`](crate::validate_document).
+pub fn read_document_value(path: &Path) -> Result<Value, SchemaError> {
+    match Encoding::from_path(path) {
+        Encoding::Json => serde_json::from_reader(BufReader::new(open_file(path)?)).map_err(SchemaError::Json),
+        Encoding::Cbor => read_cbor(path),
+        Encoding::JsonLines => {
+            let mut lines = BufReader::new(open_file(path)?).lines();
+            let mut line = 0;
+            let first: Value = next_line_record(&mut lines, &mut line)?
+                .ok_or_else(|| SchemaError::Invalid("empty JSON Lines file".to_string()))?;
+            match first.get("record").and_then(Value::as_str) {
+                Some("header") => {
+                    let header: UnitsHeader = serde_json::from_value(first).map_err(SchemaError::Json)?;
+                    let file = UnitsLinesReader::with_header(lines, line, header)?.into_units_file()?;
+                    serde_json::to_value(file).map_err(SchemaError::Json)
+                }
+                Some("metadata") => serde_json::to_value(BuildOrder::load(path)?).map_err(SchemaError::Json),
+                _ => Err(SchemaError::Invalid("line 1: expected a header or metadata record".to_string())),
+            }
+        }
+    }
+}
+
+fn next_line_record<T: DeserializeOwned>(
+    lines: &mut io::Lines<impl BufRead>,
+    line: &mut usize,
+) -> Result<Option<T>, SchemaError> {
+    for text in lines.by_ref() {
+        *line += 1;
+        let text = text.map_err(|e| SchemaError::Invalid(format!("line {}: {}", line, e)))?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        return serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| SchemaError::Invalid(format!("line {}: {}", line, e)));
+    }
+    Ok(None)
+}
+
+fn write_line(out: &mut impl Write, record: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<(), SchemaError> {
+    let mut out = create_file(path)?;
+    serde_json::to_writer_pretty(&mut out, value).map_err(SchemaError::Json)?;
+    out.flush().map_err(|source| SchemaError::Io { path: path.to_path_buf(), source })
+}
+
+fn open_file(path: &Path) -> Result<File, SchemaError> {
+    File::open(path).map_err(|source| SchemaError::Io { path: path.to_path_buf(), source })
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>, SchemaError> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|source| SchemaError::Io { path: path.to_path_buf(), source })
+}
+
+#[cfg(feature = "cbor")]
+fn read_cbor(path: &Path) -> Result<Value, SchemaError> {
+    ciborium::from_reader(BufReader::new(open_file(path)?))
+        .map_err(|e| SchemaError::Invalid(format!("malformed CBOR: {}", e)))
+}
+
+#[cfg(feature = "cbor")]
+fn write_cbor(path: &Path, value: &impl Serialize) -> Result<(), SchemaError> {
+    let mut out = create_file(path)?;
+    ciborium::into_writer(value, &mut out).map_err(|e| SchemaError::Invalid(format!("failed to encode CBOR: {}", e)))?;
+    out.flush().map_err(|source| SchemaError::Io { path: path.to_path_buf(), source })
+}
+
+#[cfg(not(feature = "cbor"))]
+fn read_cbor(path: &Path) -> Result<Value, SchemaError> {
+    Err(cbor_disabled(path))
+}
+
+#[cfg(not(feature = "cbor"))]
+fn write_cbor(path: &Path, _value: &impl Serialize) -> Result<(), SchemaError> {
+    Err(cbor_disabled(path))
+}
+
+#[cfg(not(feature = "cbor"))]
+fn cbor_disabled(path: &Path) -> SchemaError {
+    SchemaError::Invalid(format!("{:?}: CBOR support is disabled; rebuild kernel-schema with the `cbor` feature", path))
+}
+
@@ -0,0 +1,258 @@
+use crate::{AtomicUnit, SymbolTable, UnitsHeader};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the units.json layout written by this crate.
+///
+/// * 0 — bare JSON array of inline units
+/// * 1 — `{ "symbols", "units" }` table layout without metadata
+/// * 2 — self-describing envelope ([`UnitsFile`])
+/// * 3 — typed [`Dependency`](crate::Dependency) edges instead of plain strings
+/// * 4 — explicit [`UnitKind`](crate::UnitKind) on every unit
+pub const UNITS_SCHEMA_VERSION: u32 = 4;
+
+/// Tool that produced a file
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct GeneratorInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl GeneratorInfo {
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+
+    /// Placeholder for files migrated from versions that did not record a generator
+    pub fn unknown() -> Self {
+        Self::new("unknown", "unknown")
+    }
+}
+
+/// Versioned, self-describing units.json
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct UnitsFile {
+    pub schema_version: u32,
+    pub generator: GeneratorInfo,
+    /// Root the slicer was pointed at; symbol source paths are relative to it
+    pub source_root: String,
+    /// Seconds since the Unix epoch (0 if unknown)
+    pub generated_at: u64,
+    /// Digest of the options the generator ran with
+    pub config_digest: String,
+    #[serde(default, skip_serializing_if = "SymbolTable::is_empty")]
+    pub symbols: SymbolTable,
+    pub units: Vec<AtomicUnit>,
+}
+
+impl UnitsFile {
+    /// New file at the current schema version, stamped with the current time
+    /// (or `SOURCE_DATE_EPOCH` when set, for reproducible output).
+    pub fn new(
+        generator: GeneratorInfo,
+        source_root: impl Into<String>,
+        config_digest: impl Into<String>,
+        symbols: SymbolTable,
+        units: Vec<AtomicUnit>,
+    ) -> Self {
+        Self::from_parts(UnitsHeader::new(generator, source_root, config_digest), symbols, units)
+    }
+
+    /// Parse units.json of any supported version, migrating it to the current one
+    pub fn from_json_str(json: &str) -> Result<Self, SchemaError> {
+        let value: Value = serde_json::from_str(json).map_err(SchemaError::Json)?;
+        Self::from_value(value)
+    }
+
+    /// Migrate an already-parsed JSON document to the current version
+    pub fn from_value(value: Value) -> Result<Self, SchemaError> {
+        serde_json::from_value(migrate_to_current(value)?).map_err(SchemaError::Json)
+    }
+
+    /// Reassemble a file from its header and contents
+    pub fn from_parts(header: UnitsHeader, symbols: SymbolTable, units: Vec<AtomicUnit>) -> Self {
+        Self {
+            schema_version: header.schema_version,
+            generator: header.generator,
+            source_root: header.source_root,
+            generated_at: header.generated_at,
+            config_digest: header.config_digest,
+            symbols,
+            units,
+        }
+    }
+
+    /// Units with all referenced symbols copied into `required_headers`
+    pub fn inline_units(&self) -> Vec<AtomicUnit> {
+        self.units.iter().map(|unit| self.inline_unit(unit)).collect()
+    }
+
+    /// Inline a single unit against this file's symbol table
+    pub fn inline_unit(&self, unit: &AtomicUnit) -> AtomicUnit {
+        self.symbols.inline_unit(unit)
+    }
+}
+
+/// Schema version of a parsed units.json document
+pub fn detect_version(value: &Value) -> Result<u32, SchemaError> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(map) => match map.get("schema_version") {
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| SchemaError::Invalid(format!("schema_version must be an unsigned integer, found {}", version))),
+            None if map.contains_key("units") => Ok(1),
+            None => Err(SchemaError::Invalid(
+                "expected a units array or an object with a \"units\" field".to_string(),
+            )),
+        },
+        _ => Err(SchemaError::Invalid(
+            "expected a units array or an object with a \"units\" field".to_string(),
+        )),
+    }
+}
+
+/// Bring a units.json document of any supported version up to
+/// [`UNITS_SCHEMA_VERSION`] without deserializing it
+pub fn migrate_to_current(mut value: Value) -> Result<Value, SchemaError> {
+    let mut version = detect_version(&value)?;
+    if version > UNITS_SCHEMA_VERSION {
+        return Err(SchemaError::UnsupportedVersion {
+            found: version,
+            supported: UNITS_SCHEMA_VERSION,
+        });
+    }
+
+    // Apply each step in turn so every migration only knows its neighbour
+    while version < UNITS_SCHEMA_VERSION {
+        value = match version {
+            0 => migrate_v0_to_v1(value)?,
+            1 => migrate_v1_to_v2(value)?,
+            2 => migrate_v2_to_v3(value)?,
+            3 => migrate_v3_to_v4(value)?,
+            other => return Err(SchemaError::Invalid(format!("no migration from version {}", other))),
+        };
+        version += 1;
+    }
+    Ok(value)
+}
+
+/// Version 0 → 1: wrap the bare unit array in a table layout with no symbols
+pub fn migrate_v0_to_v1(value: Value) -> Result<Value, SchemaError> {
+    match value {
+        Value::Array(units) => Ok(json!({ "symbols": {}, "units": units })),
+        _ => Err(SchemaError::Invalid("version 0 units.json must be an array".to_string())),
+    }
+}
+
+/// Version 1 → 2: add the envelope metadata, marked as unknown
+pub fn migrate_v1_to_v2(value: Value) -> Result<Value, SchemaError> {
+    let mut map = into_object(value, 1)?;
+    map.insert("schema_version".to_string(), json!(2));
+    map.insert("generator".to_string(), serde_json::to_value(GeneratorInfo::unknown()).map_err(SchemaError::Json)?);
+    map.insert("source_root".to_string(), json!(""));
+    map.insert("generated_at".to_string(), json!(0));
+    map.insert("config_digest".to_string(), json!(""));
+    Ok(Value::Object(map))
+}
+
+/// Version 2 → 3: plain dependency strings become single direct calls
+pub fn migrate_v2_to_v3(value: Value) -> Result<Value, SchemaError> {
+    let mut map = into_object(value, 2)?;
+    if let Some(Value::Array(units)) = map.get_mut("units") {
+        for unit in units {
+            let Some(Value::Array(deps)) = unit.get_mut("dependencies") else { continue };
+            for dep in deps.iter_mut() {
+                if let Value::String(target) = dep {
+                    *dep = json!({ "target": target, "kind": "direct_call", "call_sites": 1 });
+                }
+            }
+        }
+    }
+    map.insert("schema_version".to_string(), json!(3));
+    Ok(Value::Object(map))
+}
+
+/// Version 3 → 4: every existing unit is a function
+pub fn migrate_v3_to_v4(value: Value) -> Result<Value, SchemaError> {
+    let mut map = into_object(value, 3)?;
+    if let Some(Value::Array(units)) = map.get_mut("units") {
+        for unit in units.iter_mut().filter_map(Value::as_object_mut) {
+            unit.entry("kind").or_insert_with(|| json!("function"));
+        }
+    }
+    map.insert("schema_version".to_string(), json!(4));
+    Ok(Value::Object(map))
+}
+
+fn into_object(value: Value, version: u32) -> Result<Map<String, Value>, SchemaError> {
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => Err(SchemaError::Invalid(format!("version {} units.json must be an object", version))),
+    }
+}
+
+/// Errors reading or interpreting schema files
+#[derive(Debug)]
+pub enum SchemaError {
+    Io { path: PathBuf, source: std::io::Error },
+    Json(serde_json::Error),
+    /// The file was written by a newer toolchain
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The document's shape is not any known version
+    Invalid(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Io { path, source } => write!(f, "I/O error on {:?}: {}", path, source),
+            SchemaError::Json(e) => write!(f, "Malformed JSON: {}", e),
+            SchemaError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Schema version {} is newer than the supported version {}; upgrade the toolchain or regenerate the file",
+                found, supported
+            ),
+            SchemaError::Invalid(msg) => write!(f, "Unrecognized document: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemaError::Io { source, .. } => Some(source),
+            SchemaError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Stable 64-bit FNV-1a digest, hex encoded. Used for config digests and content
+/// hashes, which must not change between toolchain builds.
+pub fn digest(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+pub(crate) fn timestamp_now() -> u64 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.parse().ok()) {
+        return epoch;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
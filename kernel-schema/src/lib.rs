@@ -1,20 +1,162 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+mod build_order;
+mod dependency;
+mod diff;
+mod encoding;
+mod envelope;
+mod schema;
+mod symbols;
+
+pub use build_order::{
+    BuildMetadata, BuildOrder, BuildOrderBatch, CycleAnalysis, DecompositionPlan, DecompositionStep, FasMethod,
+    FeedbackArc, FeedbackArcSet, PlanCost, Stub, ValidationError,
+};
+pub use dependency::{Dependency, DependencyKind, SourceLocation};
+pub use diff::{content_hash, diff_units, DependencyKey, ModifiedUnit, RenamedUnit, UnitsDiff};
+pub use encoding::{
+    read_document_value, BuildOrderRecord, Encoding, UnitsHeader, UnitsLinesReader, UnitsLinesWriter, UnitsRecord,
+};
+pub use envelope::{
+    detect_version, digest, migrate_to_current, migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4,
+    GeneratorInfo, SchemaError, UnitsFile, UNITS_SCHEMA_VERSION,
+};
+pub use schema::{validate_document, DocumentKind, SchemaViolation};
+pub use symbols::{Symbol, SymbolKind, SymbolTable};
+
+/// What an [`AtomicUnit`] holds, and so what it is ported to
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitKind {
+    /// A function definition
+    #[default]
+    Function,
+    /// A file-scope variable definition
+    GlobalData,
+    /// A struct, union, enum or typedef
+    TypeDefinition,
+    /// A `#define NAME(args) ...` macro
+    FunctionMacro,
+    /// A file-scope `asm(...)` block
+    InlineAsm,
+}
+
+impl UnitKind {
+    pub const ALL: [UnitKind; 5] = [
+        UnitKind::Function,
+        UnitKind::GlobalData,
+        UnitKind::TypeDefinition,
+        UnitKind::FunctionMacro,
+        UnitKind::InlineAsm,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnitKind::Function => "function",
+            UnitKind::GlobalData => "global_data",
+            UnitKind::TypeDefinition => "type_definition",
+            UnitKind::FunctionMacro => "function_macro",
+            UnitKind::InlineAsm => "inline_asm",
+        }
+    }
+
+    /// Prefix of unit IDs of this kind (`type:proc`); functions use their bare name.
+    /// Matches the [`SymbolKind`] prefixes, since C keeps tags and functions in
+    /// separate namespaces (`struct stat` and `stat()` can coexist).
+    pub fn id_prefix(self) -> Option<&'static str> {
+        match self {
+            UnitKind::Function => None,
+            UnitKind::GlobalData => Some("global"),
+            UnitKind::TypeDefinition => Some("type"),
+            UnitKind::FunctionMacro => Some("macro"),
+            UnitKind::InlineAsm => Some("asm"),
+        }
+    }
+
+    /// Unit ID for `name` (`proc` → `type:proc`)
+    pub fn unit_id(self, name: &str) -> String {
+        match self.id_prefix() {
+            Some(prefix) => format!("{}:{}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for UnitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for UnitKind {
+    type Err = String;
+
+    /// Accepts the serialized names, with `-` or `_` as separator
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().replace('-', "_");
+        UnitKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == normalized)
+            .ok_or_else(|| {
+                let known: Vec<&str> = UnitKind::ALL.iter().map(|k| k.as_str()).collect();
+                format!("unknown unit kind '{}' (expected one of: {})", s, known.join(", "))
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Hash)]
 pub struct AtomicUnit {
     pub id: String,
+    /// Absent in version 3 JSON Lines files, where every unit was a function
+    #[serde(default)]
+    pub kind: UnitKind,
     pub code: String,
-    pub dependencies: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+    /// File the unit was sliced from, relative to the source root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    /// Inline context definitions (inline layout only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub required_headers: Vec<String>,
+    /// IDs of [`SymbolTable`] entries this unit needs (table layout only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbol_refs: Vec<String>,
 }
 
 impl AtomicUnit {
-    pub fn new(id: String, code: String, dependencies: Vec<String>, required_headers: Vec<String>) -> Self {
+    pub fn new(id: String, code: String, dependencies: Vec<Dependency>, required_headers: Vec<String>) -> Self {
         Self {
             id,
+            kind: UnitKind::Function,
             code,
             dependencies,
+            source_file: None,
             required_headers,
+            symbol_refs: Vec::new(),
+        }
+    }
+
+    pub fn with_kind(mut self, kind: UnitKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_source_file(mut self, source_file: impl Into<String>) -> Self {
+        self.source_file = Some(source_file.into());
+        self
+    }
+
+    /// Targets of all dependencies, in order, without duplicates
+    pub fn dependency_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = Vec::new();
+        for dep in &self.dependencies {
+            if !ids.contains(&dep.target.as_str()) {
+                ids.push(&dep.target);
+            }
         }
+        ids
     }
 }
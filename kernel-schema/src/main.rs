@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use kernel_schema::{diff_units, read_document_value, validate_document, BuildOrder, DocumentKind, UnitsFile};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Inspect and validate Triad pipeline files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print or write JSON Schema documents for the pipeline files
+    Schema {
+        /// Only this document (units, build_order, cycle_analysis); default: all
+        #[arg(short, long)]
+        kind: Option<DocumentKind>,
+
+        /// Write `<kind>.schema.json` files here instead of printing
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+    },
+    /// Check a file against its schema
+    Validate {
+        /// File to check (.json, .jsonl or .cbor)
+        file: PathBuf,
+
+        /// Document kind; guessed from the file name when omitted
+        #[arg(short, long)]
+        kind: Option<DocumentKind>,
+
+        /// For build orders: also check batches against this units.json
+        #[arg(short, long)]
+        units: Option<PathBuf>,
+    },
+    /// Compare two units files: added, removed, renamed and modified units
+    Diff {
+        /// Units file before the upstream change
+        old: PathBuf,
+
+        /// Units file after the upstream change
+        new: PathBuf,
+
+        /// Print the diff as JSON instead of a human-readable report
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Schema { kind, out_dir } => run_schema(kind, out_dir),
+        Command::Validate { file, kind, units } => run_validate(file, kind, units),
+        Command::Diff { old, new, json } => run_diff(old, new, json),
+    }
+}
+
+fn run_schema(kind: Option<DocumentKind>, out_dir: Option<PathBuf>) -> Result<()> {
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None => DocumentKind::ALL.to_vec(),
+    };
+
+    for kind in kinds {
+        let json = serde_json::to_string_pretty(&kind.json_schema())?;
+        match &out_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory {:?}", dir))?;
+                let path = dir.join(format!("{}.schema.json", kind.file_stem()));
+                fs::write(&path, json)
+                    .with_context(|| format!("Failed to write schema to {:?}", path))?;
+                println!("Schema: Wrote {} schema to {:?}", kind, path);
+            }
+            None => println!("{}", json),
+        }
+    }
+
+    Ok(())
+}
+
+fn run_validate(file: PathBuf, kind: Option<DocumentKind>, units: Option<PathBuf>) -> Result<()> {
+    let kind = match kind.or_else(|| DocumentKind::from_path(&file)) {
+        Some(kind) => kind,
+        None => anyhow::bail!("Cannot tell what kind of document {:?} is; pass --kind", file),
+    };
+
+    // JSON Lines and CBOR files are checked as the equivalent JSON document
+    let value = read_document_value(&file)
+        .with_context(|| format!("Failed to read {:?}", file))?;
+
+    let violations = validate_document(kind, &value)
+        .with_context(|| format!("Failed to validate {:?}", file))?;
+    for violation in &violations {
+        eprintln!("{}: {}", file.display(), violation);
+    }
+    if !violations.is_empty() {
+        anyhow::bail!("{:?} does not match the {} schema ({} violation(s))", file, kind, violations.len());
+    }
+
+    if let Some(units_path) = units {
+        if kind != DocumentKind::BuildOrder {
+            anyhow::bail!("--units only applies to build orders");
+        }
+        let units_file = UnitsFile::load(&units_path)
+            .with_context(|| format!("Failed to load units file {:?}", units_path))?;
+        let build_order: BuildOrder = serde_json::from_value(value)
+            .with_context(|| format!("Failed to parse {:?}", file))?;
+        let errors = build_order.validate(&units_file.units);
+        for error in &errors {
+            eprintln!("{}: {}", file.display(), error);
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("{:?} is inconsistent with {:?} ({} error(s))", file, units_path, errors.len());
+        }
+    }
+
+    println!("Schema: {:?} is a valid {} document", file, kind);
+    Ok(())
+}
+
+fn run_diff(old: PathBuf, new: PathBuf, json: bool) -> Result<()> {
+    let old_file = UnitsFile::load(&old)
+        .with_context(|| format!("Failed to load units file {:?}", old))?;
+    let new_file = UnitsFile::load(&new)
+        .with_context(|| format!("Failed to load units file {:?}", new))?;
+
+    let diff = diff_units(&old_file, &new_file);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff);
+        let stale = diff.stale_units();
+        if !stale.is_empty() {
+            println!("Stale ports: {}", stale.join(", "));
+        }
+    }
+    Ok(())
+}
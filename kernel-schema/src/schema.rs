@@ -0,0 +1,115 @@
+use crate::{migrate_to_current, BuildOrder, CycleAnalysis, SchemaError, UnitsFile};
+use schemars::schema_for;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The pipeline files this crate defines a contract for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentKind {
+    /// units.json, written by the slicer
+    Units,
+    /// build_order.json, written by the mapper
+    BuildOrder,
+    /// cycle_analysis.json, written by the mapper
+    CycleAnalysis,
+}
+
+impl DocumentKind {
+    pub const ALL: [DocumentKind; 3] = [DocumentKind::Units, DocumentKind::BuildOrder, DocumentKind::CycleAnalysis];
+
+    /// Conventional file stem (`units`, `build_order`, `cycle_analysis`)
+    pub fn file_stem(self) -> &'static str {
+        match self {
+            DocumentKind::Units => "units",
+            DocumentKind::BuildOrder => "build_order",
+            DocumentKind::CycleAnalysis => "cycle_analysis",
+        }
+    }
+
+    /// Guess the kind from a file name such as `build_order.json` or `xv6_units.json`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        // Check the longer stems first; "units" is a substring of nothing else
+        [DocumentKind::BuildOrder, DocumentKind::CycleAnalysis, DocumentKind::Units]
+            .into_iter()
+            .find(|kind| name.contains(kind.file_stem()))
+    }
+
+    /// JSON Schema (draft 7) for this document
+    pub fn json_schema(self) -> Value {
+        let schema = match self {
+            DocumentKind::Units => schema_for!(UnitsFile),
+            DocumentKind::BuildOrder => schema_for!(BuildOrder),
+            DocumentKind::CycleAnalysis => schema_for!(Vec<CycleAnalysis>),
+        };
+        // Serializing a schemars schema cannot fail; fall back to an empty schema regardless
+        serde_json::to_value(schema).unwrap_or(Value::Bool(true))
+    }
+}
+
+impl fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file_stem())
+    }
+}
+
+impl FromStr for DocumentKind {
+    type Err = String;
+
+    /// Accepts the file stems, with `-` or `_` as separator
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().replace('-', "_");
+        DocumentKind::ALL
+            .into_iter()
+            .find(|kind| kind.file_stem() == normalized)
+            .ok_or_else(|| format!("unknown document kind '{}' (expected units, build_order or cycle_analysis)", s))
+    }
+}
+
+/// A place where a document does not match its schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value (`/units/3/dependencies/0/kind`)
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() { "/" } else { &self.path };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Check `value` against the schema for `kind`.
+///
+/// Older units.json versions are migrated first, so violations are reported
+/// against the current layout. Unsupported versions are an error rather than a
+/// list of violations.
+pub fn validate_document(kind: DocumentKind, value: &Value) -> Result<Vec<SchemaViolation>, SchemaError> {
+    let migrated;
+    let instance = match kind {
+        DocumentKind::Units => {
+            migrated = migrate_to_current(value.clone())?;
+            &migrated
+        }
+        DocumentKind::BuildOrder | DocumentKind::CycleAnalysis => value,
+    };
+
+    let schema = kind.json_schema();
+    let compiled = jsonschema::JSONSchema::compile(&schema)
+        .map_err(|e| SchemaError::Invalid(format!("Failed to compile {} schema: {}", kind, e)))?;
+
+    let violations = match compiled.validate(instance) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| SchemaViolation {
+                path: e.instance_path.to_string(),
+                message: e.to_string(),
+            })
+            .collect(),
+    };
+    Ok(violations)
+}
@@ -0,0 +1,110 @@
+use crate::AtomicUnit;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What a [`Symbol`] defines
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    /// struct, union, enum or typedef
+    Type,
+    /// `#define`, object-like or function-like
+    Macro,
+    /// File-scope variable declaration
+    Global,
+    /// Function declaration without a body
+    Prototype,
+}
+
+impl SymbolKind {
+    /// Prefix used when building symbol IDs (`type:proc`, `macro:NPROC`, ...)
+    pub fn prefix(self) -> &'static str {
+        match self {
+            SymbolKind::Type => "type",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Global => "global",
+            SymbolKind::Prototype => "proto",
+        }
+    }
+}
+
+/// A shared piece of context that units reference by ID
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    pub definition: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+}
+
+/// Deduplicated definitions shared by all units, keyed by symbol ID
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct SymbolTable {
+    entries: BTreeMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Canonical ID for a symbol of `kind` named `name`
+    pub fn symbol_id(kind: SymbolKind, name: &str) -> String {
+        format!("{}:{}", kind.prefix(), name)
+    }
+
+    /// Insert `symbol` under its canonical ID, returning the ID.
+    /// An existing entry with the same ID is kept.
+    pub fn insert(&mut self, symbol: Symbol) -> String {
+        let id = Self::symbol_id(symbol.kind, &symbol.name);
+        self.entries.entry(id.clone()).or_insert(symbol);
+        id
+    }
+
+    /// Insert `symbol` under an ID read from a file, keeping an existing entry
+    pub fn insert_with_id(&mut self, id: String, symbol: Symbol) {
+        self.entries.entry(id).or_insert(symbol);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Symbol> {
+        self.entries.get(id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in ID order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.entries.iter()
+    }
+
+    /// Resolve a unit's references, skipping IDs not present in the table
+    pub fn resolve<'a>(&'a self, refs: &'a [String]) -> impl Iterator<Item = &'a Symbol> + 'a {
+        refs.iter().filter_map(move |id| self.entries.get(id))
+    }
+
+    /// Copy the definitions `unit` references into its `required_headers` and
+    /// drop the references (the inline layout)
+    pub fn inline_unit(&self, unit: &AtomicUnit) -> AtomicUnit {
+        let mut inlined = unit.clone();
+        for symbol in self.resolve(&unit.symbol_refs) {
+            if !inlined.required_headers.contains(&symbol.definition) {
+                inlined.required_headers.push(symbol.definition.clone());
+            }
+        }
+        inlined.symbol_refs.clear();
+        inlined
+    }
+}
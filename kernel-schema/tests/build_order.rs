@@ -0,0 +1,221 @@
+use kernel_schema::{
+    AtomicUnit, BuildMetadata, BuildOrder, BuildOrderBatch, CycleAnalysis, DecompositionPlan, DecompositionStep,
+    Dependency, DependencyKind, Stub, UnitKind, ValidationError,
+};
+
+fn unit(id: &str, deps: &[&str]) -> AtomicUnit {
+    AtomicUnit::new(
+        id.to_string(),
+        format!("void {}(void) {{}}", id),
+        deps.iter().map(|d| Dependency::call(*d)).collect(),
+        Vec::new(),
+    )
+}
+
+fn batch(units: &[&str], is_super_node: bool) -> BuildOrderBatch {
+    BuildOrderBatch {
+        units: units.iter().map(|u| u.to_string()).collect(),
+        is_super_node,
+        scc_size: is_super_node.then_some(units.len()),
+        refactoring_difficulty: is_super_node.then(|| "Low".to_string()),
+        level: None,
+        estimated_tokens: None,
+        decomposition: None,
+    }
+}
+
+fn order(batches: Vec<BuildOrderBatch>) -> BuildOrder {
+    let total_units = batches.iter().map(|b| b.units.len()).sum();
+    BuildOrder {
+        metadata: BuildMetadata {
+            total_units,
+            total_batches: batches.len(),
+            super_nodes: batches.iter().filter(|b| b.is_super_node).count(),
+            largest_super_node: 0,
+            average_batch_size: total_units as f64 / batches.len() as f64,
+            edge_kinds: Vec::new(),
+            roots: Vec::new(),
+            changed: Vec::new(),
+            removed_edges: Vec::new(),
+            estimated_cost: None,
+        },
+        batches,
+    }
+}
+
+#[test]
+fn build_order_round_trips_through_json() {
+    let original = order(vec![batch(&["memmove"], false), batch(&["sched", "yield"], true)]);
+    let json = serde_json::to_string_pretty(&original).unwrap();
+    let parsed: BuildOrder = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, original);
+}
+
+#[test]
+fn optional_batch_fields_are_omitted_and_defaulted() {
+    let json = serde_json::to_value(batch(&["memmove"], false)).unwrap();
+    assert!(json.get("scc_size").is_none());
+    assert!(json.get("refactoring_difficulty").is_none());
+
+    // Files written by the old conductor-side struct carried explicit nulls
+    let parsed: BuildOrderBatch =
+        serde_json::from_str(r#"{"units":["a"],"is_super_node":false,"scc_size":null,"refactoring_difficulty":null}"#)
+            .unwrap();
+    assert_eq!(parsed, batch(&["a"], false));
+}
+
+#[test]
+fn cycle_analysis_round_trips_through_json() {
+    let original = vec![CycleAnalysis {
+        super_node: vec!["sched".to_string(), "yield".to_string()],
+        size: 2,
+        weak_edges: vec![("yield".to_string(), "sched".to_string())],
+        refactoring_suggestions: vec!["Refactor all functions together atomically.".to_string()],
+        feedback_arc_set: None,
+    }];
+    let json = serde_json::to_string(&original).unwrap();
+    let parsed: Vec<CycleAnalysis> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, original);
+}
+
+#[test]
+fn valid_order_has_no_errors() {
+    let units = vec![unit("memmove", &[]), unit("sched", &["yield"]), unit("yield", &["sched", "memmove"])];
+    let order = order(vec![batch(&["memmove"], false), batch(&["sched", "yield"], true)]);
+    assert!(order.validate(&units).is_empty());
+}
+
+#[test]
+fn unknown_units_are_reported() {
+    let units = vec![unit("memmove", &[])];
+    let errors = order(vec![batch(&["memmove", "ghost"], false)]).validate(&units);
+    assert_eq!(errors, vec![ValidationError::UnknownUnit { unit: "ghost".to_string(), batch: 0 }]);
+}
+
+#[test]
+fn duplicate_units_are_reported() {
+    let units = vec![unit("memmove", &[])];
+    let errors = order(vec![batch(&["memmove"], false), batch(&["memmove"], false)]).validate(&units);
+    assert_eq!(
+        errors,
+        vec![ValidationError::DuplicateUnit { unit: "memmove".to_string(), first_batch: 0, batch: 1 }]
+    );
+}
+
+#[test]
+fn dependencies_must_come_first() {
+    let units = vec![unit("memmove", &[]), unit("fork", &["memmove"])];
+    let errors = order(vec![batch(&["fork"], false), batch(&["memmove"], false)]).validate(&units);
+    assert_eq!(
+        errors,
+        vec![ValidationError::OrderViolation {
+            unit: "fork".to_string(),
+            dependency: "memmove".to_string(),
+            unit_batch: 0,
+            dependency_batch: 1,
+        }]
+    );
+}
+
+#[test]
+fn same_batch_dependencies_require_a_super_node() {
+    let units = vec![unit("memmove", &[]), unit("fork", &["memmove"])];
+    let errors = order(vec![batch(&["memmove", "fork"], false)]).validate(&units);
+    assert_eq!(errors.len(), 1);
+
+    let errors = order(vec![batch(&["memmove", "fork"], true)]).validate(&units);
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn only_recorded_edge_kinds_are_checked() {
+    let mut fork = unit("fork", &[]);
+    fork.dependencies.push(Dependency::new("memmove", DependencyKind::TypeUse));
+    let units = vec![unit("memmove", &[]), fork];
+
+    let mut filtered = order(vec![batch(&["fork"], false), batch(&["memmove"], false)]);
+    filtered.metadata.edge_kinds = vec![DependencyKind::DirectCall];
+    assert!(filtered.validate(&units).is_empty());
+
+    filtered.metadata.edge_kinds.clear();
+    assert_eq!(filtered.validate(&units).len(), 1);
+}
+
+#[test]
+fn removed_edges_are_not_enforced() {
+    let units = vec![unit("sched", &["yield"]), unit("yield", &["sched"])];
+    let mut broken = order(vec![batch(&["sched"], false), batch(&["yield"], false)]);
+    assert_eq!(broken.validate(&units).len(), 1);
+
+    broken.metadata.removed_edges = vec![("sched".to_string(), "yield".to_string())];
+    assert!(broken.validate(&units).is_empty());
+}
+
+#[test]
+fn type_uses_resolve_to_type_units() {
+    let proc_type = AtomicUnit::new("type:proc".to_string(), "struct proc { int pid; };".to_string(), Vec::new(), Vec::new())
+        .with_kind(UnitKind::TypeDefinition);
+    let mut fork = unit("fork", &[]);
+    fork.dependencies.push(Dependency::new("proc", DependencyKind::TypeUse));
+    let units = vec![proc_type, fork];
+
+    assert!(order(vec![batch(&["type:proc"], false), batch(&["fork"], false)]).validate(&units).is_empty());
+
+    let errors = order(vec![batch(&["fork"], false), batch(&["type:proc"], false)]).validate(&units);
+    assert_eq!(
+        errors,
+        vec![ValidationError::OrderViolation {
+            unit: "fork".to_string(),
+            dependency: "type:proc".to_string(),
+            unit_batch: 0,
+            dependency_batch: 1,
+        }]
+    );
+}
+
+fn sched_yield_plan(yield_stubs: &[&str]) -> DecompositionPlan {
+    DecompositionPlan {
+        stubs: vec![Stub {
+            unit: "sched".to_string(),
+            declaration: "void sched(void);".to_string(),
+            used_by: vec!["yield".to_string()],
+        }],
+        steps: vec![
+            DecompositionStep::Port {
+                unit: "yield".to_string(),
+                stubs: yield_stubs.iter().map(|s| s.to_string()).collect(),
+            },
+            DecompositionStep::Port { unit: "sched".to_string(), stubs: Vec::new() },
+            DecompositionStep::ReplaceStub { unit: "sched".to_string(), reverify: vec!["yield".to_string()] },
+        ],
+    }
+}
+
+#[test]
+fn decomposition_plans_must_stub_later_members() {
+    let units = vec![unit("sched", &["yield"]), unit("yield", &["sched"])];
+    let mut super_node = batch(&["yield", "sched"], true);
+    super_node.decomposition = Some(sched_yield_plan(&["sched"]));
+    assert!(order(vec![super_node.clone()]).validate(&units).is_empty());
+
+    let json = serde_json::to_value(&super_node).unwrap();
+    assert_eq!(json["decomposition"]["steps"][2]["action"], "replace_stub");
+
+    super_node.decomposition = Some(sched_yield_plan(&[]));
+    assert_eq!(
+        order(vec![super_node]).validate(&units),
+        vec![ValidationError::UnstubbedDependency {
+            unit: "yield".to_string(),
+            dependency: "sched".to_string(),
+            batch: 0,
+        }]
+    );
+}
+
+#[test]
+fn decomposition_plans_must_port_the_whole_batch() {
+    let units = vec![unit("sched", &["yield"]), unit("yield", &["sched"]), unit("memmove", &[])];
+    let mut super_node = batch(&["yield", "sched", "memmove"], true);
+    super_node.decomposition = Some(sched_yield_plan(&["sched"]));
+    assert_eq!(order(vec![super_node]).validate(&units), vec![ValidationError::PlanMismatch { batch: 0 }]);
+}
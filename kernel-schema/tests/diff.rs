@@ -0,0 +1,114 @@
+use kernel_schema::{
+    diff_units, AtomicUnit, Dependency, DependencyKind, GeneratorInfo, Symbol, SymbolKind, SymbolTable, UnitsFile,
+};
+
+fn unit(id: &str, code: &str, calls: &[&str]) -> AtomicUnit {
+    AtomicUnit::new(
+        id.to_string(),
+        code.to_string(),
+        calls.iter().map(|c| Dependency::call(*c)).collect(),
+        Vec::new(),
+    )
+}
+
+fn file(symbols: SymbolTable, units: Vec<AtomicUnit>) -> UnitsFile {
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", symbols, units)
+}
+
+#[test]
+fn identical_files_have_no_differences() {
+    let units = vec![unit("main", "int main(void) { fork(); }", &["fork"])];
+    let diff = diff_units(&file(SymbolTable::new(), units.clone()), &file(SymbolTable::new(), units));
+    assert!(diff.is_empty());
+    assert!(diff.stale_units().is_empty());
+}
+
+#[test]
+fn added_removed_and_renamed_units_are_reported() {
+    let old = file(
+        SymbolTable::new(),
+        vec![
+            unit("release", "static void release(int *lk) { *lk = 0; }", &[]),
+            unit("gone", "void gone(void) {}", &[]),
+        ],
+    );
+    let new = file(
+        SymbolTable::new(),
+        vec![
+            unit("unlock", "static void unlock(int *lk)  {  *lk = 0; }", &[]),
+            unit("fresh", "void fresh(void) { return; }", &[]),
+        ],
+    );
+
+    let diff = diff_units(&old, &new);
+    assert_eq!(diff.added, vec!["fresh"]);
+    assert_eq!(diff.removed, vec!["gone"]);
+    assert_eq!(diff.renamed.len(), 1);
+    assert_eq!((diff.renamed[0].from.as_str(), diff.renamed[0].to.as_str()), ("release", "unlock"));
+    assert!(diff.modified.is_empty());
+    assert_eq!(diff.stale_units(), vec!["unlock"]);
+}
+
+#[test]
+fn dependency_and_header_changes_mark_units_modified() {
+    let symbol = |definition: &str| {
+        let mut table = SymbolTable::new();
+        table.insert(Symbol {
+            kind: SymbolKind::Type,
+            name: "proc".to_string(),
+            definition: definition.to_string(),
+            source_file: None,
+        });
+        table
+    };
+    let with_ref = |mut unit: AtomicUnit| {
+        unit.dependencies.push(Dependency::new("proc", DependencyKind::TypeUse));
+        unit.symbol_refs = vec!["type:proc".to_string()];
+        unit
+    };
+
+    let old = file(
+        symbol("struct proc { int pid; };"),
+        vec![with_ref(unit("yield", "void yield(void) { sched(); }", &["sched"]))],
+    );
+    let new = file(
+        symbol("struct proc { int pid; int killed; };"),
+        vec![with_ref(unit("yield", "void yield(void) { sched(); }", &["sched", "wakeup"]))],
+    );
+
+    let diff = diff_units(&old, &new);
+    assert_eq!(diff.modified.len(), 1);
+    let modified = &diff.modified[0];
+    assert_eq!(modified.id, "yield");
+    assert!(!modified.code_changed);
+    assert_eq!(modified.dependencies_added.len(), 1);
+    assert_eq!(modified.dependencies_added[0].target, "wakeup");
+    assert_eq!(modified.headers_added, vec!["struct proc { int pid; int killed; };"]);
+    assert_eq!(modified.headers_removed, vec!["struct proc { int pid; };"]);
+}
+
+#[test]
+fn dependents_of_removed_units_are_reported() {
+    let old = file(
+        SymbolTable::new(),
+        vec![
+            unit("helper", "void helper(void) { return; }", &[]),
+            unit("caller", "void caller(void) { helper(); }", &["helper"]),
+            unit("moved", "void moved(void) { helper(); }", &["helper"]),
+            unit("bystander", "void bystander(void) {}", &[]),
+        ],
+    );
+    let new = file(
+        SymbolTable::new(),
+        vec![
+            unit("caller", "void caller(void) { helper(); }", &["helper"]),
+            unit("renamed", "void renamed(void) { helper(); }", &["helper"]),
+            unit("bystander", "void bystander(void) {}", &[]),
+        ],
+    );
+    let diff = diff_units(&old, &new);
+    assert_eq!(diff.removed, vec!["helper"]);
+    // `caller` is textually unchanged, so only the removal makes it stale
+    assert!(diff.stale_units().iter().all(|id| *id != "caller"));
+    assert_eq!(diff.dependents_of_removed(&old, &new), vec!["caller", "renamed"]);
+}
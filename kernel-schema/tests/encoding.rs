@@ -0,0 +1,175 @@
+use kernel_schema::{
+    read_document_value, AtomicUnit, BuildMetadata, BuildOrder, BuildOrderBatch, Dependency, DependencyKind,
+    GeneratorInfo, SchemaError, Symbol, SymbolKind, SymbolTable, UnitKind, UnitsFile, UnitsLinesReader,
+};
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("kernel-schema-encoding-{}-{}", std::process::id(), name))
+}
+
+fn symbol(kind: SymbolKind, name: &str, definition: &str) -> Symbol {
+    Symbol { kind, name: name.to_string(), definition: definition.to_string(), source_file: None }
+}
+
+/// Two units sharing `struct proc`, so the symbol must only be written once
+fn units_file() -> UnitsFile {
+    let mut symbols = SymbolTable::new();
+    let proc = symbols.insert(symbol(SymbolKind::Type, "proc", "struct proc { int pid; };"));
+    let nproc = symbols.insert(symbol(SymbolKind::Macro, "NPROC", "#define NPROC 64"));
+    let mut fork = AtomicUnit::new(
+        "fork".into(),
+        "int fork(void) { struct proc *p = allocproc(); return p->pid; }".into(),
+        vec![Dependency::call("allocproc"), Dependency::new("proc", DependencyKind::TypeUse)],
+        Vec::new(),
+    )
+    .with_source_file("kernel/proc.c");
+    fork.symbol_refs = vec![proc.clone()];
+    let mut allocproc = AtomicUnit::new(
+        "allocproc".into(),
+        "struct proc *allocproc(void) { return &procs[NPROC - 1]; }".into(),
+        vec![Dependency::new("procs", DependencyKind::GlobalRead), Dependency::new("NPROC", DependencyKind::MacroUse)],
+        Vec::new(),
+    );
+    allocproc.symbol_refs = vec![proc, nproc];
+    let procs = AtomicUnit::new("global:procs".into(), "struct proc procs[NPROC];".into(), Vec::new(), Vec::new())
+        .with_kind(UnitKind::GlobalData);
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "xv6", "digest", symbols, vec![fork, allocproc, procs])
+}
+
+fn build_order() -> BuildOrder {
+    let batch = |units: &[&str], level| BuildOrderBatch {
+        units: units.iter().map(|u| u.to_string()).collect(),
+        is_super_node: false,
+        scc_size: None,
+        refactoring_difficulty: None,
+        level: Some(level),
+        estimated_tokens: Some(1200),
+        decomposition: None,
+    };
+    BuildOrder {
+        metadata: BuildMetadata {
+            total_units: 3,
+            total_batches: 2,
+            super_nodes: 0,
+            largest_super_node: 0,
+            average_batch_size: 1.5,
+            edge_kinds: vec![DependencyKind::DirectCall],
+            roots: vec!["fork".to_string()],
+            changed: Vec::new(),
+            removed_edges: Vec::new(),
+            estimated_cost: None,
+        },
+        batches: vec![batch(&["global:procs", "allocproc"], 0), batch(&["fork"], 1)],
+    }
+}
+
+#[test]
+fn units_round_trip_through_json_lines() {
+    let original = units_file();
+    let path = temp_path("units.jsonl");
+    original.save(&path).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    let loaded = UnitsFile::load(&path).unwrap();
+    let value = read_document_value(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, original);
+    assert_eq!(value, serde_json::to_value(&original).unwrap());
+    // Each symbol once, just before the first unit that needs it
+    let records: Vec<String> = text
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            let id = record.get("id").and_then(|id| id.as_str()).unwrap_or("");
+            format!("{} {}", record["record"].as_str().unwrap(), id).trim_end().to_string()
+        })
+        .collect();
+    assert_eq!(
+        records,
+        vec!["header", "symbol type:proc", "unit fork", "symbol macro:NPROC", "unit allocproc", "unit global:procs"]
+    );
+}
+
+#[test]
+fn build_order_round_trips_through_json_lines() {
+    let original = build_order();
+    let path = temp_path("order.jsonl");
+    original.save(&path).unwrap();
+    let lines = fs::read_to_string(&path).unwrap().lines().count();
+    let loaded = BuildOrder::load(&path).unwrap();
+    let value = read_document_value(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(lines, 3, "one metadata and two batch records");
+    assert_eq!(loaded, original);
+    assert_eq!(value, serde_json::to_value(&original).unwrap());
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn units_and_build_orders_round_trip_through_cbor() {
+    let path = temp_path("units.cbor");
+    units_file().save(&path).unwrap();
+    let json_size = serde_json::to_vec_pretty(&units_file()).unwrap().len();
+    assert!((fs::metadata(&path).unwrap().len() as usize) < json_size);
+    assert_eq!(UnitsFile::load(&path).unwrap(), units_file());
+    fs::remove_file(&path).unwrap();
+
+    let path = temp_path("order.cbor");
+    build_order().save(&path).unwrap();
+    assert_eq!(BuildOrder::load(&path).unwrap(), build_order());
+    assert_eq!(read_document_value(&path).unwrap(), serde_json::to_value(build_order()).unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn truncated_json_lines_report_the_line() {
+    let path = temp_path("truncated.jsonl");
+    units_file().save(&path).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::write(&path, &text[..text.len() - 20]).unwrap();
+    let error = UnitsFile::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    match error {
+        SchemaError::Invalid(message) => assert!(message.starts_with("line 6: "), "{}", message),
+        other => panic!("expected an invalid document error, got {:?}", other),
+    }
+}
+
+#[test]
+fn json_lines_must_start_with_a_header() {
+    let text = "{\"record\":\"unit\",\"id\":\"fork\",\"code\":\"\",\"dependencies\":[]}\n";
+    let error = UnitsLinesReader::new(text.as_bytes()).err().unwrap();
+    assert_eq!(error.to_string(), "Unrecognized document: line 1: the first record must be a header");
+
+    let error = UnitsLinesReader::new("\n\n".as_bytes()).err().unwrap();
+    assert_eq!(error.to_string(), "Unrecognized document: empty units JSON Lines file");
+
+    let header = r#"{"record":"header","schema_version":4,"generator":{"name":"t","version":"0"},"source_root":"","generated_at":0,"config_digest":""}"#;
+    let text = format!("{}\n{}\n", header, header);
+    let mut reader = UnitsLinesReader::new(text.as_bytes()).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap_err().to_string(), "Unrecognized document: line 2: unexpected second header");
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn corrupt_cbor_is_an_error() {
+    let path = temp_path("corrupt.cbor");
+    units_file().save(&path).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    let truncated = UnitsFile::load(&path).unwrap_err();
+    fs::write(&path, b"\xff\xff\xff not cbor").unwrap();
+    let garbage = BuildOrder::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    for error in [truncated, garbage] {
+        match error {
+            SchemaError::Invalid(message) => assert!(message.starts_with("malformed CBOR"), "{}", message),
+            other => panic!("expected an invalid document error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use kernel_schema::{
+    Dependency, DependencyKind, GeneratorInfo, SchemaError, SymbolKind, UnitKind, UnitsFile, UNITS_SCHEMA_VERSION,
+};
+use std::path::PathBuf;
+
+/// One units.json per schema version, under tests/fixtures
+fn load(version: u32) -> Result<UnitsFile, SchemaError> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/units_v{}.json", version));
+    UnitsFile::load(&path)
+}
+
+fn ids(file: &UnitsFile) -> Vec<&str> {
+    file.units.iter().map(|unit| unit.id.as_str()).collect()
+}
+
+#[test]
+fn version_0_unit_array_is_wrapped_in_an_envelope() {
+    let file = load(0).unwrap();
+    assert_eq!(file.schema_version, UNITS_SCHEMA_VERSION);
+    assert_eq!(file.generator, GeneratorInfo::unknown());
+    assert_eq!((file.source_root.as_str(), file.generated_at, file.config_digest.as_str()), ("", 0, ""));
+    assert!(file.symbols.is_empty());
+
+    assert_eq!(ids(&file), vec!["main", "fork"]);
+    let main = &file.units[0];
+    assert_eq!(main.kind, UnitKind::Function);
+    assert_eq!(main.dependencies, vec![Dependency::call("fork"), Dependency::call("exit")]);
+    assert_eq!(main.required_headers, vec!["int fork(void);"]);
+}
+
+#[test]
+fn version_1_keeps_its_symbol_table() {
+    let file = load(1).unwrap();
+    assert_eq!(file.schema_version, UNITS_SCHEMA_VERSION);
+    assert_eq!(file.generator, GeneratorInfo::unknown());
+    assert_eq!(file.generated_at, 0);
+
+    let fork = file.symbols.get("proto:fork").unwrap();
+    assert_eq!((fork.kind, fork.definition.as_str()), (SymbolKind::Prototype, "int fork(void);"));
+    assert_eq!(file.units[0].symbol_refs, vec!["proto:fork"]);
+    assert_eq!(file.inline_unit(&file.units[0]).required_headers, vec!["int fork(void);"]);
+    assert_eq!(file.units[0].dependencies, vec![Dependency::call("fork"), Dependency::call("exit")]);
+}
+
+#[test]
+fn version_2_keeps_its_metadata_and_types_its_dependencies() {
+    let file = load(2).unwrap();
+    assert_eq!(file.schema_version, UNITS_SCHEMA_VERSION);
+    assert_eq!(file.generator, GeneratorInfo::new("slicer", "0.1.0"));
+    assert_eq!(file.source_root, "xv6");
+    assert_eq!(file.generated_at, 1_700_000_000);
+    assert_eq!(file.config_digest, "00000000deadbeef");
+    assert_eq!(file.symbols.len(), 1);
+
+    // Plain strings become single direct calls
+    assert_eq!(file.units[0].dependencies, vec![Dependency::call("fork"), Dependency::call("exit")]);
+    assert!(file.units[1].dependencies.is_empty());
+    assert!(file.units.iter().all(|unit| unit.kind == UnitKind::Function));
+}
+
+#[test]
+fn version_3_units_become_functions() {
+    let file = load(3).unwrap();
+    assert_eq!(file.schema_version, UNITS_SCHEMA_VERSION);
+    assert_eq!(file.generator, GeneratorInfo::new("slicer", "0.2.0"));
+    assert!(file.units.iter().all(|unit| unit.kind == UnitKind::Function));
+
+    // Typed dependencies are left as they are
+    let main = &file.units[0];
+    assert_eq!(main.dependencies[0].call_sites, 2);
+    assert_eq!(main.source_file.as_deref(), Some("kernel/main.c"));
+    assert_eq!(file.units[1].dependencies, vec![Dependency::new("nextpid", DependencyKind::GlobalWrite)]);
+}
+
+#[test]
+fn version_4_loads_unchanged() {
+    let file = load(4).unwrap();
+    assert_eq!(file.schema_version, 4);
+    assert_eq!(file.generator, GeneratorInfo::new("slicer", "0.3.0"));
+    assert_eq!(ids(&file), vec!["fork", "global:nextpid"]);
+    assert_eq!(file.units[1].kind, UnitKind::GlobalData);
+    assert_eq!(file.units[0].dependencies, vec![Dependency::new("nextpid", DependencyKind::GlobalWrite)]);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    match load(5) {
+        Err(SchemaError::UnsupportedVersion { found, supported }) => {
+            assert_eq!((found, supported), (5, UNITS_SCHEMA_VERSION));
+        }
+        other => panic!("expected an unsupported version error, got {:?}", other),
+    }
+}
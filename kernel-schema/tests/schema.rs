@@ -0,0 +1,66 @@
+use kernel_schema::{validate_document, AtomicUnit, Dependency, DocumentKind, GeneratorInfo, SymbolTable, UnitsFile};
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Command;
+
+fn units_value() -> Value {
+    let units = vec![
+        AtomicUnit::new("main".into(), "int main(void) { fork(); }".into(), vec![Dependency::call("fork")], Vec::new()),
+        AtomicUnit::new("fork".into(), "int fork(void) { return 0; }".into(), Vec::new(), Vec::new()),
+    ];
+    serde_json::to_value(UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units)).unwrap()
+}
+
+fn paths(kind: DocumentKind, value: &Value) -> Vec<String> {
+    validate_document(kind, value).unwrap().into_iter().map(|violation| violation.path).collect()
+}
+
+#[test]
+fn valid_documents_have_no_violations() {
+    assert!(paths(DocumentKind::Units, &units_value()).is_empty());
+    assert!(paths(DocumentKind::CycleAnalysis, &json!([])).is_empty());
+}
+
+#[test]
+fn violations_point_at_the_offending_value() {
+    let mut value = units_value();
+    value["units"][0]["dependencies"][0]["kind"] = json!("tail_call");
+    value["units"][1]["code"] = json!(42);
+    let violations = validate_document(DocumentKind::Units, &value).unwrap();
+    let mut found: Vec<String> = violations.iter().map(|violation| violation.path.clone()).collect();
+    found.sort();
+    assert_eq!(found, vec!["/units/0/dependencies/0/kind", "/units/1/code"]);
+    let kind = violations.iter().find(|violation| violation.path.ends_with("/kind")).unwrap();
+    assert!(kind.to_string().starts_with("/units/0/dependencies/0/kind: "), "{}", kind);
+    assert!(kind.message.contains("tail_call"), "{}", kind.message);
+
+    let missing = json!({ "metadata": {}, "batches": [{ "is_super_node": false }] });
+    let found = paths(DocumentKind::BuildOrder, &missing);
+    assert!(found.contains(&"/metadata".to_string()), "{:?}", found);
+    assert!(found.contains(&"/batches/0".to_string()), "{:?}", found);
+}
+
+#[test]
+fn old_versions_are_checked_after_migration() {
+    // A version 0 array: paths refer to the migrated envelope, not the array
+    let value = json!([{ "id": "main", "code": "", "dependencies": [7] }]);
+    assert_eq!(paths(DocumentKind::Units, &value), vec!["/units/0/dependencies/0"]);
+
+    let newer = json!({ "schema_version": 99, "units": [] });
+    assert!(validate_document(DocumentKind::Units, &newer).is_err());
+}
+
+#[test]
+fn validate_command_names_the_bad_value() {
+    let path = std::env::temp_dir().join(format!("kernel-schema-validate-{}-units.json", std::process::id()));
+    let mut value = units_value();
+    value["units"][1]["dependencies"] = json!([{ "target": "panic", "kind": "direct_call", "call_sites": -1 }]);
+    fs::write(&path, serde_json::to_string_pretty(&value).unwrap()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_kernel-schema")).arg("validate").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("/units/1/dependencies/0/call_sites: "), "{}", stderr);
+    assert!(stderr.contains("does not match the units schema (1 violation(s))"), "{}", stderr);
+}
@@ -0,0 +1,267 @@
+use kernel_schema::BuildOrderBatch;
+use petgraph::algo::toposort;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use std::collections::HashMap;
+
+/// The SCC of every node, and the condensed graph: one node per SCC, with an
+/// edge wherever a unit depends on a unit in another SCC
+pub fn condense<N, E>(
+    graph: &DiGraph<N, E>,
+    sccs: &[Vec<NodeIndex>],
+) -> (HashMap<NodeIndex, usize>, DiGraph<usize, ()>) {
+    let mut node_to_scc = HashMap::new();
+    for (scc_idx, scc) in sccs.iter().enumerate() {
+        for node_idx in scc {
+            node_to_scc.insert(*node_idx, scc_idx);
+        }
+    }
+
+    let mut cond_graph = DiGraph::<usize, ()>::new();
+    for i in 0..sccs.len() {
+        cond_graph.add_node(i);
+    }
+    for edge in graph.edge_indices() {
+        // Safe: edge_endpoints only returns None for invalid edge indices,
+        // but we're iterating over graph.edge_indices() so they must be valid
+        let (u, v) = graph.edge_endpoints(edge).expect("Edge index from edge_indices() must be valid");
+        let (u_scc, v_scc) = (node_to_scc[&u], node_to_scc[&v]);
+        if u_scc != v_scc {
+            cond_graph.update_edge(NodeIndex::new(u_scc), NodeIndex::new(v_scc), ());
+        }
+    }
+    (node_to_scc, cond_graph)
+}
+
+/// Longest-path level of every SCC in the condensed graph, where an edge
+/// `a -> b` means `a` depends on `b`. SCCs with no dependencies are level 0;
+/// every other SCC is one level above its deepest dependency. `min_levels`
+/// holds an SCC up to a pinned level (pushing its dependents up with it).
+pub fn scc_levels(cond_graph: &DiGraph<usize, ()>, min_levels: &HashMap<usize, usize>) -> anyhow::Result<Vec<usize>> {
+    let mut order = toposort(cond_graph, None)
+        .map_err(|_| anyhow::anyhow!("Cycle detected in condensed graph (should be impossible)"))?;
+    // Dependencies first
+    order.reverse();
+
+    let mut levels = vec![0; cond_graph.node_count()];
+    for scc in order {
+        levels[scc.index()] = cond_graph
+            .neighbors_directed(scc, Direction::Outgoing)
+            .map(|dep| levels[dep.index()] + 1)
+            .max()
+            .unwrap_or(0)
+            .max(min_levels.get(&scc.index()).copied().unwrap_or(0));
+    }
+    Ok(levels)
+}
+
+/// Group SCCs into batches level by level.
+///
+/// All plain units on a level share a batch (split into chunks of at most
+/// `max_batch_size`); each super node gets its own batch on its level and is
+/// never split, even when it is larger than `max_batch_size`. `difficulty`
+/// rates a super node given its SCC index.
+///
+/// With a `token_budget`, plain units are instead packed first-fit decreasing
+/// by `cost`, so no batch exceeds the budget (a unit over budget on its own
+/// aside) and expensive units share batches rather than holding up cheap ones.
+/// Units within such a batch are listed most expensive first, the order that
+/// keeps parallel workers evenly loaded.
+pub fn level_batches(
+    sccs: &[Vec<String>],
+    levels: &[usize],
+    max_batch_size: Option<usize>,
+    token_budget: Option<u64>,
+    cost: impl Fn(&str) -> u64,
+    difficulty: impl Fn(usize) -> &'static str,
+) -> Vec<BuildOrderBatch> {
+    // SCC indices grouped by level in one pass, rather than a scan of every SCC per level
+    let depth = levels.iter().max().map_or(0, |max| max + 1);
+    let mut by_level: Vec<Vec<usize>> = vec![Vec::new(); depth];
+    for (scc, &level) in levels.iter().enumerate() {
+        by_level[level].push(scc);
+    }
+    let mut batches = Vec::new();
+
+    for (level, on_level) in by_level.into_iter().enumerate() {
+        let mut plain: Vec<String> = Vec::new();
+        let mut super_nodes: Vec<usize> = Vec::new();
+        for scc in on_level {
+            match sccs[scc].as_slice() {
+                [unit] => plain.push(unit.clone()),
+                _ => super_nodes.push(scc),
+            }
+        }
+        plain.sort();
+        super_nodes.sort_by(|a, b| sccs[*a].iter().min().cmp(&sccs[*b].iter().min()));
+
+        for scc in super_nodes {
+            let units = &sccs[scc];
+            batches.push(BuildOrderBatch {
+                units: units.clone(),
+                is_super_node: true,
+                scc_size: Some(units.len()),
+                refactoring_difficulty: Some(difficulty(scc).to_string()),
+                level: Some(level),
+                estimated_tokens: None,
+                decomposition: None,
+            });
+        }
+
+        let chunks = match token_budget {
+            Some(budget) => pack_by_cost(plain, budget, max_batch_size, &cost),
+            None => {
+                let chunk_size = max_batch_size.unwrap_or(plain.len()).max(1);
+                plain.chunks(chunk_size).map(<[String]>::to_vec).collect()
+            }
+        };
+        for units in chunks {
+            batches.push(BuildOrderBatch {
+                units,
+                is_super_node: false,
+                scc_size: None,
+                refactoring_difficulty: None,
+                level: Some(level),
+                estimated_tokens: None,
+                decomposition: None,
+            });
+        }
+    }
+
+    batches
+}
+
+/// First-fit decreasing: each unit, most expensive first, joins the first batch
+/// with room for it under `budget` and `max_units`
+fn pack_by_cost(
+    mut units: Vec<String>,
+    budget: u64,
+    max_units: Option<usize>,
+    cost: impl Fn(&str) -> u64,
+) -> Vec<Vec<String>> {
+    units.sort_by_cached_key(|unit| std::cmp::Reverse(cost(unit)));
+    let max_units = max_units.unwrap_or(usize::MAX);
+    let mut bins: Vec<(Vec<String>, u64)> = Vec::new();
+    for unit in units {
+        let unit_cost = cost(&unit);
+        match bins.iter_mut().find(|(bin, total)| bin.len() < max_units && total + unit_cost <= budget) {
+            Some((bin, total)) => {
+                bin.push(unit);
+                *total += unit_cost;
+            }
+            None => bins.push((vec![unit], unit_cost)),
+        }
+    }
+    bins.into_iter().map(|(bin, _)| bin).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Condensed graph of `n` SCCs with `(dependent, dependency)` edges
+    fn cond_graph(n: usize, edges: &[(usize, usize)]) -> DiGraph<usize, ()> {
+        let mut graph = DiGraph::new();
+        for i in 0..n {
+            graph.add_node(i);
+        }
+        for &(from, to) in edges {
+            graph.add_edge(NodeIndex::new(from), NodeIndex::new(to), ());
+        }
+        graph
+    }
+
+    fn sccs(groups: &[&[&str]]) -> Vec<Vec<String>> {
+        groups.iter().map(|group| group.iter().map(|id| id.to_string()).collect()).collect()
+    }
+
+    fn units(batches: &[BuildOrderBatch]) -> Vec<Vec<&str>> {
+        batches.iter().map(|batch| batch.units.iter().map(String::as_str).collect()).collect()
+    }
+
+    #[test]
+    fn levels_follow_the_longest_dependency_chain() {
+        // 0 -> 1 -> 2, and 0 -> 2 directly; 3 stands alone
+        let graph = cond_graph(4, &[(0, 1), (1, 2), (0, 2)]);
+        assert_eq!(scc_levels(&graph, &HashMap::new()).unwrap(), vec![2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn pinned_levels_push_dependents_up() {
+        let graph = cond_graph(3, &[(0, 1), (1, 2)]);
+        let min_levels = HashMap::from([(1, 3)]);
+        assert_eq!(scc_levels(&graph, &min_levels).unwrap(), vec![4, 3, 0]);
+        // A pin below the level dependencies require changes nothing
+        let min_levels = HashMap::from([(0, 1)]);
+        assert_eq!(scc_levels(&graph, &min_levels).unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn plain_units_share_a_batch_per_level() {
+        let sccs = sccs(&[&["c"], &["a"], &["b"], &["d"]]);
+        let batches = level_batches(&sccs, &[0, 0, 0, 1], None, None, |_| 1, |_| "Low");
+        assert_eq!(units(&batches), vec![vec!["a", "b", "c"], vec!["d"]]);
+        assert_eq!(batches.iter().map(|b| b.level).collect::<Vec<_>>(), vec![Some(0), Some(1)]);
+        assert!(batches.iter().all(|b| !b.is_super_node));
+    }
+
+    #[test]
+    fn max_batch_size_splits_levels() {
+        let sccs = sccs(&[&["a"], &["b"], &["c"], &["d"], &["e"]]);
+        let batches = level_batches(&sccs, &[0; 5], Some(2), None, |_| 1, |_| "Low");
+        assert_eq!(units(&batches), vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+    }
+
+    #[test]
+    fn super_nodes_are_never_split() {
+        let sccs = sccs(&[&["x"], &["s2", "s1", "s3"], &["y"]]);
+        let batches = level_batches(&sccs, &[0, 0, 0], Some(2), Some(10), |_| 100, |_| "High");
+        // The super node comes first on its level, keeps its order and ignores both limits
+        assert_eq!(units(&batches), vec![vec!["s2", "s1", "s3"], vec!["x"], vec!["y"]]);
+        assert!(batches[0].is_super_node);
+        assert_eq!(batches[0].scc_size, Some(3));
+        assert_eq!(batches[0].refactoring_difficulty.as_deref(), Some("High"));
+        assert!(!batches[1].is_super_node);
+    }
+
+    #[test]
+    fn token_budget_splits_levels() {
+        let costs = HashMap::from([("a", 60), ("b", 50), ("c", 40), ("d", 30), ("e", 20)]);
+        let sccs = sccs(&[&["a"], &["b"], &["c"], &["d"], &["e"]]);
+        let batches = level_batches(&sccs, &[0; 5], None, Some(100), |id| costs[id], |_| "Low");
+        assert_eq!(units(&batches), vec![vec!["a", "c"], vec!["b", "d", "e"]]);
+
+        // The unit limit still applies under a budget
+        let batches = level_batches(&sccs, &[0; 5], Some(2), Some(100), |id| costs[id], |_| "Low");
+        assert!(batches.iter().all(|b| b.units.len() <= 2));
+        assert!(batches.iter().all(|b| b.units.iter().map(|id| costs[id.as_str()]).sum::<u64>() <= 100));
+    }
+
+    fn pack(costs: &[(&str, u64)], budget: u64, max_units: Option<usize>) -> Vec<Vec<String>> {
+        let table: HashMap<&str, u64> = costs.iter().copied().collect();
+        pack_by_cost(costs.iter().map(|(id, _)| id.to_string()).collect(), budget, max_units, |id| table[id])
+    }
+
+    #[test]
+    fn first_fit_decreasing_fills_known_bins() {
+        let bins = pack(&[("a", 5), ("b", 4), ("c", 3), ("d", 3), ("e", 2), ("f", 2), ("g", 1)], 10, None);
+        assert_eq!(bins, vec![vec!["a", "b", "g"], vec!["c", "d", "e", "f"]]);
+
+        let bins = pack(&[("a", 6), ("b", 6), ("c", 6)], 10, None);
+        assert_eq!(bins.len(), 3);
+    }
+
+    #[test]
+    fn zero_cost_units_fill_the_first_bin_with_room() {
+        let bins = pack(&[("a", 0), ("b", 0), ("c", 10), ("d", 0)], 10, None);
+        assert_eq!(bins, vec![vec!["c", "a", "b", "d"]]);
+        let bins = pack(&[("a", 0), ("b", 0), ("c", 0)], 10, Some(2));
+        assert_eq!(bins, vec![vec!["a", "b"], vec!["c"]]);
+    }
+
+    #[test]
+    fn an_oversized_unit_gets_a_batch_of_its_own() {
+        let bins = pack(&[("small", 30), ("huge", 150), ("tiny", 20)], 100, None);
+        assert_eq!(bins, vec![vec!["huge"], vec!["small", "tiny"]]);
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{feedback, DepEdge};
+use kernel_schema::AtomicUnit;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+/// Local-moving passes before giving up on convergence
+const MAX_PASSES: usize = 50;
+/// Crate for units whose source file is unknown
+const UNKNOWN_CRATE: &str = "misc";
+
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    /// Rust module path, `crate::module`
+    pub path: String,
+    pub crate_name: String,
+    pub module: String,
+    pub units: Vec<String>,
+    /// Source files the units came from
+    pub source_files: Vec<String>,
+    /// Units from another top-level directory, pulled in by their dependencies
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub relocated: Vec<String>,
+    pub internal_edges: usize,
+    /// Edges to and from other clusters
+    pub boundary_edges: usize,
+    /// Internal share of the edges touching the cluster, 0 to 1
+    pub cohesion: f64,
+}
+
+/// Dependencies of one cluster on another
+#[derive(Debug, Serialize, Clone)]
+pub struct ClusterEdge {
+    pub from: String,
+    pub to: String,
+    pub edges: usize,
+    pub call_sites: usize,
+}
+
+/// Clusters that depend on each other in a cycle
+#[derive(Debug, Serialize)]
+pub struct ClusterCycle {
+    pub clusters: Vec<String>,
+    /// Cheapest set of cluster dependencies to remove (by moving units or
+    /// extracting interfaces) to make the cycle acyclic
+    pub break_edges: Vec<ClusterEdge>,
+    /// The unit dependencies behind `break_edges`, `(from, to)`
+    pub unit_edges: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProposedCrate {
+    pub name: String,
+    /// 0 for crates with no dependencies, otherwise one above the highest dependency
+    pub layer: usize,
+    pub modules: Vec<String>,
+    pub depends_on: Vec<String>,
+    /// Top-level directories folded into this crate because they depend on each other
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merged_from: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterReport {
+    /// Newman modularity of the partition on the call-site-weighted graph
+    pub modularity: f64,
+    pub clusters: Vec<Cluster>,
+    pub inter_cluster_edges: Vec<ClusterEdge>,
+    pub cycles: Vec<ClusterCycle>,
+    /// Crates in layering order, lowest layer first
+    pub crates: Vec<ProposedCrate>,
+}
+
+/// Directory and file stem of a source file: `kernel/proc.h` -> (`kernel`, `proc`)
+fn split_source(file: &str) -> (String, String) {
+    let path = Path::new(file);
+    let dir = path.parent().map(|p| p.display().to_string()).unwrap_or_default();
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    (dir, stem)
+}
+
+/// Crate a directory's code belongs to: its top-level component
+fn crate_for_dir(dir: &str) -> String {
+    rust_ident(dir.split('/').find(|part| !part.is_empty() && *part != ".").unwrap_or("core"))
+}
+
+/// A valid snake_case Rust identifier
+fn rust_ident(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, 'm');
+    }
+    ident
+}
+
+/// Partition the units into modules and propose a crate layout.
+///
+/// Units start out grouped by directory and file stem, so `proc.c` and
+/// `proc.h` share a module; edges within a directory count `prior` times their
+/// call sites. Louvain local moving then reassigns units to the neighbouring
+/// cluster with the best modularity gain until nothing moves.
+pub fn cluster(graph: &DiGraph<String, DepEdge>, units: &[AtomicUnit], prior: f64) -> ClusterReport {
+    let source: HashMap<&str, &str> = units
+        .iter()
+        .filter_map(|unit| unit.source_file.as_deref().map(|file| (unit.id.as_str(), file)))
+        .collect();
+
+    // Nodes in ID order, so the result does not depend on graph construction
+    let mut order: Vec<NodeIndex> = graph.node_indices().collect();
+    order.sort_by(|a, b| graph[*a].cmp(&graph[*b]));
+    let n = graph.node_count();
+    let location: Vec<Option<(String, String)>> = graph
+        .node_indices()
+        .map(|idx| source.get(graph[idx].as_str()).map(|file| split_source(file)))
+        .collect();
+
+    // Undirected weighted adjacency; self-loops do not affect the partition
+    let mut adjacency: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    for edge in graph.edge_references() {
+        let (u, v) = (edge.source().index(), edge.target().index());
+        if u == v {
+            continue;
+        }
+        let same_dir = matches!((&location[u], &location[v]), (Some((a, _)), Some((b, _))) if a == b);
+        let weight = edge.weight().call_sites.max(1) as f64 * if same_dir { prior } else { 1.0 };
+        adjacency[u].push((v, weight));
+        adjacency[v].push((u, weight));
+    }
+
+    // Initial partition: one community per directory and file stem
+    let mut seeds: BTreeMap<String, usize> = BTreeMap::new();
+    let mut community = vec![0; n];
+    for &idx in &order {
+        let key = match &location[idx.index()] {
+            Some((dir, stem)) => format!("{}/{}", dir, stem),
+            None => format!("\u{0}{}", graph[idx]),
+        };
+        let next = seeds.len();
+        community[idx.index()] = *seeds.entry(key).or_insert(next);
+    }
+
+    let degree: Vec<f64> = adjacency.iter().map(|edges| edges.iter().map(|(_, w)| w).sum()).collect();
+    let total: f64 = degree.iter().sum();
+    if total > 0.0 {
+        local_moving(&order, &adjacency, &degree, total, &mut community, seeds.len());
+    }
+    let modularity = modularity(&adjacency, &degree, total, &community);
+
+    build_report(graph, &order, &location, &community, modularity)
+}
+
+fn local_moving(
+    order: &[NodeIndex],
+    adjacency: &[Vec<(usize, f64)>],
+    degree: &[f64],
+    total: f64,
+    community: &mut [usize],
+    communities: usize,
+) {
+    let mut community_degree = vec![0.0; communities];
+    for (node, &c) in community.iter().enumerate() {
+        community_degree[c] += degree[node];
+    }
+
+    for _ in 0..MAX_PASSES {
+        let mut moved = false;
+        for idx in order {
+            let node = idx.index();
+            if degree[node] == 0.0 {
+                continue;
+            }
+            let current = community[node];
+            let mut links: BTreeMap<usize, f64> = BTreeMap::new();
+            for &(neighbour, weight) in &adjacency[node] {
+                *links.entry(community[neighbour]).or_insert(0.0) += weight;
+            }
+
+            // Gain of joining community c, with the node itself taken out first
+            community_degree[current] -= degree[node];
+            let gain = |c: usize, community_degree: &[f64]| {
+                links.get(&c).copied().unwrap_or(0.0) - community_degree[c] * degree[node] / total
+            };
+            let mut best = current;
+            let mut best_gain = gain(current, &community_degree);
+            for &c in links.keys() {
+                let g = gain(c, &community_degree);
+                if g > best_gain + 1e-12 {
+                    best = c;
+                    best_gain = g;
+                }
+            }
+            community_degree[best] += degree[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+}
+
+fn modularity(adjacency: &[Vec<(usize, f64)>], degree: &[f64], total: f64, community: &[usize]) -> f64 {
+    if total == 0.0 {
+        return 0.0;
+    }
+    let mut internal: HashMap<usize, f64> = HashMap::new();
+    let mut community_degree: HashMap<usize, f64> = HashMap::new();
+    for (node, edges) in adjacency.iter().enumerate() {
+        *community_degree.entry(community[node]).or_insert(0.0) += degree[node];
+        for &(neighbour, weight) in edges {
+            if community[neighbour] == community[node] {
+                *internal.entry(community[node]).or_insert(0.0) += weight;
+            }
+        }
+    }
+    community_degree
+        .iter()
+        .map(|(c, d)| internal.get(c).copied().unwrap_or(0.0) / total - (d / total).powi(2))
+        .sum()
+}
+
+fn build_report(
+    graph: &DiGraph<String, DepEdge>,
+    order: &[NodeIndex],
+    location: &[Option<(String, String)>],
+    community: &[usize],
+    modularity: f64,
+) -> ClusterReport {
+    // Members of each community, in ID order
+    let mut members: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+    for &idx in order {
+        members.entry(community[idx.index()]).or_default().push(idx);
+    }
+
+    // Name clusters after their most common file: crate from the top-level
+    // directory, module from the file stem
+    let mut named: Vec<(String, String, Vec<NodeIndex>)> = members
+        .into_values()
+        .map(|nodes| {
+            let mut counts: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+            for idx in &nodes {
+                if let Some((dir, stem)) = &location[idx.index()] {
+                    *counts.entry((dir.as_str(), stem.as_str())).or_insert(0) += 1;
+                }
+            }
+            let dominant = counts.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0))).map(|(key, _)| *key);
+            let (crate_name, module) = match dominant {
+                Some((dir, stem)) => {
+                    (crate_for_dir(dir), rust_ident(stem))
+                }
+                None => (UNKNOWN_CRATE.to_string(), rust_ident(&graph[nodes[0]])),
+            };
+            (crate_name, module, nodes)
+        })
+        .collect();
+    named.sort_by(|a, b| (&a.0, &a.1, &graph[a.2[0]]).cmp(&(&b.0, &b.1, &graph[b.2[0]])));
+
+    // Same module name twice in a crate: number the later ones
+    let mut taken: HashMap<(String, String), usize> = HashMap::new();
+    for (crate_name, module, _) in &mut named {
+        let count = taken.entry((crate_name.clone(), module.clone())).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            *module = format!("{}_{}", module, count);
+        }
+    }
+
+    let mut cluster_of = vec![0; graph.node_count()];
+    for (i, (_, _, nodes)) in named.iter().enumerate() {
+        for idx in nodes {
+            cluster_of[idx.index()] = i;
+        }
+    }
+    let path = |i: usize| format!("{}::{}", named[i].0, named[i].1);
+
+    // Cluster-level graph, edges weighted like the unit graph
+    let mut cluster_graph = DiGraph::<String, DepEdge>::new();
+    for i in 0..named.len() {
+        cluster_graph.add_node(path(i));
+    }
+    let mut internal = vec![0; named.len()];
+    let mut boundary = vec![0; named.len()];
+    let mut unit_edges: HashMap<(usize, usize), Vec<(String, String)>> = HashMap::new();
+    for edge in graph.edge_references() {
+        let (from, to) = (cluster_of[edge.source().index()], cluster_of[edge.target().index()]);
+        if from == to {
+            internal[from] += 1;
+            continue;
+        }
+        boundary[from] += 1;
+        boundary[to] += 1;
+        unit_edges
+            .entry((from, to))
+            .or_default()
+            .push((graph[edge.source()].clone(), graph[edge.target()].clone()));
+        let (a, b) = (NodeIndex::new(from), NodeIndex::new(to));
+        match cluster_graph.find_edge(a, b) {
+            Some(existing) => cluster_graph[existing].call_sites += edge.weight().call_sites,
+            None => {
+                cluster_graph.add_edge(a, b, *edge.weight());
+            }
+        }
+    }
+
+    let clusters: Vec<Cluster> = named
+        .iter()
+        .enumerate()
+        .map(|(i, (crate_name, module, nodes))| {
+            let files: BTreeSet<String> = nodes
+                .iter()
+                .filter_map(|idx| location[idx.index()].as_ref())
+                .map(|(dir, stem)| if dir.is_empty() { stem.clone() } else { format!("{}/{}", dir, stem) })
+                .collect();
+            let relocated = nodes
+                .iter()
+                .filter(|idx| {
+                    location[idx.index()].as_ref().is_some_and(|(dir, _)| crate_for_dir(dir) != *crate_name)
+                })
+                .map(|idx| graph[*idx].clone())
+                .collect();
+            let touching = internal[i] + boundary[i];
+            Cluster {
+                path: path(i),
+                crate_name: crate_name.clone(),
+                module: module.clone(),
+                units: nodes.iter().map(|idx| graph[*idx].clone()).collect(),
+                source_files: files.into_iter().collect(),
+                relocated,
+                internal_edges: internal[i],
+                boundary_edges: boundary[i],
+                cohesion: if touching == 0 { 1.0 } else { internal[i] as f64 / touching as f64 },
+            }
+        })
+        .collect();
+
+    let mut inter_cluster_edges: Vec<ClusterEdge> = cluster_graph
+        .edge_references()
+        .map(|edge| ClusterEdge {
+            from: path(edge.source().index()),
+            to: path(edge.target().index()),
+            edges: unit_edges[&(edge.source().index(), edge.target().index())].len(),
+            call_sites: edge.weight().call_sites,
+        })
+        .collect();
+    inter_cluster_edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+    let mut cycles: Vec<ClusterCycle> = tarjan_scc(&cluster_graph)
+        .into_iter()
+        .filter(|scc| scc.len() > 1)
+        .map(|scc| {
+            let fas = feedback::feedback_arc_set(&cluster_graph, &scc);
+            let index: HashMap<&str, usize> = (0..named.len()).map(|i| (cluster_graph[NodeIndex::new(i)].as_str(), i)).collect();
+            let mut clusters: Vec<String> = scc.iter().map(|idx| cluster_graph[*idx].clone()).collect();
+            clusters.sort();
+            let mut behind = Vec::new();
+            let break_edges = fas
+                .arcs
+                .iter()
+                .map(|arc| {
+                    let key = (index[arc.from.as_str()], index[arc.to.as_str()]);
+                    behind.extend(unit_edges[&key].iter().cloned());
+                    ClusterEdge {
+                        from: arc.from.clone(),
+                        to: arc.to.clone(),
+                        edges: unit_edges[&key].len(),
+                        call_sites: arc.call_sites,
+                    }
+                })
+                .collect();
+            behind.sort();
+            ClusterCycle { clusters, break_edges, unit_edges: behind }
+        })
+        .collect();
+    cycles.sort_by(|a, b| a.clusters.cmp(&b.clusters));
+
+    let crates = propose_crates(&named, &cluster_graph);
+    ClusterReport { modularity, clusters, inter_cluster_edges, cycles, crates }
+}
+
+/// One crate per top-level directory, with crates that depend on each other
+/// merged (crates cannot be cyclic), layered by their dependencies
+fn propose_crates(named: &[(String, String, Vec<NodeIndex>)], cluster_graph: &DiGraph<String, DepEdge>) -> Vec<ProposedCrate> {
+    let names: Vec<&str> = named.iter().map(|(crate_name, _, _)| crate_name.as_str()).collect::<BTreeSet<_>>().into_iter().collect();
+    let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (*name, i)).collect();
+
+    let mut crate_graph = DiGraph::<usize, ()>::new();
+    for i in 0..names.len() {
+        crate_graph.add_node(i);
+    }
+    for edge in cluster_graph.edge_references() {
+        let from = index[named[edge.source().index()].0.as_str()];
+        let to = index[named[edge.target().index()].0.as_str()];
+        if from != to {
+            crate_graph.update_edge(NodeIndex::new(from), NodeIndex::new(to), ());
+        }
+    }
+
+    // Merge mutually dependent crates; tarjan_scc lists dependencies first
+    let groups = tarjan_scc(&crate_graph);
+    let mut group_of = vec![0; names.len()];
+    for (g, group) in groups.iter().enumerate() {
+        for idx in group {
+            group_of[idx.index()] = g;
+        }
+    }
+    let mut layers = vec![0; groups.len()];
+    let mut depends_on: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); groups.len()];
+    for (g, group) in groups.iter().enumerate() {
+        for idx in group {
+            for dep in crate_graph.neighbors(*idx) {
+                let d = group_of[dep.index()];
+                if d != g {
+                    depends_on[g].insert(d);
+                    layers[g] = layers[g].max(layers[d] + 1);
+                }
+            }
+        }
+    }
+
+    let group_name = |g: usize| -> String {
+        let mut members: Vec<&str> = groups[g].iter().map(|idx| names[idx.index()]).collect();
+        members.sort();
+        members.join("_")
+    };
+    let mut crates: Vec<ProposedCrate> = (0..groups.len())
+        .map(|g| {
+            let mut merged: Vec<String> = groups[g].iter().map(|idx| names[idx.index()].to_string()).collect();
+            merged.sort();
+            let modules = named
+                .iter()
+                .filter(|(crate_name, _, _)| group_of[index[crate_name.as_str()]] == g)
+                .map(|(crate_name, module, _)| {
+                    if merged.len() > 1 { format!("{}::{}", crate_name, module) } else { module.clone() }
+                })
+                .collect();
+            ProposedCrate {
+                name: group_name(g),
+                layer: layers[g],
+                modules,
+                depends_on: depends_on[g].iter().map(|d| group_name(*d)).collect(),
+                merged_from: if merged.len() > 1 { merged } else { Vec::new() },
+            }
+        })
+        .collect();
+    crates.sort_by(|a, b| (a.layer, &a.name).cmp(&(b.layer, &b.name)));
+    crates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::DependencyKind;
+
+    /// Two cliques of four units, `a*` and `b*`, joined by a single `a1 -> b1`
+    /// call. Every unit has a file of its own, so the initial partition is no help.
+    fn two_groups(reversed: bool) -> (DiGraph<String, DepEdge>, Vec<AtomicUnit>) {
+        let mut ids: Vec<String> = ["a", "b"].iter().flat_map(|g| (1..=4).map(move |i| format!("{}{}", g, i))).collect();
+        if reversed {
+            ids.reverse();
+        }
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<String, NodeIndex> = ids.iter().map(|id| (id.clone(), graph.add_node(id.clone()))).collect();
+        let edge = |call_sites| DepEdge { kind: DependencyKind::DirectCall, call_sites };
+        for group in ["a", "b"] {
+            for i in 1..=4 {
+                for j in i + 1..=4 {
+                    graph.add_edge(nodes[&format!("{}{}", group, i)], nodes[&format!("{}{}", group, j)], edge(3));
+                }
+            }
+        }
+        graph.add_edge(nodes["a1"], nodes["b1"], edge(1));
+        let units = ids
+            .iter()
+            .map(|id| AtomicUnit::new(id.clone(), String::new(), Vec::new(), Vec::new()).with_source_file(format!("kern/{}.c", id)))
+            .collect();
+        (graph, units)
+    }
+
+    #[test]
+    fn dense_groups_joined_by_a_bridge_become_two_clusters() {
+        let (graph, units) = two_groups(false);
+        let report = cluster(&graph, &units, 1.0);
+
+        let clusters: Vec<(&str, Vec<&str>)> = report
+            .clusters
+            .iter()
+            .map(|c| (c.path.as_str(), c.units.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(clusters, vec![("kern::a1", vec!["a1", "a2", "a3", "a4"]), ("kern::b1", vec!["b1", "b2", "b3", "b4"])]);
+        assert_eq!(report.inter_cluster_edges.len(), 1);
+        assert_eq!((report.inter_cluster_edges[0].from.as_str(), report.inter_cluster_edges[0].to.as_str()), ("kern::a1", "kern::b1"));
+        assert!(report.cycles.is_empty());
+        // Each clique holds 18 of the 37 call sites, and half the bridge
+        assert!((report.modularity - (2.0 * (18.0 / 37.0) - 2.0 * (18.5f64 / 37.0).powi(2))).abs() < 1e-9);
+        assert_eq!(report.crates.len(), 1);
+        assert_eq!(report.crates[0].modules, vec!["a1", "b1"]);
+
+        // Same partition whatever order the graph was built in
+        let (graph, units) = two_groups(true);
+        let again = cluster(&graph, &units, 1.0);
+        assert_eq!(serde_json::to_string(&report).unwrap(), serde_json::to_string(&again).unwrap());
+    }
+}
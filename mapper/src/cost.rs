@@ -0,0 +1,206 @@
+use kernel_schema::{AtomicUnit, BuildOrderBatch, PlanCost, UnitsFile};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Characters per token, the usual rule of thumb for source code
+const CHARS_PER_TOKEN: usize = 4;
+/// Instructions sent with every unit
+const PROMPT_OVERHEAD_TOKENS: u64 = 400;
+/// Rust tokens written per C token ported
+const OUTPUT_RATIO: f64 = 1.3;
+/// Expected extra attempts per decision point: branchy code fails verification more often
+const RETRIES_PER_DECISION: f64 = 0.02;
+const MAX_ATTEMPTS: f64 = 3.0;
+
+/// Expected cost of porting one unit
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitCost {
+    pub id: String,
+    pub code_tokens: u64,
+    /// Inlined context definitions sent along with the code
+    pub header_tokens: u64,
+    /// Cyclomatic complexity: 1 plus the decision points
+    pub complexity: usize,
+    /// Expected attempts until the port verifies
+    pub attempts: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchCost {
+    pub batch: usize,
+    pub level: Option<usize>,
+    pub units: usize,
+    pub tokens: u64,
+    pub makespan_tokens: u64,
+}
+
+/// Everything the cost report contains
+#[derive(Debug, Serialize)]
+pub struct CostReport<'a> {
+    pub plan: &'a PlanCost,
+    pub batches: Vec<BatchCost>,
+    /// Most expensive first
+    pub units: Vec<&'a UnitCost>,
+}
+
+fn tokens(chars: usize) -> u64 {
+    chars.div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+pub fn estimate(units_file: &UnitsFile, unit: &AtomicUnit) -> UnitCost {
+    let inlined = units_file.inline_unit(unit);
+    let code_tokens = tokens(unit.code.len());
+    let header_tokens = tokens(inlined.required_headers.iter().map(String::len).sum());
+    let complexity = cyclomatic_complexity(&unit.code);
+    let attempts = (1.0 + RETRIES_PER_DECISION * (complexity - 1) as f64).min(MAX_ATTEMPTS);
+    let input_tokens = ((PROMPT_OVERHEAD_TOKENS + code_tokens + header_tokens) as f64 * attempts).ceil() as u64;
+    let output_tokens = (code_tokens as f64 * OUTPUT_RATIO * attempts).ceil() as u64;
+    UnitCost {
+        id: unit.id.clone(),
+        code_tokens,
+        header_tokens,
+        complexity,
+        attempts,
+        input_tokens,
+        output_tokens,
+        total_tokens: input_tokens + output_tokens,
+    }
+}
+
+/// 1 plus the `if`, `for`, `while`, `case`, `&&`, `||` and `?` in `code`,
+/// outside comments and literals
+pub fn cyclomatic_complexity(code: &str) -> usize {
+    let bytes = code.as_bytes();
+    let mut decisions = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            b'&' if bytes.get(i + 1) == Some(&b'&') => {
+                decisions += 1;
+                i += 2;
+            }
+            b'|' if bytes.get(i + 1) == Some(&b'|') => {
+                decisions += 1;
+                i += 2;
+            }
+            b'?' => {
+                decisions += 1;
+                i += 1;
+            }
+            c if c == b'_' || c.is_ascii_alphabetic() => {
+                let start = i;
+                while i < bytes.len() && (bytes[i] == b'_' || bytes[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                if matches!(&code[start..i], "if" | "for" | "while" | "case") {
+                    decisions += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    1 + decisions
+}
+
+/// Time for `workers` to finish `jobs`, longest job first onto the least busy worker
+pub fn makespan(jobs: impl IntoIterator<Item = u64>, workers: usize) -> u64 {
+    let mut jobs: Vec<u64> = jobs.into_iter().collect();
+    jobs.sort_unstable_by(|a, b| b.cmp(a));
+    let mut loads: BinaryHeap<Reverse<u64>> = (0..workers.max(1)).map(|_| Reverse(0)).collect();
+    for job in jobs {
+        let Reverse(load) = loads.pop().expect("at least one worker");
+        loads.push(Reverse(load + job));
+    }
+    loads.into_iter().map(|Reverse(load)| load).max().unwrap_or(0)
+}
+
+/// Fill in each batch's estimate and total up the plan
+pub fn plan_cost(
+    batches: &mut [BuildOrderBatch],
+    costs: &HashMap<String, UnitCost>,
+    workers: usize,
+    token_budget: Option<u64>,
+) -> (PlanCost, Vec<BatchCost>) {
+    let mut plan = PlanCost {
+        input_tokens: 0,
+        output_tokens: 0,
+        total_tokens: 0,
+        workers,
+        token_budget,
+        makespan_tokens: 0,
+    };
+    let mut batch_costs = Vec::with_capacity(batches.len());
+    for (i, batch) in batches.iter_mut().enumerate() {
+        let units: Vec<&UnitCost> = batch.units.iter().map(|id| &costs[id]).collect();
+        let tokens = units.iter().map(|c| c.total_tokens).sum();
+        let span = makespan(units.iter().map(|c| c.total_tokens), workers);
+        plan.input_tokens += units.iter().map(|c| c.input_tokens).sum::<u64>();
+        plan.output_tokens += units.iter().map(|c| c.output_tokens).sum::<u64>();
+        plan.total_tokens += tokens;
+        plan.makespan_tokens += span;
+        batch.estimated_tokens = Some(tokens);
+        batch_costs.push(BatchCost {
+            batch: i,
+            level: batch.level,
+            units: batch.units.len(),
+            tokens,
+            makespan_tokens: span,
+        });
+    }
+    (plan, batch_costs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makespan_assigns_longest_jobs_first() {
+        // LPT: 7 | 5, then 4 to the idler worker (9), 3 to 7 (10), 3 to 9 (12)
+        assert_eq!(makespan([3, 7, 3, 5, 4], 2), 12);
+        assert_eq!(makespan([4, 4, 4, 4], 2), 8);
+        assert_eq!(makespan([1, 2, 3], 1), 6);
+    }
+
+    #[test]
+    fn makespan_edge_cases() {
+        assert_eq!(makespan([], 4), 0);
+        assert_eq!(makespan([0, 0, 0], 2), 0);
+        assert_eq!(makespan([5, 0, 0, 5], 2), 5);
+        // One job dominates however many workers there are
+        assert_eq!(makespan([100, 1, 1, 1], 3), 100);
+        assert_eq!(makespan([2, 3], 8), 3);
+        // Zero workers is treated as one
+        assert_eq!(makespan([2, 3], 0), 5);
+    }
+
+    #[test]
+    fn cyclomatic_complexity_counts_decisions_outside_comments_and_strings() {
+        assert_eq!(cyclomatic_complexity("void f(void) { return; }"), 1);
+        assert_eq!(cyclomatic_complexity("int f(int a) { if (a && b) return a ? 1 : 2; while (x) {} }"), 5);
+        assert_eq!(cyclomatic_complexity("void f(void) { /* if */ puts(\"for || while\"); // case\n }"), 1);
+    }
+}
@@ -0,0 +1,194 @@
+use kernel_schema::{AtomicUnit, DecompositionPlan, DecompositionStep, FeedbackArcSet, Stub, UnitKind};
+use std::collections::HashMap;
+
+/// Port a super node in its feedback arc set order: each arc `from -> to` has
+/// `from` ported against a stub of `to`, which is replaced once `to` is ported
+pub fn plan(fas: &FeedbackArcSet, units: &HashMap<&str, &AtomicUnit>) -> DecompositionPlan {
+    let position: HashMap<&str, usize> = fas.order.iter().enumerate().map(|(i, id)| (id.as_str(), i)).collect();
+    let mut arcs: Vec<(&str, &str)> = fas.arcs.iter().map(|arc| (arc.from.as_str(), arc.to.as_str())).collect();
+    arcs.sort_by_key(|(from, to)| (position[to], position[from]));
+
+    let mut stubs: Vec<Stub> = Vec::new();
+    for (from, to) in arcs {
+        match stubs.last_mut() {
+            Some(stub) if stub.unit == to => stub.used_by.push(from.to_string()),
+            _ => stubs.push(Stub {
+                unit: to.to_string(),
+                declaration: declaration(units[to]),
+                used_by: vec![from.to_string()],
+            }),
+        }
+    }
+
+    let mut steps = Vec::new();
+    for unit in &fas.order {
+        let mut needed: Vec<&Stub> = stubs.iter().filter(|stub| stub.used_by.contains(unit)).collect();
+        needed.sort_by_key(|stub| position[stub.unit.as_str()]);
+        steps.push(DecompositionStep::Port {
+            unit: unit.clone(),
+            stubs: needed.iter().map(|stub| stub.unit.clone()).collect(),
+        });
+        if let Some(stub) = stubs.iter().find(|stub| &stub.unit == unit) {
+            steps.push(DecompositionStep::ReplaceStub { unit: unit.clone(), reverify: stub.used_by.clone() });
+        }
+    }
+    DecompositionPlan { stubs, steps }
+}
+
+/// The C declaration other members can be compiled against before `unit` is ported
+fn declaration(unit: &AtomicUnit) -> String {
+    let code = unit.code.trim();
+    let head = |end: usize| code[..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    match unit.kind {
+        UnitKind::Function => match code.find('{') {
+            Some(body) => {
+                let head = head(body);
+                knr_declarator(&head).map_or_else(|| format!("{};", head), |declarator| format!("{}();", declarator))
+            }
+            None => code.to_string(),
+        },
+        // `struct proc;` and `typedef struct proc proc_t;`; enums and other
+        // typedefs have no forward form
+        UnitKind::TypeDefinition => {
+            let Some(body) = code.find('{') else { return code.to_string() };
+            let head = head(body);
+            let is_tagged = matches!(head.split(' ').next_back(), Some(tag) if tag != "struct" && tag != "union");
+            let is_record = head.starts_with("struct ") || head.starts_with("union ");
+            let typedef_record = head.strip_prefix("typedef ").filter(|rest| rest.starts_with("struct ") || rest.starts_with("union "));
+            match typedef_record {
+                Some(record) if is_tagged => match code.rfind('}') {
+                    Some(close) => format!("typedef {} {}", record, code[close + 1..].trim()),
+                    None => code.to_string(),
+                },
+                None if is_record && is_tagged => format!("{};", head),
+                _ => code.to_string(),
+            }
+        }
+        // `extern int ticks;`, or a tentative definition for file-local data
+        UnitKind::GlobalData => {
+            let end = code.find('=').or_else(|| code.rfind(';')).unwrap_or(code.len());
+            let head = head(end);
+            if head.starts_with("static ") { format!("{};", head) } else { format!("extern {};", head.trim_start_matches("extern ")) }
+        }
+        UnitKind::FunctionMacro | UnitKind::InlineAsm => code.to_string(),
+    }
+}
+
+/// `int add(a, b)` of a K&R definition, whose parameter types follow the
+/// list as `int a; int b;`: the names alone make no prototype, so the stub
+/// is the unprototyped `int add();`
+fn knr_declarator(head: &str) -> Option<&str> {
+    let mut depth = 0;
+    let mut open = 0;
+    for (i, c) in head.char_indices() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    open = i;
+                }
+                depth += 1;
+            }
+            ')' => {
+                depth -= 1;
+                let names = &head[open + 1..i];
+                let is_names = names.split(',').all(|name| {
+                    let name = name.trim();
+                    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                });
+                if depth == 0 && is_names && head[i + 1..].contains(';') {
+                    return Some(head[..open].trim_end());
+                }
+            }
+            // Only a K&R list is followed by declarations
+            ';' if depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::{FasMethod, FeedbackArc};
+
+    fn function(id: &str, code: &str) -> AtomicUnit {
+        AtomicUnit::new(id.into(), code.into(), Vec::new(), Vec::new())
+    }
+
+    #[test]
+    fn function_stubs_are_prototypes() {
+        let cases = [
+            ("int\nadd(int a,\n    int b)\n{\n    return a + b;\n}", "int add(int a, int b);"),
+            ("int add(a, b)\n    int a;\n    int b;\n{\n    return a + b;\n}", "int add();"),
+            ("static char *\nskipelem(path, name) char *path; char *name; { return 0; }", "static char * skipelem();"),
+            (
+                "__attribute__((noreturn))\nvoid\npanic(char *s) __attribute__((cold))\n{\n    for (;;)\n        ;\n}",
+                "__attribute__((noreturn)) void panic(char *s) __attribute__((cold));",
+            ),
+            ("static inline uint64\nr_sp(void)\n{\n    return 0;\n}", "static inline uint64 r_sp(void);"),
+            (
+                "void (*signal(int sig, void (*handler)(int)))(int) {\n    return handler;\n}",
+                "void (*signal(int sig, void (*handler)(int)))(int);",
+            ),
+            ("int (*lookup(char *name))(void) { return 0; }", "int (*lookup(char *name))(void);"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(declaration(&function("f", code)), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn data_and_type_stubs_are_forward_declarations() {
+        let unit = |kind, code: &str| function("x", code).with_kind(kind);
+        assert_eq!(declaration(&unit(UnitKind::GlobalData, "int ticks = 0;")), "extern int ticks;");
+        assert_eq!(declaration(&unit(UnitKind::GlobalData, "static struct spinlock lock;")), "static struct spinlock lock;");
+        assert_eq!(declaration(&unit(UnitKind::TypeDefinition, "struct proc {\n    int pid;\n};")), "struct proc;");
+        assert_eq!(
+            declaration(&unit(UnitKind::TypeDefinition, "typedef struct proc { int pid; } proc_t;")),
+            "typedef struct proc proc_t;"
+        );
+        let enumeration = "enum procstate { UNUSED, RUNNING };";
+        assert_eq!(declaration(&unit(UnitKind::TypeDefinition, enumeration)), enumeration);
+    }
+
+    /// `sched -> yield -> swtch -> sched`, plus `swtch -> yield`, ported as
+    /// swtch, yield, sched with both of swtch's calls stubbed
+    #[test]
+    fn stubs_are_replaced_as_soon_as_their_unit_is_ported() {
+        let arc = |from: &str, to: &str| FeedbackArc { from: from.into(), to: to.into(), call_sites: 1 };
+        let fas = FeedbackArcSet {
+            method: FasMethod::Exact,
+            arcs: vec![arc("swtch", "sched"), arc("swtch", "yield")],
+            total_call_sites: 2,
+            order: vec!["swtch".into(), "yield".into(), "sched".into()],
+        };
+        let units = [
+            function("sched", "void sched(void) { yield(); }"),
+            function("yield", "void yield(void) { swtch(); }"),
+            function("swtch", "void swtch(void) { sched(); yield(); }"),
+        ];
+        let by_id: HashMap<&str, &AtomicUnit> = units.iter().map(|unit| (unit.id.as_str(), unit)).collect();
+        let plan = plan(&fas, &by_id);
+
+        let stubs: Vec<(&str, &str, Vec<String>)> =
+            plan.stubs.iter().map(|stub| (stub.unit.as_str(), stub.declaration.as_str(), stub.used_by.clone())).collect();
+        assert_eq!(
+            stubs,
+            vec![
+                ("yield", "void yield(void);", vec!["swtch".to_string()]),
+                ("sched", "void sched(void);", vec!["swtch".to_string()]),
+            ]
+        );
+        let port = |unit: &str, stubs: &[&str]| DecompositionStep::Port {
+            unit: unit.into(),
+            stubs: stubs.iter().map(|stub| stub.to_string()).collect(),
+        };
+        let replace = |unit: &str| DecompositionStep::ReplaceStub { unit: unit.into(), reverify: vec!["swtch".into()] };
+        assert_eq!(
+            plan.steps,
+            vec![port("swtch", &["yield", "sched"]), port("yield", &[]), replace("yield"), port("sched", &[]), replace("sched")]
+        );
+    }
+}
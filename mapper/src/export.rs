@@ -0,0 +1,695 @@
+use crate::DepEdge;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use kernel_schema::{AtomicUnit, DependencyKind, FeedbackArcSet};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Export format, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dot,
+    GraphMl,
+    Mermaid,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dot" | "gv") => Ok(Self::Dot),
+            Some("graphml") => Ok(Self::GraphMl),
+            Some("mmd" | "mermaid") => Ok(Self::Mermaid),
+            _ => bail!("Cannot tell the export format of {:?}: use .dot, .gv, .graphml, .mmd or .mermaid", path),
+        }
+    }
+
+    fn render(self, view: &View) -> String {
+        match self {
+            Self::Dot => render_dot(view),
+            Self::GraphMl => render_graphml(view),
+            Self::Mermaid => render_mermaid(view),
+        }
+    }
+}
+
+/// Which graph to export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GraphView {
+    /// One node per unit; super nodes are drawn as clusters
+    Units,
+    /// The condensed graph: one node per SCC
+    Sccs,
+}
+
+/// What node colors encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorBy {
+    /// Plain nodes
+    None,
+    /// Source file of the unit
+    File,
+    /// Directory of the unit's source file
+    Module,
+    /// Conductor task state (requires --state)
+    State,
+}
+
+/// Graphs and per-unit groups shared by every export
+pub struct ExportInput<'a> {
+    pub graph: &'a DiGraph<String, DepEdge>,
+    pub sccs: &'a [Vec<NodeIndex>],
+    pub node_to_scc: &'a HashMap<NodeIndex, usize>,
+    pub feedback_sets: &'a HashMap<usize, FeedbackArcSet>,
+    /// Unit ID -> color group
+    pub groups: HashMap<String, String>,
+}
+
+/// Conductor task states keyed by unit ID, as printed by
+/// `sqlite3 -json conductor.db "SELECT atomic_unit_id, state FROM tasks"`
+pub fn load_states(path: &Path) -> Result<HashMap<String, String>> {
+    #[derive(Deserialize)]
+    struct Row {
+        atomic_unit_id: String,
+        state: String,
+    }
+
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read conductor state {:?}", path))?;
+    // sqlite3 prints nothing at all for an empty result
+    if text.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<Row> = serde_json::from_str(&text)
+        .with_context(|| format!("Expected a JSON array of {{atomic_unit_id, state}} rows in {:?}", path))?;
+    Ok(rows.into_iter().map(|row| (row.atomic_unit_id, row.state)).collect())
+}
+
+/// Color group of every unit; units without one are left out
+pub fn unit_groups(units: &[AtomicUnit], color_by: ColorBy, states: &HashMap<String, String>) -> HashMap<String, String> {
+    units
+        .iter()
+        .filter_map(|unit| {
+            let group = match color_by {
+                ColorBy::None => None,
+                ColorBy::File => unit.source_file.clone(),
+                ColorBy::Module => unit.source_file.as_deref().map(|file| match Path::new(file).parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.display().to_string(),
+                    _ => ".".to_string(),
+                }),
+                ColorBy::State => Some(states.get(&unit.id).cloned().unwrap_or_else(|| "UNTRACKED".to_string())),
+            };
+            group.map(|group| (unit.id.clone(), group))
+        })
+        .collect()
+}
+
+/// Write one export of the graph, limited to `depth` hops around `root` if given.
+/// Returns the number of nodes and edges written.
+pub fn export(
+    input: &ExportInput,
+    path: &Path,
+    view: GraphView,
+    root: Option<&str>,
+    depth: Option<usize>,
+) -> Result<(usize, usize)> {
+    let format = ExportFormat::from_path(path)?;
+    let view = match view {
+        GraphView::Units => units_view(input, root, depth)?,
+        GraphView::Sccs => sccs_view(input, root, depth)?,
+    };
+    fs::write(path, format.render(&view)).with_context(|| format!("Failed to write graph export {:?}", path))?;
+    Ok((view.nodes.len(), view.edges.len()))
+}
+
+/// Format-independent graph ready for rendering
+struct View {
+    nodes: Vec<ViewNode>,
+    edges: Vec<ViewEdge>,
+    /// Clusters (super nodes in the units view) with their member node indices
+    clusters: Vec<(String, Vec<usize>)>,
+    /// Group -> fill color, in display order
+    legend: Vec<(String, &'static str)>,
+}
+
+struct ViewNode {
+    label: String,
+    group: Option<String>,
+    color: &'static str,
+    /// A whole super node (SCC view only)
+    super_node: bool,
+}
+
+struct ViewEdge {
+    from: usize,
+    to: usize,
+    kind: DependencyKind,
+    call_sites: usize,
+    /// Part of the super node's feedback arc set
+    feedback: bool,
+}
+
+const DEFAULT_COLOR: &str = "#ffffff";
+const MIXED_COLOR: &str = "#bdbdbd";
+const PALETTE: [&str; 12] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462",
+    "#b3de69", "#fccde5", "#bc80bd", "#ccebc5", "#ffed6f", "#a6cee3",
+];
+
+fn state_color(state: &str) -> Option<&'static str> {
+    match state {
+        "PENDING" => Some("#d9d9d9"),
+        "IN_PROGRESS" => Some("#ffe082"),
+        "COMPLETED" => Some("#a5d6a7"),
+        "FAILED" => Some("#ef9a9a"),
+        "UNTRACKED" => Some(DEFAULT_COLOR),
+        _ => None,
+    }
+}
+
+/// Fill color of every group: fixed colors for conductor states, otherwise the
+/// palette in group order (cycling when there are more groups than colors)
+fn group_colors<'a>(groups: impl Iterator<Item = &'a String>) -> Vec<(String, &'static str)> {
+    let groups: BTreeSet<&String> = groups.collect();
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(i, group)| (group.clone(), state_color(group).unwrap_or(PALETTE[i % PALETTE.len()])))
+        .collect()
+}
+
+/// Nodes within `depth` hops of `start`, following edges in either direction
+fn neighbourhood<N, E>(graph: &DiGraph<N, E>, start: NodeIndex, depth: Option<usize>) -> HashSet<NodeIndex> {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((node, distance)) = queue.pop_front() {
+        if depth.is_some_and(|depth| distance >= depth) {
+            continue;
+        }
+        for next in graph.neighbors_undirected(node) {
+            if seen.insert(next) {
+                queue.push_back((next, distance + 1));
+            }
+        }
+    }
+    seen
+}
+
+fn find_root(graph: &DiGraph<String, DepEdge>, root: &str) -> Result<NodeIndex> {
+    graph
+        .node_indices()
+        .find(|idx| graph[*idx] == root)
+        .with_context(|| format!("Root unit '{}' is not in the dependency graph", root))
+}
+
+fn units_view(input: &ExportInput, root: Option<&str>, depth: Option<usize>) -> Result<View> {
+    let graph = input.graph;
+    let keep = match root {
+        Some(root) => neighbourhood(graph, find_root(graph, root)?, depth),
+        None => graph.node_indices().collect(),
+    };
+    let mut kept: Vec<NodeIndex> = keep.into_iter().collect();
+    kept.sort_by(|a, b| graph[*a].cmp(&graph[*b]));
+    let position: HashMap<NodeIndex, usize> = kept.iter().enumerate().map(|(i, idx)| (*idx, i)).collect();
+
+    let legend = group_colors(kept.iter().filter_map(|idx| input.groups.get(&graph[*idx])));
+    let colors: HashMap<&str, &'static str> = legend.iter().map(|(group, color)| (group.as_str(), *color)).collect();
+    let nodes = kept
+        .iter()
+        .map(|idx| {
+            let group = input.groups.get(&graph[*idx]).cloned();
+            ViewNode {
+                label: graph[*idx].clone(),
+                color: group.as_deref().map_or(DEFAULT_COLOR, |group| colors[group]),
+                group,
+                super_node: false,
+            }
+        })
+        .collect();
+
+    // Super nodes in order of their first member
+    let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for idx in &kept {
+        let scc = input.node_to_scc[idx];
+        if input.sccs[scc].len() > 1 {
+            members.entry(scc).or_default().push(position[idx]);
+        }
+    }
+    let mut clusters: Vec<(usize, Vec<usize>)> = members.into_iter().collect();
+    clusters.sort_by_key(|(_, nodes)| nodes[0]);
+    let clusters = clusters
+        .into_iter()
+        .enumerate()
+        .map(|(i, (scc, nodes))| (format!("Super Node {} ({} units)", i + 1, input.sccs[scc].len()), nodes))
+        .collect();
+
+    let mut edges: Vec<ViewEdge> = graph
+        .edge_references()
+        .filter_map(|edge| {
+            let (from, to) = (*position.get(&edge.source())?, *position.get(&edge.target())?);
+            let feedback = input.feedback_sets.get(&input.node_to_scc[&edge.source()]).is_some_and(|fas| {
+                fas.arcs.iter().any(|arc| arc.from == graph[edge.source()] && arc.to == graph[edge.target()])
+            });
+            Some(ViewEdge { from, to, kind: edge.weight().kind, call_sites: edge.weight().call_sites, feedback })
+        })
+        .collect();
+    edges.sort_by_key(|edge| (edge.from, edge.to));
+
+    Ok(View { nodes, edges, clusters, legend })
+}
+
+fn sccs_view(input: &ExportInput, root: Option<&str>, depth: Option<usize>) -> Result<View> {
+    let graph = input.graph;
+
+    // Condensed edges carry the merged kind and call sites of the unit edges they stand for
+    let mut cond_graph = DiGraph::<usize, DepEdge>::new();
+    for i in 0..input.sccs.len() {
+        cond_graph.add_node(i);
+    }
+    for edge in graph.edge_references() {
+        let (from, to) = (input.node_to_scc[&edge.source()], input.node_to_scc[&edge.target()]);
+        if from == to {
+            continue;
+        }
+        let (from, to) = (NodeIndex::new(from), NodeIndex::new(to));
+        match cond_graph.find_edge(from, to) {
+            Some(existing) => {
+                let existing = &mut cond_graph[existing];
+                existing.call_sites += edge.weight().call_sites;
+                if edge.weight().kind.is_call() && !existing.kind.is_call() {
+                    existing.kind = edge.weight().kind;
+                }
+            }
+            None => {
+                cond_graph.add_edge(from, to, *edge.weight());
+            }
+        }
+    }
+
+    let keep = match root {
+        Some(root) => {
+            let scc = input.node_to_scc[&find_root(graph, root)?];
+            neighbourhood(&cond_graph, NodeIndex::new(scc), depth)
+        }
+        None => cond_graph.node_indices().collect(),
+    };
+
+    // SCC members sorted by ID; SCCs ordered by their first member
+    let mut kept: Vec<(usize, Vec<&String>)> = keep
+        .into_iter()
+        .map(|idx| {
+            let mut units: Vec<&String> = input.sccs[idx.index()].iter().map(|node| &graph[*node]).collect();
+            units.sort();
+            (idx.index(), units)
+        })
+        .collect();
+    kept.sort_by(|a, b| a.1.cmp(&b.1));
+    let position: HashMap<usize, usize> = kept.iter().enumerate().map(|(i, (scc, _))| (*scc, i)).collect();
+
+    // An SCC takes its members' group when they all share one
+    let scc_group = |units: &[&String]| -> Option<String> {
+        let groups: BTreeSet<Option<&String>> = units.iter().map(|unit| input.groups.get(*unit)).collect();
+        match groups.into_iter().collect::<Vec<_>>().as_slice() {
+            [Some(group)] => Some((*group).clone()),
+            _ => None,
+        }
+    };
+    let groups: Vec<Option<String>> = kept.iter().map(|(_, units)| scc_group(units)).collect();
+    let legend = group_colors(groups.iter().flatten());
+    let colors: HashMap<&str, &'static str> = legend.iter().map(|(group, color)| (group.as_str(), *color)).collect();
+    let nodes = kept
+        .iter()
+        .zip(groups)
+        .map(|((_, units), group)| {
+            let super_node = units.len() > 1;
+            let label = if super_node {
+                let shown: Vec<&str> = units.iter().take(3).map(|unit| unit.as_str()).collect();
+                let more = if units.len() > 3 { ", ..." } else { "" };
+                format!("Super Node ({} units): {}{}", units.len(), shown.join(", "), more)
+            } else {
+                units[0].clone()
+            };
+            let color = match &group {
+                Some(group) => colors[group.as_str()],
+                None if super_node && !input.groups.is_empty() => MIXED_COLOR,
+                None => DEFAULT_COLOR,
+            };
+            ViewNode { label, group, color, super_node }
+        })
+        .collect();
+
+    let mut edges: Vec<ViewEdge> = cond_graph
+        .edge_references()
+        .filter_map(|edge| {
+            let (from, to) = (*position.get(&edge.source().index())?, *position.get(&edge.target().index())?);
+            Some(ViewEdge { from, to, kind: edge.weight().kind, call_sites: edge.weight().call_sites, feedback: false })
+        })
+        .collect();
+    edges.sort_by_key(|edge| (edge.from, edge.to));
+
+    Ok(View { nodes, edges, clusters: Vec::new(), legend })
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Mermaid entity codes; `#` first, since the codes themselves use it
+fn mermaid_escape(text: &str) -> String {
+    text.replace('#', "#35;").replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;").replace('&', "#amp;")
+}
+
+/// Graphviz DOT; super nodes become `cluster_` subgraphs and a legend cluster
+/// lists the color groups
+fn render_dot(view: &View) -> String {
+    let mut out = String::new();
+    let node_line = |out: &mut String, i: usize, indent: &str| {
+        let node = &view.nodes[i];
+        let mut attrs = format!("label=\"{}\", fillcolor=\"{}\"", dot_escape(&node.label), node.color);
+        if let Some(group) = &node.group {
+            let _ = write!(attrs, ", tooltip=\"{}\"", dot_escape(group));
+        }
+        if node.super_node {
+            attrs.push_str(", peripheries=2, color=\"#d62728\"");
+        }
+        let _ = writeln!(out, "{}n{} [{}];", indent, i, attrs);
+    };
+
+    out.push_str("digraph dependencies {\n");
+    out.push_str("  rankdir=LR;\n");
+    out.push_str("  node [shape=box, style=filled, fillcolor=\"#ffffff\", fontname=\"Helvetica\"];\n");
+    out.push_str("  edge [fontname=\"Helvetica\", fontsize=10];\n");
+
+    let mut clustered = vec![false; view.nodes.len()];
+    for (i, (label, members)) in view.clusters.iter().enumerate() {
+        let _ = writeln!(out, "  subgraph cluster_{} {{", i);
+        let _ = writeln!(out, "    label=\"{}\";", dot_escape(label));
+        out.push_str("    style=dashed;\n    color=\"#d62728\";\n");
+        for &member in members {
+            clustered[member] = true;
+            node_line(&mut out, member, "    ");
+        }
+        out.push_str("  }\n");
+    }
+    for i in (0..view.nodes.len()).filter(|i| !clustered[*i]) {
+        node_line(&mut out, i, "  ");
+    }
+
+    for edge in &view.edges {
+        let mut attrs = format!("tooltip=\"{} x{}\"", edge.kind, edge.call_sites);
+        if !edge.kind.is_call() {
+            attrs.push_str(", style=dashed");
+        }
+        if edge.feedback {
+            attrs.push_str(", color=\"#d62728\", penwidth=2");
+        }
+        let _ = writeln!(out, "  n{} -> n{} [{}];", edge.from, edge.to, attrs);
+    }
+
+    if !view.legend.is_empty() {
+        out.push_str("  subgraph cluster_legend {\n    label=\"Legend\";\n    style=solid;\n    color=\"#999999\";\n");
+        for (i, (group, color)) in view.legend.iter().enumerate() {
+            let _ = writeln!(out, "    legend{} [label=\"{}\", fillcolor=\"{}\"];", i, dot_escape(group), color);
+        }
+        out.push_str("  }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// GraphML; super nodes are nested graphs, and colors, groups, kinds and call
+/// sites are kept as data attributes
+fn render_graphml(view: &View) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (id, domain, kind) in [
+        ("label", "node", "string"),
+        ("group", "node", "string"),
+        ("color", "node", "string"),
+        ("super_node", "node", "boolean"),
+        ("kind", "edge", "string"),
+        ("call_sites", "edge", "int"),
+        ("feedback", "edge", "boolean"),
+    ] {
+        let _ = writeln!(out, "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>", id, domain, kind);
+    }
+    out.push_str("  <graph id=\"dependencies\" edgedefault=\"directed\">\n");
+
+    let node_element = |out: &mut String, i: usize, indent: &str| {
+        let node = &view.nodes[i];
+        let _ = writeln!(out, "{}<node id=\"n{}\">", indent, i);
+        let _ = writeln!(out, "{}  <data key=\"label\">{}</data>", indent, xml_escape(&node.label));
+        if let Some(group) = &node.group {
+            let _ = writeln!(out, "{}  <data key=\"group\">{}</data>", indent, xml_escape(group));
+        }
+        let _ = writeln!(out, "{}  <data key=\"color\">{}</data>", indent, node.color);
+        let _ = writeln!(out, "{}  <data key=\"super_node\">{}</data>", indent, node.super_node);
+        let _ = writeln!(out, "{}</node>", indent);
+    };
+
+    let mut clustered = vec![false; view.nodes.len()];
+    for (i, (label, members)) in view.clusters.iter().enumerate() {
+        let _ = writeln!(out, "    <node id=\"cluster{}\">", i);
+        let _ = writeln!(out, "      <data key=\"label\">{}</data>", xml_escape(label));
+        out.push_str("      <data key=\"super_node\">true</data>\n");
+        let _ = writeln!(out, "      <graph id=\"cluster{}:\" edgedefault=\"directed\">", i);
+        for &member in members {
+            clustered[member] = true;
+            node_element(&mut out, member, "        ");
+        }
+        out.push_str("      </graph>\n    </node>\n");
+    }
+    for i in (0..view.nodes.len()).filter(|i| !clustered[*i]) {
+        node_element(&mut out, i, "    ");
+    }
+
+    for edge in &view.edges {
+        let _ = writeln!(out, "    <edge source=\"n{}\" target=\"n{}\">", edge.from, edge.to);
+        let _ = writeln!(out, "      <data key=\"kind\">{}</data>", edge.kind);
+        let _ = writeln!(out, "      <data key=\"call_sites\">{}</data>", edge.call_sites);
+        let _ = writeln!(out, "      <data key=\"feedback\">{}</data>", edge.feedback);
+        out.push_str("    </edge>\n");
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+/// Mermaid flowchart; super nodes are subgraphs, color groups are classes
+fn render_mermaid(view: &View) -> String {
+    let mut out = String::from("flowchart LR\n");
+    let node_line = |out: &mut String, i: usize, indent: &str| {
+        let node = &view.nodes[i];
+        if node.super_node {
+            let _ = writeln!(out, "{}n{}[[\"{}\"]]", indent, i, mermaid_escape(&node.label));
+        } else {
+            let _ = writeln!(out, "{}n{}[\"{}\"]", indent, i, mermaid_escape(&node.label));
+        }
+    };
+
+    let mut clustered = vec![false; view.nodes.len()];
+    for (i, (label, members)) in view.clusters.iter().enumerate() {
+        let _ = writeln!(out, "  subgraph cluster{}[\"{}\"]", i, mermaid_escape(label));
+        for &member in members {
+            clustered[member] = true;
+            node_line(&mut out, member, "    ");
+        }
+        out.push_str("  end\n");
+    }
+    for i in (0..view.nodes.len()).filter(|i| !clustered[*i]) {
+        node_line(&mut out, i, "  ");
+    }
+
+    for edge in &view.edges {
+        let arrow = if edge.kind.is_call() { "-->" } else { "-.->" };
+        let _ = writeln!(out, "  n{} {} n{}", edge.from, arrow, edge.to);
+    }
+    let feedback: Vec<String> =
+        view.edges.iter().enumerate().filter(|(_, edge)| edge.feedback).map(|(i, _)| i.to_string()).collect();
+    if !feedback.is_empty() {
+        let _ = writeln!(out, "  linkStyle {} stroke:#d62728,stroke-width:2px", feedback.join(","));
+    }
+
+    for (i, (group, color)) in view.legend.iter().enumerate() {
+        let members: Vec<String> = (0..view.nodes.len())
+            .filter(|n| view.nodes[*n].group.as_ref() == Some(group))
+            .map(|n| format!("n{}", n))
+            .collect();
+        let _ = writeln!(out, "  %% {}", group);
+        let _ = writeln!(out, "  classDef group{} fill:{},stroke:#333", i, color);
+        let _ = writeln!(out, "  class {} group{}", members.join(","), i);
+    }
+    if view.nodes.iter().any(|node| node.color == MIXED_COLOR) {
+        let mixed: Vec<String> = (0..view.nodes.len())
+            .filter(|n| view.nodes[*n].color == MIXED_COLOR)
+            .map(|n| format!("n{}", n))
+            .collect();
+        let _ = writeln!(out, "  classDef mixed fill:{},stroke:#333", MIXED_COLOR);
+        let _ = writeln!(out, "  class {} mixed", mixed.join(","));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{batching, feedback};
+    use petgraph::algo::tarjan_scc;
+
+    /// An ID that needs escaping in every format
+    const ODD: &str = "fs:\"<&>\"";
+
+    /// `main -> sys:open -> x <-> y -> ODD`: one super node of two units
+    fn units() -> Vec<AtomicUnit> {
+        let unit = |id: &str, file: Option<&str>, calls: &[&str]| {
+            let deps = calls.iter().map(|callee| kernel_schema::Dependency::call(*callee)).collect();
+            let unit = AtomicUnit::new(id.into(), String::new(), deps, Vec::new());
+            match file {
+                Some(file) => unit.with_source_file(file),
+                None => unit,
+            }
+        };
+        vec![
+            unit("main", Some("init.c"), &["sys:open"]),
+            unit("sys:open", Some("kernel/sysfile.c"), &["x"]),
+            unit("x", Some("kernel/proc.c"), &["y"]),
+            unit("y", Some("kernel/proc.c"), &["x", ODD]),
+            unit(ODD, None, &[]),
+        ]
+    }
+
+    struct Fixture {
+        graph: DiGraph<String, DepEdge>,
+        sccs: Vec<Vec<NodeIndex>>,
+        node_to_scc: HashMap<NodeIndex, usize>,
+        feedback_sets: HashMap<usize, FeedbackArcSet>,
+    }
+
+    fn fixture() -> Fixture {
+        let units = units();
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<String, NodeIndex> =
+            units.iter().map(|unit| (unit.id.clone(), graph.add_node(unit.id.clone()))).collect();
+        for unit in &units {
+            for dep in &unit.dependencies {
+                graph.add_edge(nodes[&unit.id], nodes[&dep.target], DepEdge { kind: dep.kind, call_sites: 1 });
+            }
+        }
+        let sccs = tarjan_scc(&graph);
+        let (node_to_scc, _) = batching::condense(&graph, &sccs);
+        let feedback_sets = sccs
+            .iter()
+            .enumerate()
+            .filter(|(_, scc)| scc.len() > 1)
+            .map(|(i, scc)| (i, feedback::feedback_arc_set(&graph, scc)))
+            .collect();
+        Fixture { graph, sccs, node_to_scc, feedback_sets }
+    }
+
+    fn input(fixture: &Fixture, groups: HashMap<String, String>) -> ExportInput<'_> {
+        ExportInput {
+            graph: &fixture.graph,
+            sccs: &fixture.sccs,
+            node_to_scc: &fixture.node_to_scc,
+            feedback_sets: &fixture.feedback_sets,
+            groups,
+        }
+    }
+
+    fn render(format: ExportFormat, groups: HashMap<String, String>) -> String {
+        let fixture = fixture();
+        format.render(&units_view(&input(&fixture, groups), None, None).unwrap())
+    }
+
+    #[test]
+    fn super_nodes_are_clusters_in_every_format() {
+        let dot = render(ExportFormat::Dot, HashMap::new());
+        assert!(dot.contains("subgraph cluster_0 {\n    label=\"Super Node 1 (2 units)\";"), "{}", dot);
+        assert_eq!(dot.matches(" -> ").count(), 5);
+        assert!(dot.contains("color=\"#d62728\", penwidth=2"), "the feedback arc is highlighted");
+
+        let graphml = render(ExportFormat::GraphMl, HashMap::new());
+        assert!(graphml.contains("<graph id=\"cluster0:\" edgedefault=\"directed\">"), "{}", graphml);
+        assert_eq!(graphml.matches("<edge ").count(), 5);
+
+        let mermaid = render(ExportFormat::Mermaid, HashMap::new());
+        assert!(mermaid.contains("  subgraph cluster0[\"Super Node 1 (2 units)\"]\n"), "{}", mermaid);
+        assert!(mermaid.contains("linkStyle "), "the feedback arc is highlighted");
+    }
+
+    #[test]
+    fn odd_ids_are_escaped_in_every_format() {
+        // Units are numbered in ID order, so ODD is n0 and sys:open is n2
+        let dot = render(ExportFormat::Dot, HashMap::new());
+        assert!(dot.contains(r#"n0 [label="fs:\"<&>\"""#), "{}", dot);
+        assert!(dot.contains(r#"n2 [label="sys:open""#), "{}", dot);
+
+        let graphml = render(ExportFormat::GraphMl, HashMap::new());
+        assert!(graphml.contains("<data key=\"label\">fs:&quot;&lt;&amp;&gt;&quot;</data>"), "{}", graphml);
+        assert!(!graphml.contains("<&>"));
+
+        let mermaid = render(ExportFormat::Mermaid, HashMap::new());
+        assert!(mermaid.contains("n0[\"fs:#quot;#lt;#amp;#gt;#quot;\"]"), "{}", mermaid);
+        assert_eq!(mermaid_escape("#quot;"), "#35;quot;");
+    }
+
+    #[test]
+    fn export_root_limits_the_depth() {
+        let fixture = fixture();
+        let input = input(&fixture, HashMap::new());
+        let labels = |view: View| view.nodes.into_iter().map(|node| node.label).collect::<Vec<_>>();
+
+        assert_eq!(labels(units_view(&input, Some("main"), Some(1)).unwrap()), vec!["main", "sys:open"]);
+        assert_eq!(labels(units_view(&input, Some("x"), Some(1)).unwrap()), vec!["sys:open", "x", "y"]);
+        assert_eq!(units_view(&input, Some("main"), None).unwrap().nodes.len(), 5);
+        // In the SCC view the super node is one hop, whichever member it is entered by
+        let sccs = labels(sccs_view(&input, Some("sys:open"), Some(1)).unwrap());
+        assert_eq!(sccs, vec!["main", "sys:open", "Super Node (2 units): x, y"]);
+        assert!(units_view(&input, Some("nope"), Some(1)).is_err());
+
+        let path = std::env::temp_dir().join(format!("mapper-export-{}.dot", std::process::id()));
+        let written = export(&input, &path, GraphView::Units, Some("main"), Some(2)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, (3, 2));
+    }
+
+    #[test]
+    fn colors_follow_file_module_and_state() {
+        let units = units();
+        let by_file = unit_groups(&units, ColorBy::File, &HashMap::new());
+        assert_eq!(by_file["x"], "kernel/proc.c");
+        assert!(!by_file.contains_key(ODD), "units without a file have no group");
+        let by_module = unit_groups(&units, ColorBy::Module, &HashMap::new());
+        assert_eq!((by_module["main"].as_str(), by_module["sys:open"].as_str()), (".", "kernel"));
+        assert!(unit_groups(&units, ColorBy::None, &HashMap::new()).is_empty());
+
+        let dot = render(ExportFormat::Dot, by_module);
+        assert!(dot.contains("legend0 [label=\".\", fillcolor=\"#8dd3c7\"]"), "{}", dot);
+        assert!(dot.contains("legend1 [label=\"kernel\", fillcolor=\"#ffffb3\"]"), "{}", dot);
+        assert!(dot.contains("n4 [label=\"y\", fillcolor=\"#ffffb3\", tooltip=\"kernel\"]"), "{}", dot);
+
+        let path = std::env::temp_dir().join(format!("mapper-export-states-{}.json", std::process::id()));
+        fs::write(&path, r#"[{"atomic_unit_id": "x", "state": "COMPLETED"}, {"atomic_unit_id": "y", "state": "FAILED"}]"#)
+            .unwrap();
+        let states = load_states(&path).unwrap();
+        fs::write(&path, "\n").unwrap();
+        assert!(load_states(&path).unwrap().is_empty(), "sqlite3 prints nothing for no rows");
+        fs::write(&path, "{}").unwrap();
+        assert!(load_states(&path).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let by_state = unit_groups(&units, ColorBy::State, &states);
+        assert_eq!((by_state["x"].as_str(), by_state["main"].as_str()), ("COMPLETED", "UNTRACKED"));
+        let mermaid = render(ExportFormat::Mermaid, by_state);
+        assert!(mermaid.contains("classDef group0 fill:#a5d6a7,stroke:#333\n  class n3 group0"), "{}", mermaid);
+        assert!(mermaid.contains("classDef group1 fill:#ef9a9a,stroke:#333\n  class n4 group1"), "{}", mermaid);
+        assert!(mermaid.contains("fill:#ffffff,stroke:#333\n  class n0,n1,n2 group2"), "{}", mermaid);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::DepEdge;
+use kernel_schema::{FasMethod, FeedbackArc, FeedbackArcSet};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+
+/// Largest super node solved exactly; the DP is O(2^n · n²)
+pub const EXACT_LIMIT: usize = 16;
+
+/// Minimum-weight feedback arc set of one SCC, weighted by call sites.
+///
+/// Finding a minimum feedback arc set is the same as finding the vertex order
+/// with the lightest set of "backward" edges, here a unit placed before one of
+/// its dependencies. SCCs of up to [`EXACT_LIMIT`] units are solved exactly by
+/// dynamic programming over subsets; larger ones use the Eades–Lin–Smyth
+/// heuristic. Self-loops (recursion) never need breaking and are ignored.
+pub fn feedback_arc_set(graph: &DiGraph<String, DepEdge>, scc: &[NodeIndex]) -> FeedbackArcSet {
+    // Local indices in ID order, so results do not depend on SCC discovery order
+    let mut nodes = scc.to_vec();
+    nodes.sort_by(|a, b| graph[*a].cmp(&graph[*b]));
+    let local: HashMap<NodeIndex, usize> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+    // (dependent, dependency, call sites)
+    let mut edges = Vec::new();
+    for &node in &nodes {
+        for edge in graph.edges(node) {
+            if let Some(&to) = local.get(&edge.target())
+                && edge.target() != node
+            {
+                edges.push((local[&node], to, edge.weight().call_sites));
+            }
+        }
+    }
+
+    let (method, order) = if nodes.len() <= EXACT_LIMIT {
+        (FasMethod::Exact, exact_order(nodes.len(), &edges))
+    } else {
+        (FasMethod::EadesLinSmyth, eades_lin_smyth_order(nodes.len(), &edges))
+    };
+
+    let mut position = vec![0; nodes.len()];
+    for (pos, &v) in order.iter().enumerate() {
+        position[v] = pos;
+    }
+    let mut arcs: Vec<FeedbackArc> = edges
+        .iter()
+        .filter(|(from, to, _)| position[*from] < position[*to])
+        .map(|&(from, to, call_sites)| FeedbackArc {
+            from: graph[nodes[from]].clone(),
+            to: graph[nodes[to]].clone(),
+            call_sites,
+        })
+        .collect();
+    arcs.sort_by(|a, b| a.call_sites.cmp(&b.call_sites).then_with(|| (&a.from, &a.to).cmp(&(&b.from, &b.to))));
+
+    FeedbackArcSet {
+        method,
+        total_call_sites: arcs.iter().map(|a| a.call_sites).sum(),
+        arcs,
+        order: order.iter().map(|&v| graph[nodes[v]].clone()).collect(),
+    }
+}
+
+/// Dependencies-first order minimizing the weight of edges `u -> v` with `u`
+/// placed before `v`. `best[S]` is the cheapest way to order the set `S` as a
+/// prefix; appending `v` costs the edges into `v` from units already in `S`.
+fn exact_order(n: usize, edges: &[(usize, usize, usize)]) -> Vec<usize> {
+    let mut incoming: Vec<Vec<(usize, u64)>> = vec![Vec::new(); n];
+    for &(from, to, weight) in edges {
+        incoming[to].push((from, weight as u64));
+    }
+
+    let full = 1usize << n;
+    let mut best = vec![u64::MAX; full];
+    let mut last = vec![0usize; full];
+    best[0] = 0;
+    for set in 0..full {
+        if best[set] == u64::MAX {
+            continue;
+        }
+        for v in (0..n).filter(|v| set & (1 << v) == 0) {
+            let cost: u64 = incoming[v].iter().filter(|(u, _)| set & (1 << u) != 0).map(|(_, w)| w).sum();
+            let next = set | (1 << v);
+            if best[set] + cost < best[next] {
+                best[next] = best[set] + cost;
+                last[next] = v;
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(n);
+    let mut set = full - 1;
+    while set != 0 {
+        order.push(last[set]);
+        set &= !(1 << last[set]);
+    }
+    order.reverse();
+    order
+}
+
+/// Eades–Lin–Smyth greedy ordering, weighted. Works on the "must come before"
+/// direction (dependency before dependent): sinks go to the back, sources to the
+/// front, and otherwise the vertex with the largest outgoing minus incoming
+/// weight is placed next.
+fn eades_lin_smyth_order(n: usize, edges: &[(usize, usize, usize)]) -> Vec<usize> {
+    // after[v]: dependents of v, which should follow it; before[v]: its dependencies
+    let mut after: Vec<Vec<(usize, usize)>> = vec![Vec::new(); n];
+    let mut before: Vec<Vec<(usize, usize)>> = vec![Vec::new(); n];
+    for &(dependent, dependency, weight) in edges {
+        after[dependency].push((dependent, weight));
+        before[dependent].push((dependency, weight));
+    }
+
+    let mut remaining = vec![true; n];
+    let mut out_weight: Vec<usize> = after.iter().map(|e| e.iter().map(|(_, w)| w).sum()).collect();
+    let mut in_weight: Vec<usize> = before.iter().map(|e| e.iter().map(|(_, w)| w).sum()).collect();
+    let mut front = Vec::with_capacity(n);
+    let mut back = Vec::new();
+    let mut left = n;
+
+    let remove = |v: usize, remaining: &mut [bool], out_weight: &mut [usize], in_weight: &mut [usize]| {
+        remaining[v] = false;
+        for &(u, w) in &after[v] {
+            in_weight[u] -= w;
+        }
+        for &(u, w) in &before[v] {
+            out_weight[u] -= w;
+        }
+    };
+
+    while left > 0 {
+        let mut progressed = true;
+        while progressed {
+            progressed = false;
+            for v in 0..n {
+                if remaining[v] && out_weight[v] == 0 {
+                    back.push(v);
+                    remove(v, &mut remaining, &mut out_weight, &mut in_weight);
+                    left -= 1;
+                    progressed = true;
+                } else if remaining[v] && in_weight[v] == 0 {
+                    front.push(v);
+                    remove(v, &mut remaining, &mut out_weight, &mut in_weight);
+                    left -= 1;
+                    progressed = true;
+                }
+            }
+        }
+        if left == 0 {
+            break;
+        }
+        let next = (0..n)
+            .filter(|&v| remaining[v])
+            .max_by_key(|&v| (out_weight[v] as i64 - in_weight[v] as i64, std::cmp::Reverse(v)));
+        if let Some(v) = next {
+            front.push(v);
+            remove(v, &mut remaining, &mut out_weight, &mut in_weight);
+            left -= 1;
+        }
+    }
+
+    back.reverse();
+    front.extend(back);
+    front
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::DependencyKind;
+    use petgraph::algo::is_cyclic_directed;
+
+    /// A graph of units `u00`, `u01`, ... with weighted `(dependent, dependency, call sites)` edges
+    fn graph(n: usize, edges: &[(usize, usize, usize)]) -> (DiGraph<String, DepEdge>, Vec<NodeIndex>) {
+        let mut graph = DiGraph::new();
+        let nodes: Vec<NodeIndex> = (0..n).map(|i| graph.add_node(format!("u{:02}", i))).collect();
+        for &(from, to, call_sites) in edges {
+            graph.add_edge(nodes[from], nodes[to], DepEdge { kind: DependencyKind::DirectCall, call_sites });
+        }
+        (graph, nodes)
+    }
+
+    /// A ring `0 -> 1 -> ... -> 0` plus pseudo-random chords, strongly connected
+    fn random_scc(n: usize, chords: usize, seed: u64) -> Vec<(usize, usize, usize)> {
+        let mut state = seed;
+        let mut next = |bound: usize| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % bound as u64) as usize
+        };
+        let mut edges: Vec<(usize, usize, usize)> = (0..n).map(|i| (i, (i + 1) % n, 1 + next(5))).collect();
+        for _ in 0..chords {
+            let (from, to) = (next(n), next(n));
+            if from != to && !edges.iter().any(|e| (e.0, e.1) == (from, to)) {
+                edges.push((from, to, 1 + next(5)));
+            }
+        }
+        edges
+    }
+
+    /// The graph with the returned arcs removed has no cycles, and every
+    /// remaining edge has its dependency earlier in the order
+    fn assert_breaks_cycles(graph: &DiGraph<String, DepEdge>, fas: &FeedbackArcSet) {
+        let removed: Vec<(&str, &str)> = fas.arcs.iter().map(|a| (a.from.as_str(), a.to.as_str())).collect();
+        let position: HashMap<&str, usize> = fas.order.iter().enumerate().map(|(i, id)| (id.as_str(), i)).collect();
+        let acyclic = graph.filter_map(
+            |_, id| Some(id.clone()),
+            |edge, weight| {
+                let (from, to) = graph.edge_endpoints(edge).unwrap();
+                let pair = (graph[from].as_str(), graph[to].as_str());
+                (!removed.contains(&pair) && from != to).then_some(*weight)
+            },
+        );
+        assert!(!is_cyclic_directed(&acyclic), "removing {:?} leaves a cycle", removed);
+        for edge in acyclic.edge_indices() {
+            let (from, to) = acyclic.edge_endpoints(edge).unwrap();
+            assert!(position[acyclic[to].as_str()] < position[acyclic[from].as_str()]);
+        }
+    }
+
+    /// Cheapest backward-edge weight over every ordering, by brute force
+    fn brute_force_minimum(n: usize, edges: &[(usize, usize, usize)]) -> usize {
+        fn permute(order: &mut Vec<usize>, k: usize, edges: &[(usize, usize, usize)], best: &mut usize) {
+            if k == order.len() {
+                let position: Vec<usize> = (0..order.len()).map(|v| order.iter().position(|&u| u == v).unwrap()).collect();
+                let cost = edges.iter().filter(|(from, to, _)| position[*from] < position[*to]).map(|e| e.2).sum();
+                *best = (*best).min(cost);
+                return;
+            }
+            for i in k..order.len() {
+                order.swap(k, i);
+                permute(order, k + 1, edges, best);
+                order.swap(k, i);
+            }
+        }
+        let mut best = usize::MAX;
+        permute(&mut (0..n).collect(), 0, edges, &mut best);
+        best
+    }
+
+    #[test]
+    fn two_cycle_breaks_the_lighter_edge() {
+        let (graph, nodes) = graph(2, &[(0, 1, 3), (1, 0, 1)]);
+        let fas = feedback_arc_set(&graph, &nodes);
+        assert_eq!(fas.method, FasMethod::Exact);
+        assert_eq!(fas.arcs, vec![FeedbackArc { from: "u01".into(), to: "u00".into(), call_sites: 1 }]);
+        assert_eq!(fas.order, vec!["u01", "u00"]);
+    }
+
+    #[test]
+    fn shared_edge_breaks_both_cycles_at_once() {
+        // u00 <-> u01 and u00 -> u01 -> u02 -> u00 share u00 -> u01
+        let (graph, nodes) = graph(3, &[(0, 1, 2), (1, 0, 5), (1, 2, 5), (2, 0, 5)]);
+        let fas = feedback_arc_set(&graph, &nodes);
+        assert_eq!(fas.total_call_sites, 2);
+        assert_eq!(fas.arcs.len(), 1);
+        assert_eq!((fas.arcs[0].from.as_str(), fas.arcs[0].to.as_str()), ("u00", "u01"));
+        assert_breaks_cycles(&graph, &fas);
+    }
+
+    #[test]
+    fn self_loops_are_not_broken() {
+        let (graph, nodes) = graph(2, &[(0, 0, 4), (0, 1, 1), (1, 0, 2)]);
+        let fas = feedback_arc_set(&graph, &nodes);
+        assert_eq!(fas.total_call_sites, 1);
+        assert!(fas.arcs.iter().all(|arc| arc.from != arc.to));
+    }
+
+    #[test]
+    fn exact_method_matches_brute_force() {
+        for seed in 0..30 {
+            let n = 3 + (seed as usize % 5);
+            let edges = random_scc(n, n * 2, seed);
+            let (graph, nodes) = graph(n, &edges);
+            let fas = feedback_arc_set(&graph, &nodes);
+            assert_eq!(fas.total_call_sites, brute_force_minimum(n, &edges), "seed {}", seed);
+            assert_breaks_cycles(&graph, &fas);
+        }
+    }
+
+    #[test]
+    fn heuristic_breaks_every_cycle() {
+        for seed in 0..10 {
+            let n = EXACT_LIMIT + 1 + seed as usize * 3;
+            let edges = random_scc(n, n * 3, seed);
+            let (graph, nodes) = graph(n, &edges);
+            let fas = feedback_arc_set(&graph, &nodes);
+            assert_eq!(fas.method, FasMethod::EadesLinSmyth);
+            assert_eq!(fas.order.len(), n);
+            assert_breaks_cycles(&graph, &fas);
+        }
+    }
+
+    #[test]
+    fn method_switches_above_the_exact_limit() {
+        for (n, method) in [(EXACT_LIMIT, FasMethod::Exact), (EXACT_LIMIT + 1, FasMethod::EadesLinSmyth)] {
+            let ring: Vec<(usize, usize, usize)> = (0..n).map(|i| (i, (i + 1) % n, 1)).collect();
+            let (graph, nodes) = graph(n, &ring);
+            let fas = feedback_arc_set(&graph, &nodes);
+            assert_eq!(fas.method, method, "{} units", n);
+            // Either way, a single ring needs exactly one edge removed
+            assert_eq!(fas.arcs.len(), 1, "{} units", n);
+            assert_breaks_cycles(&graph, &fas);
+        }
+    }
+}
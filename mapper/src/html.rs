@@ -0,0 +1,100 @@
+use crate::DepEdge;
+use anyhow::{Context, Result};
+use kernel_schema::{BuildMetadata, BuildOrder, BuildOrderBatch, CycleAnalysis, UnitKind, UnitsFile};
+use petgraph::graph::DiGraph;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Page layout, styles and script; the plan is embedded as JSON, so the file
+/// needs no network access
+const TEMPLATE: &str = include_str!("report.html");
+
+#[derive(Debug, Serialize)]
+struct UnitData<'a> {
+    id: &'a str,
+    kind: UnitKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    code: &'a str,
+    /// Required headers with the symbol table's definitions inlined
+    headers: Vec<String>,
+    batch: usize,
+    /// Index into the cycle analysis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    super_node: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportData<'a> {
+    metadata: &'a BuildMetadata,
+    levels: usize,
+    batches: &'a [BuildOrderBatch],
+    cycle_analysis: &'a [CycleAnalysis],
+    /// In build order
+    units: Vec<UnitData<'a>>,
+    /// `[from, to, is_call]`, indices into `units`
+    edges: Vec<(usize, usize, bool)>,
+}
+
+/// Write a self-contained HTML page for reviewing `build_order`: batches,
+/// super nodes with their cycle analysis, a zoomable graph and each unit's code
+pub fn write(
+    path: &Path,
+    title: &str,
+    graph: &DiGraph<String, DepEdge>,
+    units_file: &UnitsFile,
+    build_order: &BuildOrder,
+    cycle_analysis: &[CycleAnalysis],
+) -> Result<()> {
+    let by_id: HashMap<&str, _> = units_file.units.iter().map(|unit| (unit.id.as_str(), unit)).collect();
+    let super_node_of: HashMap<&str, usize> = cycle_analysis
+        .iter()
+        .enumerate()
+        .flat_map(|(i, analysis)| analysis.super_node.iter().map(move |id| (id.as_str(), i)))
+        .collect();
+
+    let mut units = Vec::new();
+    for (batch_index, batch) in build_order.batches.iter().enumerate() {
+        for id in &batch.units {
+            let unit = by_id[id.as_str()];
+            units.push(UnitData {
+                id: &unit.id,
+                kind: unit.kind,
+                file: unit.source_file.as_deref(),
+                code: &unit.code,
+                headers: units_file.inline_unit(unit).required_headers,
+                batch: batch_index,
+                super_node: super_node_of.get(id.as_str()).copied(),
+            });
+        }
+    }
+    let index: HashMap<&str, usize> = units.iter().enumerate().map(|(i, unit)| (unit.id, i)).collect();
+    let mut edges: Vec<(usize, usize, bool)> = graph
+        .edge_indices()
+        .filter_map(|edge| {
+            let (from, to) = graph.edge_endpoints(edge)?;
+            Some((index[graph[from].as_str()], index[graph[to].as_str()], graph[edge].kind.is_call()))
+        })
+        .collect();
+    edges.sort_unstable();
+
+    let data = ReportData {
+        metadata: &build_order.metadata,
+        levels: build_order.batches.iter().filter_map(|batch| batch.level).max().map_or(0, |max| max + 1),
+        batches: &build_order.batches,
+        cycle_analysis,
+        units,
+        edges,
+    };
+    // `<` only occurs inside JSON strings, where the escape keeps C code such
+    // as "</script>" from ending the script element early
+    let json = serde_json::to_string(&data)?.replace('<', "\\u003c");
+    let page = TEMPLATE.replace("__TITLE__", &escape(title)).replace("__DATA__", &json);
+    fs::write(path, page).with_context(|| format!("Failed to write HTML report {:?}", path))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,156 @@
+use crate::DepEdge;
+use anyhow::{bail, Result};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// A unit that must be re-verified because something it depends on changed
+#[derive(Debug, Serialize)]
+pub struct AffectedUnit {
+    pub id: String,
+    /// Dependency hops to the nearest changed unit (0 for changed units)
+    pub distance: usize,
+}
+
+/// Changed units and everything that transitively depends on them
+#[derive(Debug, Serialize)]
+pub struct Impact {
+    pub changed: Vec<String>,
+    /// Sorted by distance, then ID
+    pub affected: Vec<AffectedUnit>,
+    pub total_units: usize,
+}
+
+/// Restrict `graph` to the changed units and their transitive dependents,
+/// returning the restricted graph and the impact report
+pub fn restrict_to_dependents(
+    graph: &DiGraph<String, DepEdge>,
+    changed: &[String],
+) -> Result<(DiGraph<String, DepEdge>, Impact)> {
+    let nodes: HashMap<&str, NodeIndex> = graph.node_indices().map(|idx| (graph[idx].as_str(), idx)).collect();
+    let unknown: Vec<&str> = changed.iter().map(String::as_str).filter(|id| !nodes.contains_key(id)).collect();
+    if !unknown.is_empty() {
+        bail!("Changed unit(s) not in the dependency graph: {}", unknown.join(", "));
+    }
+
+    // Breadth-first along incoming edges: a unit's dependents point at it
+    let mut distance: HashMap<NodeIndex, usize> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for id in changed {
+        let idx = nodes[id.as_str()];
+        if distance.insert(idx, 0).is_none() {
+            queue.push_back(idx);
+        }
+    }
+    while let Some(node) = queue.pop_front() {
+        let next = distance[&node] + 1;
+        for dependent in graph.neighbors_directed(node, Direction::Incoming) {
+            distance.entry(dependent).or_insert_with(|| {
+                queue.push_back(dependent);
+                next
+            });
+        }
+    }
+
+    let mut affected: Vec<AffectedUnit> = distance
+        .iter()
+        .map(|(idx, distance)| AffectedUnit { id: graph[*idx].clone(), distance: *distance })
+        .collect();
+    affected.sort_by(|a, b| a.distance.cmp(&b.distance).then_with(|| a.id.cmp(&b.id)));
+
+    let restricted = graph.filter_map(
+        |idx, id| distance.contains_key(&idx).then(|| id.clone()),
+        |_, edge| Some(*edge),
+    );
+    let impact = Impact { changed: changed.to_vec(), affected, total_units: graph.node_count() };
+    Ok((restricted, impact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::DependencyKind;
+
+    /// `main -> sched <-> yield -> swtch`, `yield -> memmove`, and `main -> exit`
+    fn graph() -> DiGraph<String, DepEdge> {
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<&str, NodeIndex> = ["main", "sched", "yield", "swtch", "memmove", "exit"]
+            .into_iter()
+            .map(|id| (id, graph.add_node(id.to_string())))
+            .collect();
+        let edges = [
+            ("main", "sched"),
+            ("sched", "yield"),
+            ("yield", "sched"),
+            ("yield", "swtch"),
+            ("yield", "memmove"),
+            ("main", "exit"),
+        ];
+        for (from, to) in edges {
+            graph.add_edge(nodes[from], nodes[to], DepEdge { kind: DependencyKind::DirectCall, call_sites: 1 });
+        }
+        graph
+    }
+
+    fn restrict(changed: &[&str]) -> (DiGraph<String, DepEdge>, Impact) {
+        let changed: Vec<String> = changed.iter().map(|id| id.to_string()).collect();
+        restrict_to_dependents(&graph(), &changed).unwrap()
+    }
+
+    fn affected(impact: &Impact) -> Vec<(&str, usize)> {
+        impact.affected.iter().map(|unit| (unit.id.as_str(), unit.distance)).collect()
+    }
+
+    fn edges(graph: &DiGraph<String, DepEdge>) -> Vec<(&str, &str)> {
+        let mut edges: Vec<(&str, &str)> = graph
+            .edge_indices()
+            .map(|edge| {
+                let (from, to) = graph.edge_endpoints(edge).unwrap();
+                (graph[from].as_str(), graph[to].as_str())
+            })
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    #[test]
+    fn dependents_are_found_through_a_cycle() {
+        let (restricted, impact) = restrict(&["swtch"]);
+        assert_eq!(affected(&impact), vec![("swtch", 0), ("yield", 1), ("sched", 2), ("main", 3)]);
+        assert_eq!((impact.changed.clone(), impact.total_units), (vec!["swtch".to_string()], 6));
+
+        // Only the edges between affected units are kept; exit and memmove stay
+        // out of the plan even though main and yield call them
+        assert_eq!(restricted.node_count(), 4);
+        assert_eq!(edges(&restricted), vec![("main", "sched"), ("sched", "yield"), ("yield", "sched"), ("yield", "swtch")]);
+    }
+
+    #[test]
+    fn the_nearest_change_sets_the_distance() {
+        let (_, impact) = restrict(&["swtch", "sched", "swtch"]);
+        assert_eq!(affected(&impact), vec![("sched", 0), ("swtch", 0), ("main", 1), ("yield", 1)]);
+    }
+
+    #[test]
+    fn a_changed_leaf_brings_in_only_its_callers() {
+        let (restricted, impact) = restrict(&["memmove"]);
+        assert_eq!(affected(&impact), vec![("memmove", 0), ("yield", 1), ("sched", 2), ("main", 3)]);
+        // The other leaves, swtch and exit, did not change and are not re-planned
+        assert_eq!(
+            edges(&restricted),
+            vec![("main", "sched"), ("sched", "yield"), ("yield", "memmove"), ("yield", "sched")]
+        );
+
+        let (restricted, impact) = restrict(&["exit"]);
+        assert_eq!(affected(&impact), vec![("exit", 0), ("main", 1)]);
+        assert_eq!(edges(&restricted), vec![("main", "exit")]);
+    }
+
+    #[test]
+    fn unknown_changed_units_are_an_error() {
+        let changed = vec!["swtch".to_string(), "memmov".to_string(), "mian".to_string()];
+        let error = restrict_to_dependents(&graph(), &changed).unwrap_err();
+        assert_eq!(error.to_string(), "Changed unit(s) not in the dependency graph: memmov, mian");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::reachability::matches_pattern;
+use crate::DepEdge;
+use anyhow::{bail, Context, Result};
+use kernel_schema::{AtomicUnit, DependencyKind, SourceLocation};
+use petgraph::graph::{DiGraph, NodeIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// A pair of modules that must not depend on each other in this direction
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DenyRule {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Allowed dependency directions between modules, read from JSON or YAML
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayeringRules {
+    /// Module name to its members: source path prefixes ending in `/`, source
+    /// path `*` patterns, or `unit:` followed by a unit ID pattern
+    pub modules: BTreeMap<String, Vec<String>>,
+    /// Module names, lowest first: each may depend only on itself and the modules below it
+    #[serde(default)]
+    pub layers: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<DenyRule>,
+}
+
+impl LayeringRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read layering rules {:?}", path))?;
+        let rules: Self =
+            serde_yaml::from_str(&text).with_context(|| format!("Failed to parse layering rules {:?}", path))?;
+        let mut unknown: Vec<&str> = rules
+            .layers
+            .iter()
+            .chain(rules.deny.iter().flat_map(|rule| [&rule.from, &rule.to]))
+            .map(String::as_str)
+            .filter(|name| !rules.modules.contains_key(*name))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort_unstable();
+            unknown.dedup();
+            bail!("Layering rules {:?} name undefined module(s): {}", path, unknown.join(", "));
+        }
+        Ok(rules)
+    }
+
+    /// Every module `unit` belongs to
+    fn modules_of(&self, unit: &AtomicUnit) -> Vec<&str> {
+        let member = |pattern: &String| match pattern.strip_prefix("unit:") {
+            Some(id_pattern) => matches_pattern(id_pattern, &unit.id),
+            None => unit.source_file.as_deref().is_some_and(|file| {
+                if pattern.ends_with('/') { file.starts_with(pattern.as_str()) } else { matches_pattern(pattern, file) }
+            }),
+        };
+        self.modules.iter().filter(|(_, patterns)| patterns.iter().any(member)).map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Rules a dependency of module `from` on module `to` breaks, with their reasons
+    fn broken(&self, from: &str, to: &str) -> Vec<(String, Option<String>)> {
+        let mut broken = Vec::new();
+        let layer = |module: &str| self.layers.iter().position(|name| name == module);
+        if let (Some(from_layer), Some(to_layer)) = (layer(from), layer(to))
+            && from_layer < to_layer
+        {
+            broken.push((format!("layer {} may not depend on higher layer {}", from, to), None));
+        }
+        for rule in self.deny.iter().filter(|rule| rule.from == from && rule.to == to) {
+            broken.push((format!("{} may not depend on {}", from, to), rule.reason.clone()));
+        }
+        broken
+    }
+}
+
+/// A dependency that crosses module boundaries the wrong way
+#[derive(Debug, Serialize)]
+pub struct Violation {
+    pub caller: String,
+    pub callee: String,
+    pub from_module: String,
+    pub to_module: String,
+    pub rule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub kinds: BTreeSet<DependencyKind>,
+    pub call_sites: usize,
+    /// First offending reference, when the slicer recorded it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+    /// Not in the baseline
+    pub new: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct ViolationKey {
+    pub caller: String,
+    pub callee: String,
+    pub rule: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayeringReport {
+    pub violations: Vec<Violation>,
+    pub new_violations: usize,
+    /// Baseline violations that no longer occur
+    pub fixed: Vec<ViolationKey>,
+}
+
+/// Violations accepted earlier: the `violations` of a previous layering report
+pub fn load_baseline(path: &Path) -> Result<HashSet<ViolationKey>> {
+    #[derive(Deserialize)]
+    struct Baseline {
+        violations: Vec<ViolationKey>,
+    }
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read layering baseline {:?}", path))?;
+    let baseline: Baseline =
+        serde_yaml::from_str(&text).with_context(|| format!("Failed to parse layering baseline {:?}", path))?;
+    Ok(baseline.violations.into_iter().collect())
+}
+
+/// Check every dependency `graph` turned into an edge (of the followed `edge_kinds`) against `rules`
+pub fn check(
+    graph: &DiGraph<String, DepEdge>,
+    units: &[AtomicUnit],
+    edge_kinds: &[DependencyKind],
+    rules: &LayeringRules,
+    baseline: &HashSet<ViolationKey>,
+) -> LayeringReport {
+    let nodes: HashMap<&str, NodeIndex> = graph.node_indices().map(|idx| (graph[idx].as_str(), idx)).collect();
+    let modules: HashMap<&str, Vec<&str>> = units
+        .iter()
+        .filter(|unit| nodes.contains_key(unit.id.as_str()))
+        .map(|unit| (unit.id.as_str(), rules.modules_of(unit)))
+        .collect();
+
+    let mut found: BTreeMap<(String, String, String), Violation> = BTreeMap::new();
+    for unit in units.iter().filter(|unit| nodes.contains_key(unit.id.as_str())) {
+        for dep in unit.dependencies.iter().filter(|dep| edge_kinds.contains(&dep.kind)) {
+            let Some((callee, to_idx)) = dep.resolve_with(|id| nodes.get_key_value(id)) else { continue };
+            if *callee == unit.id || graph.find_edge(nodes[unit.id.as_str()], *to_idx).is_none() {
+                continue;
+            }
+            for from in &modules[unit.id.as_str()] {
+                for to in modules[callee].iter().filter(|to| !modules[unit.id.as_str()].contains(to)) {
+                    for (rule, reason) in rules.broken(from, to) {
+                        let key = (unit.id.clone(), callee.to_string(), rule.clone());
+                        let violation = found.entry(key).or_insert_with(|| Violation {
+                            caller: unit.id.clone(),
+                            callee: callee.to_string(),
+                            from_module: from.to_string(),
+                            to_module: to.to_string(),
+                            rule,
+                            reason,
+                            kinds: BTreeSet::new(),
+                            call_sites: 0,
+                            location: None,
+                            new: false,
+                        });
+                        violation.kinds.insert(dep.kind);
+                        violation.call_sites += dep.call_sites;
+                        if violation.location.is_none() {
+                            violation.location = dep.location.clone();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut violations: Vec<Violation> = found.into_values().collect();
+    let mut seen = HashSet::new();
+    for violation in &mut violations {
+        let key = ViolationKey {
+            caller: violation.caller.clone(),
+            callee: violation.callee.clone(),
+            rule: violation.rule.clone(),
+        };
+        violation.new = !baseline.contains(&key);
+        seen.insert(key);
+    }
+    let mut fixed: Vec<ViolationKey> = baseline.iter().filter(|key| !seen.contains(*key)).cloned().collect();
+    fixed.sort_by(|a, b| (&a.caller, &a.callee, &a.rule).cmp(&(&b.caller, &b.callee, &b.rule)));
+
+    LayeringReport {
+        new_violations: violations.iter().filter(|v| v.new).count(),
+        violations,
+        fixed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::Dependency;
+
+    const RULES: &str = "
+modules:
+  lib: [lib/]
+  kernel: [kernel/]
+  fs: ['kernel/fs*', 'unit:bio_*']
+  drivers: [drivers/]
+layers: [lib, kernel, drivers]
+deny:
+  - {from: fs, to: drivers, reason: go through the device switch}
+";
+
+    fn rules() -> LayeringRules {
+        serde_yaml::from_str(RULES).unwrap()
+    }
+
+    fn unit(id: &str, file: &str, calls: &[(&str, usize)]) -> AtomicUnit {
+        let deps = calls
+            .iter()
+            .map(|(callee, line)| {
+                let mut dep = Dependency::call(*callee);
+                dep.location = Some(SourceLocation { file: file.to_string(), line: *line, column: 5 });
+                dep
+            })
+            .collect();
+        AtomicUnit::new(id.into(), String::new(), deps, Vec::new()).with_source_file(file)
+    }
+
+    /// The dependency graph of `units`, one edge per resolved call
+    fn graph(units: &[AtomicUnit]) -> DiGraph<String, DepEdge> {
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<&str, NodeIndex> =
+            units.iter().map(|unit| (unit.id.as_str(), graph.add_node(unit.id.clone()))).collect();
+        for unit in units {
+            for dep in &unit.dependencies {
+                if let Some(to) = nodes.get(dep.target.as_str()) {
+                    graph.add_edge(nodes[unit.id.as_str()], *to, DepEdge { kind: dep.kind, call_sites: dep.call_sites });
+                }
+            }
+        }
+        graph
+    }
+
+    /// `memmove` in lib, `fork` and `fs_open` in the kernel (the latter also in
+    /// fs), `bio_read` in fs by ID, and `uart_putc` in drivers
+    fn program() -> Vec<AtomicUnit> {
+        vec![
+            unit("memmove", "lib/string.c", &[]),
+            unit("fork", "kernel/proc.c", &[("memmove", 10), ("uart_putc", 12)]),
+            unit("fs_open", "kernel/fsopen.c", &[("memmove", 20), ("uart_putc", 21)]),
+            unit("bio_read", "disk/bio.c", &[("fork", 30)]),
+            unit("uart_putc", "drivers/uart.c", &[("memmove", 40), ("fork", 41)]),
+        ]
+    }
+
+    fn run(units: &[AtomicUnit], baseline: &HashSet<ViolationKey>) -> LayeringReport {
+        check(&graph(units), units, &DependencyKind::ALL, &rules(), baseline)
+    }
+
+    #[test]
+    fn units_belong_to_every_module_they_match() {
+        let rules = rules();
+        let modules = |unit: AtomicUnit| rules.modules_of(&unit);
+        assert_eq!(modules(unit("memmove", "lib/string.c", &[])), vec!["lib"]);
+        assert_eq!(modules(unit("fs_open", "kernel/fsopen.c", &[])), vec!["fs", "kernel"]);
+        assert_eq!(modules(unit("bio_read", "disk/bio.c", &[])), vec!["fs"]);
+        // A prefix must match from the start of the path
+        assert!(modules(unit("x", "src/lib/x.c", &[])).is_empty());
+        assert!(modules(AtomicUnit::new("y".into(), String::new(), Vec::new(), Vec::new())).is_empty());
+    }
+
+    #[test]
+    fn only_upward_and_denied_dependencies_are_broken() {
+        let rules = rules();
+        assert!(rules.broken("kernel", "lib").is_empty(), "a lower layer is allowed");
+        assert!(rules.broken("kernel", "fs").is_empty(), "fs has no layer");
+        assert_eq!(
+            rules.broken("kernel", "drivers"),
+            vec![("layer kernel may not depend on higher layer drivers".to_string(), None)]
+        );
+        assert_eq!(
+            rules.broken("fs", "drivers"),
+            vec![("fs may not depend on drivers".to_string(), Some("go through the device switch".to_string()))]
+        );
+    }
+
+    #[test]
+    fn violations_name_caller_callee_and_location() {
+        let report = run(&program(), &HashSet::new());
+        let found: Vec<(&str, &str, &str, &str, usize)> = report
+            .violations
+            .iter()
+            .map(|v| {
+                let line = v.location.as_ref().unwrap().line;
+                (v.caller.as_str(), v.callee.as_str(), v.from_module.as_str(), v.to_module.as_str(), line)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("fork", "uart_putc", "kernel", "drivers", 12),
+                ("fs_open", "uart_putc", "fs", "drivers", 21),
+                ("fs_open", "uart_putc", "kernel", "drivers", 21),
+            ]
+        );
+        assert_eq!(report.violations[1].reason.as_deref(), Some("go through the device switch"));
+        assert_eq!(report.violations[0].kinds, BTreeSet::from([DependencyKind::DirectCall]));
+        assert_eq!(report.new_violations, 3);
+        assert!(report.fixed.is_empty());
+    }
+
+    #[test]
+    fn dependencies_without_an_edge_are_not_checked() {
+        let units = program();
+        let calls_only = check(&graph(&units), &units, &[DependencyKind::TypeUse], &rules(), &HashSet::new());
+        assert!(calls_only.violations.is_empty());
+
+        let mut graph = graph(&units);
+        graph.clear_edges();
+        assert!(check(&graph, &units, &DependencyKind::ALL, &rules(), &HashSet::new()).violations.is_empty());
+    }
+
+    #[test]
+    fn baseline_splits_new_from_fixed_violations() {
+        let key = |caller: &str, callee: &str, rule: &str| ViolationKey {
+            caller: caller.into(),
+            callee: callee.into(),
+            rule: rule.into(),
+        };
+        let accepted = key("fork", "uart_putc", "layer kernel may not depend on higher layer drivers");
+        let gone = key("exec", "uart_putc", "layer kernel may not depend on higher layer drivers");
+        let report = run(&program(), &HashSet::from([accepted, gone.clone()]));
+
+        let new: Vec<(&str, bool)> = report.violations.iter().map(|v| (v.caller.as_str(), v.new)).collect();
+        assert_eq!(new, vec![("fork", false), ("fs_open", true), ("fs_open", true)]);
+        assert_eq!(report.new_violations, 2);
+        assert_eq!(report.fixed, vec![gone]);
+    }
+}
@@ -1,273 +1,863 @@
+mod batching;
+mod clustering;
+mod cost;
+mod decomposition;
+mod export;
+mod feedback;
+mod html;
+mod impact;
+mod layering;
+mod metrics;
+mod overrides;
+mod reachability;
+mod reports;
+mod roots;
+
 use anyhow::{Context, Result};
 use clap::Parser;
-use kernel_schema::AtomicUnit;
-use petgraph::algo::{tarjan_scc, toposort};
+use export::{ColorBy, ExportFormat, ExportInput, GraphView};
+use overrides::Overrides;
+use reports::{ReportFormat, Reports, RunSummary};
+use kernel_schema::{
+    diff_units, AtomicUnit, BuildMetadata, BuildOrder, CycleAnalysis, DependencyKind, FasMethod, FeedbackArcSet,
+    PlanCost, UnitKind, UnitsFile,
+};
+use petgraph::algo::tarjan_scc;
 use petgraph::graph::DiGraph;
-use petgraph::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::fs;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Map dependencies and detect cycles for Atomic Units")]
 struct Args {
-    /// Path to the units.json file
+    /// Path to the units file (.json, .jsonl or .cbor)
     #[arg(short, long, default_value = "units.json")]
     units: PathBuf,
 
-    /// Path to the output build_order.json file
-    #[arg(short, long, default_value = "build_order.json")]
-    output: PathBuf,
+    /// Path to the output build order (.json, .jsonl or .cbor) [default: build_order.json,
+    /// in --report-dir if given]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Write the build order and every report (cycle analysis, metrics, costs,
+    /// clusters, graph exports, run summary) to this directory under fixed names
+    #[arg(long, value_name = "DIR")]
+    report_dir: Option<PathBuf>,
+
+    /// Encoding of the reports: compact JSON, indented JSON or YAML
+    #[arg(long, value_enum, default_value_t = ReportFormat::Pretty)]
+    format: ReportFormat,
+
+    /// Write the run summary (headline numbers and every file written) here
+    #[arg(long, value_name = "PATH")]
+    summary: Option<PathBuf>,
 
-    /// Analyze cycles and suggest refactoring strategies
+    /// Analyze cycles and suggest refactoring strategies, next to the build order
     #[arg(long)]
     analyze_cycles: bool,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildOrderBatch {
-    pub units: Vec<String>,
-    pub is_super_node: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub scc_size: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub refactoring_difficulty: Option<String>,
-}
+    /// Write the cycle analysis here (implies --analyze-cycles)
+    #[arg(long, value_name = "PATH")]
+    cycle_analysis: Option<PathBuf>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildOrder {
-    pub metadata: BuildMetadata,
-    pub batches: Vec<BuildOrderBatch>,
-}
+    /// Write a self-contained HTML page of the plan: batches, super nodes, cycle
+    /// analysis, a zoomable graph and each unit's code [default: report.html in --report-dir]
+    #[arg(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+
+    /// Split each level into batches of at most this many units (super nodes are never split)
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_batch_size: Option<usize>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildMetadata {
-    pub total_units: usize,
-    pub total_batches: usize,
-    pub super_nodes: usize,
-    pub largest_super_node: usize,
-    pub average_batch_size: f64,
+    /// Pack each level into batches whose estimated tokens stay under N
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    token_budget: Option<u64>,
+
+    /// Units the conductor ports in parallel, for the duration estimate
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    workers: usize,
+
+    /// Write estimated tokens per unit and per batch as JSON
+    #[arg(long, value_name = "PATH")]
+    cost_report: Option<PathBuf>,
+
+    /// Dependency kinds that become graph edges (default: all)
+    #[arg(long, value_delimiter = ',', value_name = "KINDS")]
+    edge_kinds: Vec<DependencyKind>,
+
+    /// Entry points for dead code detection: unit IDs or `*` patterns such as
+    /// `sys_*` or `global:syscalls` (default: main)
+    #[arg(long, value_delimiter = ',', value_name = "IDS")]
+    entry_points: Vec<String>,
+
+    /// File of entry points, one per line (`#` starts a comment)
+    #[arg(long, value_name = "PATH")]
+    entry_points_file: Option<PathBuf>,
+
+    /// Leave units no entry point reaches out of the build order
+    #[arg(long)]
+    skip_dead_code: bool,
+
+    /// Write unresolved dependencies and dead code as JSON
+    #[arg(long, value_name = "PATH")]
+    dependency_report: Option<PathBuf>,
+
+    /// Overrides (JSON or YAML) that remove or add edges, exclude units or pin
+    /// them to a level; the mapper prints the plan's shape before and after
+    #[arg(long, value_name = "PATH")]
+    overrides: Option<PathBuf>,
+
+    /// Write the overrides applied and the before/after comparison
+    #[arg(long, value_name = "PATH", requires = "overrides")]
+    overrides_report: Option<PathBuf>,
+
+    /// Layering rules (JSON or YAML): modules by source path, their layer order and
+    /// denied directions; the run fails on violations not in --layering-baseline
+    #[arg(long, value_name = "PATH")]
+    layering: Option<PathBuf>,
+
+    /// Write every layering violation with caller, callee and location
+    #[arg(long, value_name = "PATH", requires = "layering")]
+    layering_report: Option<PathBuf>,
+
+    /// A previous layering report whose violations are accepted
+    #[arg(long, value_name = "PATH", requires = "layering")]
+    layering_baseline: Option<PathBuf>,
+
+    /// Only schedule these units and what they transitively depend on (comma-separated IDs)
+    #[arg(long, value_delimiter = ',', value_name = "IDS")]
+    roots: Vec<String>,
+
+    /// File of root unit IDs, one per line (`#` starts a comment)
+    #[arg(long, value_name = "PATH")]
+    roots_file: Option<PathBuf>,
+
+    /// Write the root slice (included units, external frontier, size) as JSON
+    #[arg(long, value_name = "PATH")]
+    slice_report: Option<PathBuf>,
+
+    /// Only re-schedule these changed units and their transitive dependents (comma-separated IDs)
+    #[arg(long, value_delimiter = ',', value_name = "IDS")]
+    changed: Vec<String>,
+
+    /// File of changed unit IDs, one per line (`#` starts a comment)
+    #[arg(long, value_name = "PATH")]
+    changed_file: Option<PathBuf>,
+
+    /// Previous units file; units added, modified or renamed since count as changed
+    #[arg(long, value_name = "PATH")]
+    changed_since: Option<PathBuf>,
+
+    /// Write the impact analysis (changed units and their dependents) as JSON
+    #[arg(long, value_name = "PATH")]
+    impact_report: Option<PathBuf>,
+
+    /// Write per-unit fan-in/fan-out, betweenness and depth, super node ratings and the critical path as JSON
+    #[arg(long, value_name = "PATH")]
+    metrics: Option<PathBuf>,
+
+    /// Cluster units into modules and write a proposed crate/module layout as JSON
+    #[arg(long, value_name = "PATH")]
+    clusters: Option<PathBuf>,
+
+    /// Weight of edges within a source directory relative to other edges when clustering
+    #[arg(long, value_name = "WEIGHT", default_value_t = 2.0)]
+    cluster_prior: f64,
+
+    /// Export the graph as DOT (.dot, .gv), GraphML (.graphml) or Mermaid (.mmd, .mermaid); repeatable
+    #[arg(long, value_name = "PATH")]
+    export: Vec<PathBuf>,
+
+    /// Graph to export
+    #[arg(long, value_enum, default_value_t = GraphView::Units)]
+    export_graph: GraphView,
+
+    /// What node colors encode in exports
+    #[arg(long, value_enum, default_value_t = ColorBy::None)]
+    color_by: ColorBy,
+
+    /// Conductor task states as JSON rows, from
+    /// `sqlite3 -json conductor.db "SELECT atomic_unit_id, state FROM tasks"`
+    #[arg(long, value_name = "PATH")]
+    state: Option<PathBuf>,
+
+    /// Export only the units around this one
+    #[arg(long, value_name = "ID")]
+    export_root: Option<String>,
+
+    /// Hops around --export-root to export, following edges either way (default: unlimited)
+    #[arg(long, value_name = "N", requires = "export_root")]
+    export_depth: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CycleAnalysis {
-    pub super_node: Vec<String>,
-    pub size: usize,
-    pub weak_edges: Vec<(String, String)>,
-    pub refactoring_suggestions: Vec<String>,
+/// Edge payload: the strongest dependency kind and total references between two units
+#[derive(Debug, Clone, Copy)]
+struct DepEdge {
+    kind: DependencyKind,
+    call_sites: usize,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    for path in &args.export {
+        ExportFormat::from_path(path)?;
+    }
+    if args.color_by == ColorBy::State && args.state.is_none() {
+        anyhow::bail!("--color-by state needs conductor states from --state");
+    }
+    let roots = roots::collect_ids(&args.roots, args.roots_file.as_deref())?;
+    if args.slice_report.is_some() && roots.is_empty() {
+        anyhow::bail!("--slice-report needs roots from --roots or --roots-file");
+    }
+    let impact_run = !args.changed.is_empty() || args.changed_file.is_some() || args.changed_since.is_some();
+    if args.impact_report.is_some() && !impact_run {
+        anyhow::bail!("--impact-report needs --changed, --changed-file or --changed-since");
+    }
+    let mut reports = Reports::new(args.report_dir.clone(), args.format)?;
+    let output = match (&args.output, reports.in_dir("build_order.json")) {
+        (Some(path), _) => path.clone(),
+        (None, Some(path)) => path,
+        (None, None) => PathBuf::from("build_order.json"),
+    };
 
     println!("Mapper: Loading units from {:?}", args.units);
-    let units_json = fs::read_to_string(&args.units)
-        .with_context(|| format!("Failed to read units file {:?}", args.units))?;
-    let units: Vec<AtomicUnit> = serde_json::from_str(&units_json)
-        .context("Failed to parse units.json")?;
+    let mut units_file = UnitsFile::load(&args.units)
+        .with_context(|| format!("Failed to load units file {:?}", args.units))?;
+    // Canonical order: node indices, and with them SCC discovery, tie-breaks
+    // and report order, must not depend on the order of units.json. File-local
+    // statics can share an ID across files, so ties go by file, then code.
+    units_file.units.sort_by(|a, b| {
+        (&a.id, &a.source_file, &a.code).cmp(&(&b.id, &b.source_file, &b.code))
+    });
+    let units = &units_file.units;
+
+    let edge_kinds: Vec<DependencyKind> = if args.edge_kinds.is_empty() {
+        DependencyKind::ALL.to_vec()
+    } else {
+        args.edge_kinds.clone()
+    };
 
-    println!("Mapper: Constructing dependency graph for {} units", units.len());
-    let mut graph = DiGraph::<String, ()>::new();
+    let mut kind_counts: BTreeMap<UnitKind, usize> = BTreeMap::new();
+    for unit in units {
+        *kind_counts.entry(unit.kind).or_insert(0) += 1;
+    }
+    let breakdown: Vec<String> = kind_counts.iter().map(|(kind, n)| format!("{} {}", n, kind)).collect();
+    println!("Mapper: Constructing dependency graph for {} units ({})", units.len(), breakdown.join(", "));
+    let mut graph = DiGraph::<String, DepEdge>::new();
     let mut nodes = HashMap::new();
 
     // Add nodes
-    for unit in &units {
+    for unit in units {
         let idx = graph.add_node(unit.id.clone());
         nodes.insert(unit.id.clone(), idx);
     }
 
     // Add edges
-    for unit in &units {
+    let mut unresolved = reachability::UnresolvedCollector::default();
+    for unit in units {
         // Safe: we just inserted this node above, so it must exist
         let from_idx = nodes.get(&unit.id)
             .expect("Node was just inserted; this is a bug");
         for dep in &unit.dependencies {
-            if let Some(to_idx) = nodes.get(dep) {
-                graph.add_edge(*from_idx, *to_idx, ());
+            // Types, globals and macros are nodes too when the slicer emitted them as units
+            let Some(to_idx) = dep.resolve_with(|id| nodes.get(id)) else {
+                unresolved.record(&unit.id, dep, &units_file.symbols, |id| nodes.contains_key(id));
+                continue;
+            };
+            if !edge_kinds.contains(&dep.kind) {
+                continue;
+            }
+            // One edge per unit pair: references accumulate, calls win over other kinds
+            match graph.find_edge(*from_idx, *to_idx) {
+                Some(edge) => {
+                    let existing = &mut graph[edge];
+                    existing.call_sites += dep.call_sites;
+                    if dep.kind.is_call() && !existing.kind.is_call() {
+                        existing.kind = dep.kind;
+                    }
+                }
+                None => {
+                    graph.add_edge(*from_idx, *to_idx, DepEdge { kind: dep.kind, call_sites: dep.call_sites });
+                }
             }
         }
     }
 
-    println!("Mapper: Running cycle detection (Tarjan's SCC)");
-    let sccs = tarjan_scc(&graph);
-    
-    // Build a map from node index to SCC index
-    let mut node_to_scc = HashMap::new();
-    for (scc_idx, scc) in sccs.iter().enumerate() {
-        for node_idx in scc {
-            node_to_scc.insert(*node_idx, scc_idx);
+    let pointer_calls = unresolved.pointer_calls();
+    let unresolved = unresolved.finish();
+    print_unresolved_summary(&unresolved, pointer_calls);
+
+    println!("Mapper: Estimating token costs");
+    let costs: HashMap<String, cost::UnitCost> =
+        units.iter().map(|unit| (unit.id.clone(), cost::estimate(&units_file, unit))).collect();
+    let cost_of = |id: &str| costs[id].total_tokens;
+
+    let overrides = args.overrides.as_deref().map(Overrides::load).transpose()?;
+    let mut removed_edges = Vec::new();
+    if let Some(overrides) = &overrides {
+        let before = overrides::shape(&graph, None, args.max_batch_size, args.token_budget, cost_of)?;
+        let (overridden, applied) = overrides::apply(&graph, overrides)?;
+        graph = overridden;
+        let after = overrides::shape(&graph, Some(overrides), args.max_batch_size, args.token_budget, cost_of)?;
+        print_what_if(&applied, before, after);
+        if let Some(path) = reports.path("overrides", args.overrides_report.as_deref()) {
+            let report = overrides::WhatIf { overrides, applied: &applied, before, after };
+            reports.write("overrides", &path, &report)?;
+        }
+        removed_edges = applied.removed_edges;
+    }
+
+    // Layering is checked on the whole (overridden) graph, before any slicing
+    let mut new_violations = 0;
+    if let Some(path) = &args.layering {
+        let rules = layering::LayeringRules::load(path)?;
+        let baseline = match &args.layering_baseline {
+            Some(path) => layering::load_baseline(path)?,
+            None => HashSet::new(),
+        };
+        let report = layering::check(&graph, units, &edge_kinds, &rules, &baseline);
+        print_layering_summary(&report);
+        if let Some(path) = reports.path("layering", args.layering_report.as_deref()) {
+            reports.write("layering", &path, &report)?;
+        }
+        new_violations = report.new_violations;
+    }
+
+    let mut entry_points = roots::collect_ids(&args.entry_points, args.entry_points_file.as_deref())?;
+    if entry_points.is_empty() {
+        entry_points.push("main".to_string());
+    }
+    let (reachable, dead_code) = reachability::dead_code(&graph, units, &entry_points);
+    print_dead_code_summary(&dead_code);
+    if let Some(path) = reports.path("dependencies", args.dependency_report.as_deref()) {
+        let report = reachability::DependencyReport { unresolved: &unresolved, pointer_calls, dead_code: &dead_code };
+        reports.write("dependencies", &path, &report)?;
+    }
+    if args.skip_dead_code {
+        if dead_code.skipped {
+            anyhow::bail!("--skip-dead-code: no unit matches the entry points {}", entry_points.join(", "));
         }
+        graph = graph.filter_map(|idx, id| reachable.contains(&idx).then(|| id.clone()), |_, edge| Some(*edge));
+        println!("Mapper: Skipping {} unreachable unit(s)", dead_code.unreachable.len());
     }
 
-    // Construct the condensed graph (DAG of SCCs)
-    let mut cond_graph = DiGraph::<usize, ()>::new();
-    for i in 0..sccs.len() {
-        cond_graph.add_node(i);
-    }
-
-    for edge in graph.edge_indices() {
-        // Safe: edge_endpoints only returns None for invalid edge indices,
-        // but we're iterating over graph.edge_indices() so they must be valid
-        let (u, v) = graph.edge_endpoints(edge)
-            .expect("Edge index from edge_indices() must be valid");
-        // Safe: all nodes were assigned SCC indices in the loop above
-        let u_scc = *node_to_scc.get(&u)
-            .expect("All nodes must have SCC assignments");
-        let v_scc = *node_to_scc.get(&v)
-            .expect("All nodes must have SCC assignments");
-        if u_scc != v_scc {
-            cond_graph.update_edge(NodeIndex::new(u_scc), NodeIndex::new(v_scc), ());
+    if !roots.is_empty() {
+        let (restricted, slice) = roots::restrict_to_roots(&graph, units, &roots)?;
+        graph = restricted;
+        print_slice_summary(&slice);
+        if let Some(path) = reports.path("slice", args.slice_report.as_deref()) {
+            reports.write("slice", &path, &slice)?;
+        }
+    }
+
+    let mut changed = Vec::new();
+    if impact_run {
+        changed = roots::collect_ids(&args.changed, args.changed_file.as_deref())?;
+        if let Some(path) = &args.changed_since {
+            let old = UnitsFile::load(path).with_context(|| format!("Failed to load units file {:?}", path))?;
+            let diff = diff_units(&old, &units_file);
+            // Removed units are not in the graph, but what depended on them must be re-ported
+            let broken = diff.dependents_of_removed(&old, &units_file);
+            let since: Vec<&str> =
+                diff.added.iter().map(String::as_str).chain(diff.stale_units()).chain(broken.iter().copied()).collect();
+            println!(
+                "Mapper: {} unit(s) added, changed or depending on one of {} removed unit(s) since {:?}",
+                since.len(),
+                diff.removed.len(),
+                path
+            );
+            for id in since {
+                if !changed.iter().any(|c| c == id) {
+                    changed.push(id.to_string());
+                }
+            }
+        }
+
+        // Units outside this run (a --roots slice, skipped dead code, excluded
+        // by overrides) are left out, however they were named; IDs that are
+        // not units at all are still an error
+        let in_graph: HashSet<&str> = graph.node_weights().map(String::as_str).collect();
+        let is_unit: HashSet<&str> = units.iter().map(|unit| unit.id.as_str()).collect();
+        let before = changed.len();
+        changed.retain(|id| in_graph.contains(id.as_str()) || !is_unit.contains(id.as_str()));
+        if changed.len() < before {
+            println!("Mapper: Ignoring {} changed unit(s) outside this run", before - changed.len());
+        }
+
+        let (restricted, impact) = impact::restrict_to_dependents(&graph, &changed)?;
+        graph = restricted;
+        println!(
+            "Mapper: {} changed unit(s) affect {} of {} units",
+            impact.changed.len(),
+            impact.affected.len(),
+            impact.total_units
+        );
+        if let Some(path) = reports.path("impact", args.impact_report.as_deref()) {
+            reports.write("impact", &path, &impact)?;
         }
     }
 
-    println!("Mapper: Generating topological sort");
-    let mut order = toposort(&cond_graph, None)
-        .map_err(|_| anyhow::anyhow!("Cycle detected in condensed graph (should be impossible)"))?;
+    println!("Mapper: Running cycle detection (Tarjan's SCC)");
+    let sccs = tarjan_scc(&graph);
     
-    order.reverse();
-
-    let mut batches = Vec::new();
-    let mut super_node_count = 0;
-    let mut largest_super_node = 0;
-
-    for scc_idx in order {
-        let scc = &sccs[scc_idx.index()];
-        let unit_ids: Vec<String> = scc.iter().map(|idx| graph[*idx].clone()).collect();
-        let is_super_node = unit_ids.len() > 1;
-        
-        if is_super_node {
-            super_node_count += 1;
-            largest_super_node = largest_super_node.max(unit_ids.len());
-            println!("Mapper: Detected Super Node: {:?}", unit_ids);
+    // Map each node to its SCC and build the condensed graph (DAG of SCCs)
+    let (node_to_scc, cond_graph) = batching::condense(&graph, &sccs);
+
+    println!("Mapper: Assigning dependency levels");
+    let (min_levels, unplaced) = match &overrides {
+        Some(overrides) => overrides.min_levels(&graph, &node_to_scc),
+        None => Default::default(),
+    };
+    for unit in unplaced {
+        eprintln!("WARNING: Pinned unit '{}' is not part of this plan.", unit);
+    }
+    let levels = batching::scc_levels(&cond_graph, &min_levels)?;
+    for pin in overrides.iter().flat_map(|overrides| &overrides.pin) {
+        if let Some(idx) = graph.node_indices().find(|idx| graph[*idx] == pin.unit)
+            && levels[node_to_scc[&idx]] > pin.level
+        {
+            eprintln!(
+                "WARNING: '{}' is pinned to level {}, but its dependencies put it on level {}.",
+                pin.unit,
+                pin.level,
+                levels[node_to_scc[&idx]]
+            );
         }
+    }
 
-        let difficulty = if unit_ids.len() > 20 {
-            Some("Very High".to_string())
-        } else if unit_ids.len() > 10 {
-            Some("High".to_string())
-        } else if unit_ids.len() > 5 {
-            Some("Medium".to_string())
-        } else if is_super_node {
-            Some("Low".to_string())
-        } else {
-            None
-        };
+    // Break each super node with a minimum feedback arc set; its order is the
+    // order units are listed in the super node's batch
+    let feedback_sets: HashMap<usize, FeedbackArcSet> = sccs
+        .iter()
+        .enumerate()
+        .filter(|(_, scc)| scc.len() > 1)
+        .map(|(i, scc)| (i, feedback::feedback_arc_set(&graph, scc)))
+        .collect();
+    let scc_units: Vec<Vec<String>> = sccs
+        .iter()
+        .enumerate()
+        .map(|(i, scc)| match feedback_sets.get(&i) {
+            Some(fas) => fas.order.clone(),
+            None => scc.iter().map(|idx| graph[*idx].clone()).collect(),
+        })
+        .collect();
+
+    let super_node_count = scc_units.iter().filter(|units| units.len() > 1).count();
+    let largest_super_node = scc_units.iter().map(Vec::len).filter(|&len| len > 1).max().unwrap_or(0);
+    if super_node_count > 0 {
+        println!(
+            "Mapper: Detected {} super node(s), the largest with {} units",
+            super_node_count, largest_super_node
+        );
+    }
 
-        batches.push(BuildOrderBatch {
-            units: unit_ids,
-            is_super_node,
-            scc_size: if is_super_node { Some(scc.len()) } else { None },
-            refactoring_difficulty: difficulty,
-        });
+    let mut batches = batching::level_batches(
+        &scc_units,
+        &levels,
+        args.max_batch_size,
+        args.token_budget,
+        cost_of,
+        |scc| metrics::refactoring_difficulty(sccs[scc].len(), &feedback_sets[&scc]),
+    );
+    // Super node batches list their units in FAS order; the plan says how to port them one by one
+    let by_id: HashMap<&str, &AtomicUnit> = units.iter().map(|unit| (unit.id.as_str(), unit)).collect();
+    let scc_of: HashMap<&str, usize> = feedback_sets.keys().map(|&scc| (scc_units[scc][0].as_str(), scc)).collect();
+    for batch in batches.iter_mut().filter(|batch| batch.is_super_node) {
+        let fas = &feedback_sets[&scc_of[batch.units[0].as_str()]];
+        batch.decomposition = Some(decomposition::plan(fas, &by_id));
     }
+    let (plan_cost, batch_costs) = cost::plan_cost(&mut batches, &costs, args.workers, args.token_budget);
+    let depth = levels.iter().max().map_or(0, |max| max + 1);
+    println!("Mapper: {} levels; widest batch holds {} units", depth,
+        batches.iter().map(|b| b.units.len()).max().unwrap_or(0));
 
     // Validation warnings
     for batch in &batches {
-        if let Some(size) = batch.scc_size {
-            if size > 20 {
-                eprintln!("WARNING: Super Node with {} functions detected. Consider breaking this cycle.", size);
-            }
+        if let Some(size) = batch.scc_size
+            && size > 20
+        {
+            eprintln!("WARNING: Super Node with {} functions detected. Consider breaking this cycle.", size);
+        }
+        if let (Some(budget), Some(tokens)) = (args.token_budget, batch.estimated_tokens)
+            && tokens > budget
+        {
+            eprintln!(
+                "WARNING: Batch of {} unit(s) starting with '{}' is estimated at {} tokens, over the budget of {}.",
+                batch.units.len(),
+                batch.units[0],
+                tokens,
+                budget
+            );
         }
     }
+    print_cost_summary(&plan_cost);
+    if let Some(path) = reports.path("cost", args.cost_report.as_deref()) {
+        let mut by_cost: Vec<&cost::UnitCost> = graph.node_weights().map(|id| &costs[id]).collect();
+        by_cost.sort_by(|a, b| b.total_tokens.cmp(&a.total_tokens).then_with(|| a.id.cmp(&b.id)));
+        let report = cost::CostReport { plan: &plan_cost, batches: batch_costs, units: by_cost };
+        reports.write("cost", &path, &report)?;
+    }
 
     let metadata = BuildMetadata {
-        total_units: units.len(),
+        total_units: graph.node_count(),
         total_batches: batches.len(),
         super_nodes: super_node_count,
         largest_super_node,
-        average_batch_size: units.len() as f64 / batches.len() as f64,
+        average_batch_size: if batches.is_empty() { 0.0 } else { graph.node_count() as f64 / batches.len() as f64 },
+        edge_kinds: args.edge_kinds.clone(),
+        roots,
+        changed,
+        removed_edges,
+        estimated_cost: Some(plan_cost.clone()),
     };
 
     let build_order = BuildOrder { metadata, batches };
 
-    let output_json = serde_json::to_string_pretty(&build_order)?;
-    fs::write(&args.output, output_json)
-        .with_context(|| format!("Failed to write build order to {:?}", args.output))?;
+    build_order.save(&output)
+        .with_context(|| format!("Failed to write build order to {:?}", output))?;
+    reports.record("build_order", &output);
 
-    println!("Mapper: Generated {} batches to {:?}", build_order.batches.len(), args.output);
+    println!("Mapper: Generated {} batches to {:?}", build_order.batches.len(), output);
 
-    // Cycle analysis
-    if args.analyze_cycles {
-        println!("Mapper: Analyzing cycles...");
-        let mut analyses = Vec::new();
-
-        for scc in &sccs {
-            if scc.len() > 1 {
-                let unit_ids: Vec<String> = scc.iter().map(|idx| graph[*idx].clone()).collect();
-                let weak_edges = find_weak_edges(&graph, scc);
-                let suggestions = generate_refactoring_suggestions(scc.len(), &weak_edges);
-
-                analyses.push(CycleAnalysis {
-                    super_node: unit_ids,
-                    size: scc.len(),
-                    weak_edges,
-                    refactoring_suggestions: suggestions,
-                });
-            }
+    if let Some(path) = reports.path("metrics", args.metrics.as_deref()) {
+        println!("Mapper: Computing graph metrics");
+        let metrics = metrics::compute(&graph, &sccs, &node_to_scc, &cond_graph, &levels, &feedback_sets);
+        print_metrics_summary(&metrics);
+        reports.write("metrics", &path, &metrics)?;
+    }
+
+    if let Some(path) = reports.path("clusters", args.clusters.as_deref()) {
+        println!("Mapper: Clustering units into modules");
+        let report = clustering::cluster(&graph, units, args.cluster_prior);
+        print_cluster_summary(&report);
+        reports.write("clusters", &path, &report)?;
+    }
+
+    // A report directory always gets the graph in every export format
+    let mut exports: Vec<(String, PathBuf)> =
+        args.export.iter().enumerate().map(|(i, path)| (format!("export_{}", i + 1), path.clone())).collect();
+    for file_name in ["graph.dot", "graph.graphml", "graph.mmd"] {
+        if let Some(path) = reports.in_dir(file_name) {
+            exports.push((file_name.replace('.', "_"), path));
         }
+    }
+    if !exports.is_empty() {
+        let states = match &args.state {
+            Some(path) => export::load_states(path)?,
+            None => HashMap::new(),
+        };
+        let input = ExportInput {
+            graph: &graph,
+            sccs: &sccs,
+            node_to_scc: &node_to_scc,
+            feedback_sets: &feedback_sets,
+            groups: export::unit_groups(units, args.color_by, &states),
+        };
+        for (name, path) in &exports {
+            let (nodes, edges) =
+                export::export(&input, path, args.export_graph, args.export_root.as_deref(), args.export_depth)?;
+            println!("Mapper: Exported {} nodes and {} edges to {:?}", nodes, edges, path);
+            reports.record(name, path);
+        }
+    }
+
+    // Cycle analysis: an explicit path, the report directory, or next to the build order
+    let analyses: Vec<CycleAnalysis> = sccs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, scc)| {
+            let fas = feedback_sets.get(&i)?;
+            Some(CycleAnalysis {
+                super_node: scc_units[i].clone(),
+                size: scc.len(),
+                weak_edges: fas.arcs.iter().map(|arc| (arc.from.clone(), arc.to.clone())).collect(),
+                refactoring_suggestions: generate_refactoring_suggestions(scc.len(), fas),
+                feedback_arc_set: Some(fas.clone()),
+            })
+        })
+        .collect();
+    let cycle_analysis_path = reports.path("cycle_analysis", args.cycle_analysis.as_deref()).or_else(|| {
+        args.analyze_cycles.then(|| output.with_file_name(format!("cycle_analysis.{}", args.format.extension())))
+    });
+    if let Some(path) = cycle_analysis_path {
+        println!("Mapper: Analyzing cycles...");
+        reports.write("cycle_analysis", &path, &analyses)?;
+    }
+
+    if let Some(path) = args.html.clone().or_else(|| reports.in_dir("report.html")) {
+        let title = args.units.display().to_string();
+        html::write(&path, &title, &graph, &units_file, &build_order, &analyses)?;
+        println!("Mapper: Wrote HTML report to {:?}", path);
+        reports.record("html", &path);
+    }
 
-        let analysis_json = serde_json::to_string_pretty(&analyses)?;
-        fs::write("cycle_analysis.json", analysis_json)?;
-        println!("Mapper: Cycle analysis written to cycle_analysis.json");
+    let summary = RunSummary {
+        units_file: args.units.clone(),
+        total_units: units.len(),
+        scheduled_units: build_order.metadata.total_units,
+        total_batches: build_order.batches.len(),
+        levels: depth,
+        super_nodes: super_node_count,
+        largest_super_node,
+        feedback_arcs: feedback_sets.values().map(|fas| fas.arcs.len()).sum(),
+        unresolved_dependencies: unresolved.len(),
+        pointer_calls,
+        unreachable_units: (!dead_code.skipped).then_some(dead_code.unreachable.len()),
+        estimated_cost: plan_cost,
+        files: BTreeMap::new(),
+    };
+    reports.write_summary(args.summary.as_deref(), summary)?;
+
+    if new_violations > 0 {
+        anyhow::bail!("{} new layering violation(s)", new_violations);
     }
 
     Ok(())
 }
 
-/// Find edges that might be good candidates for breaking the cycle.
-/// 
-/// NOTE: This is a HEURISTIC, not an optimal minimum feedback arc set.
-/// We identify edges where the target has low in-degree within the SCC,
-/// reasoning that such edges might be easier to refactor.
-/// 
-/// For production use, consider implementing a proper minimum FAS algorithm.
-fn find_weak_edges(graph: &DiGraph<String, ()>, scc: &[NodeIndex]) -> Vec<(String, String)> {
-    let scc_set: HashSet<_> = scc.iter().copied().collect();
-    let mut weak_edges = Vec::new();
-
-    // A weak edge is one whose removal would break the SCC
-    // For simplicity, we identify edges that are part of the minimum feedback arc set
-    // Here we use a heuristic: edges with low in-degree targets
-    for &node in scc {
-        for edge in graph.edges(node) {
-            let target = edge.target();
-            if scc_set.contains(&target) {
-                // Count in-degree within SCC
-                let in_degree = graph.edges_directed(target, petgraph::Direction::Incoming)
-                    .filter(|e| scc_set.contains(&e.source()))
-                    .count();
-                
-                if in_degree <= 2 {
-                    weak_edges.push((graph[node].clone(), graph[target].clone()));
-                }
-            }
+fn print_layering_summary(report: &layering::LayeringReport) {
+    println!(
+        "Mapper: {} layering violation(s), {} new; {} fixed since the baseline",
+        report.violations.len(),
+        report.new_violations,
+        report.fixed.len()
+    );
+    for violation in report.violations.iter().filter(|v| v.new).take(20) {
+        let location = match &violation.location {
+            Some(location) => format!("{}:{}:{}", location.file, location.line, location.column),
+            None => "unknown location".to_string(),
+        };
+        let reason = violation.reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+        println!("  {} -> {} at {}: {}{}", violation.caller, violation.callee, location, violation.rule, reason);
+    }
+    if report.new_violations > 20 {
+        println!("  ... and {} more", report.new_violations - 20);
+    }
+}
+
+fn print_what_if(applied: &overrides::Applied, before: overrides::PlanShape, after: overrides::PlanShape) {
+    println!(
+        "Mapper: Overrides removed {} edge(s), added {} and excluded {} unit(s)",
+        applied.removed_edges.len(),
+        applied.added_edges.len(),
+        applied.excluded.len()
+    );
+    for (from, to) in &applied.missing_edges {
+        eprintln!("WARNING: Override removes {} -> {}, which is not in the graph.", from, to);
+    }
+    let rows = [
+        ("units", before.units, after.units),
+        ("super nodes", before.super_nodes, after.super_nodes),
+        ("largest SCC", before.largest_scc, after.largest_scc),
+        ("levels", before.levels, after.levels),
+        ("batches", before.batches, after.batches),
+    ];
+    println!("Mapper: {:<12} {:>8} {:>8} {:>8}", "", "before", "after", "change");
+    for (name, before, after) in rows {
+        println!("Mapper: {:<12} {:>8} {:>8} {:>+8}", name, before, after, after as i64 - before as i64);
+    }
+}
+
+fn print_cost_summary(plan: &PlanCost) {
+    println!(
+        "Mapper: Estimated spend: {} tokens ({} input, {} output)",
+        plan.total_tokens, plan.input_tokens, plan.output_tokens
+    );
+    println!(
+        "Mapper: Estimated makespan on {} worker(s): {} tokens ({:.0}% of the sequential time)",
+        plan.workers,
+        plan.makespan_tokens,
+        if plan.total_tokens == 0 { 100.0 } else { 100.0 * plan.makespan_tokens as f64 / plan.total_tokens as f64 }
+    );
+}
+
+fn print_cluster_summary(report: &clustering::ClusterReport) {
+    println!(
+        "Mapper: {} clusters (modularity {:.3}), {} inter-cluster dependencies, {} cycle(s) between clusters",
+        report.clusters.len(),
+        report.modularity,
+        report.inter_cluster_edges.len(),
+        report.cycles.len()
+    );
+    for cluster in report.clusters.iter().filter(|cluster| !cluster.relocated.is_empty()) {
+        println!("  {} takes {} from other directories", cluster.path, cluster.relocated.join(", "));
+    }
+    for cycle in &report.cycles {
+        let cuts: Vec<String> = cycle.break_edges.iter().map(|edge| format!("{} -> {}", edge.from, edge.to)).collect();
+        println!("  Cycle {}: break {}", cycle.clusters.join(" <-> "), cuts.join(", "));
+    }
+    println!("Mapper: Proposed crates, lowest layer first:");
+    for proposed in &report.crates {
+        let deps = if proposed.depends_on.is_empty() {
+            String::new()
+        } else {
+            format!(" (uses {})", proposed.depends_on.join(", "))
+        };
+        println!("  [{}] {}{}: {}", proposed.layer, proposed.name, deps, proposed.modules.join(", "));
+    }
+}
+
+fn print_metrics_summary(metrics: &metrics::GraphMetrics) {
+    let mut chain: Vec<String> = metrics
+        .critical_path
+        .iter()
+        .map(|units| match units.as_slice() {
+            [unit] => unit.clone(),
+            _ => format!("[{}]", units.join(", ")),
+        })
+        .collect();
+    if chain.len() > 12 {
+        chain.splice(5..chain.len() - 5, ["...".to_string()]);
+    }
+    println!(
+        "Mapper: Critical path: {} sequential round(s): {}",
+        metrics.critical_path_length,
+        chain.join(" -> ")
+    );
+
+    let by_id: HashMap<&str, &metrics::UnitMetrics> =
+        metrics.unit_metrics.iter().map(|m| (m.id.as_str(), m)).collect();
+    if !metrics.hubs.is_empty() {
+        println!("Mapper: Hubs worth a senior review (betweenness, fan-in, fan-out):");
+        for hub in metrics.hubs.iter().take(5) {
+            let m = by_id[hub.as_str()];
+            println!("  {} ({:.3}, {}, {})", m.id, m.betweenness, m.fan_in, m.fan_out);
         }
     }
+    let mut by_fan_in: Vec<&metrics::UnitMetrics> = metrics.unit_metrics.iter().filter(|m| m.fan_in > 0).collect();
+    by_fan_in.sort_by(|a, b| b.fan_in.cmp(&a.fan_in).then_with(|| a.id.cmp(&b.id)));
+    let top: Vec<String> = by_fan_in.iter().take(5).map(|m| format!("{} ({})", m.id, m.fan_in)).collect();
+    if !top.is_empty() {
+        println!("Mapper: Most depended on: {}", top.join(", "));
+    }
+    for super_node in &metrics.super_nodes {
+        println!(
+            "Mapper: Super Node of {} units: {} edge(s) to break, {} dependent(s), difficulty {}",
+            super_node.units.len(),
+            super_node.feedback_arcs,
+            super_node.dependents,
+            super_node.difficulty
+        );
+    }
+}
+
+fn print_unresolved_summary(unresolved: &[reachability::UnresolvedDependency], pointer_calls: usize) {
+    let references: usize = unresolved.iter().map(|dep| dep.references).sum();
+    println!(
+        "Mapper: {} unresolved dependency target(s) ({} reference(s)); {} call(s) through function pointers",
+        unresolved.len(),
+        references,
+        pointer_calls
+    );
+    const SHOWN: usize = 10;
+    for dep in unresolved.iter().take(SHOWN) {
+        let kinds: Vec<&str> = dep.kinds.iter().map(|kind| kind.as_str()).collect();
+        let callers: Vec<&str> = dep.callers.iter().map(|caller| caller.unit.as_str()).collect();
+        println!(
+            "  {} ({}{}) x{} from {}",
+            dep.target,
+            kinds.join(", "),
+            if dep.declared { ", declared" } else { "" },
+            dep.references,
+            callers.join(", ")
+        );
+    }
+    if unresolved.len() > SHOWN {
+        println!("  ... and {} more", unresolved.len() - SHOWN);
+    }
+}
 
-    weak_edges
+fn print_dead_code_summary(dead_code: &reachability::DeadCode) {
+    if !dead_code.unmatched.is_empty() {
+        eprintln!("WARNING: Entry point(s) matched no unit: {}", dead_code.unmatched.join(", "));
+    }
+    if dead_code.skipped {
+        println!("Mapper: No entry points found; skipping dead code detection");
+        return;
+    }
+    println!(
+        "Mapper: {} unit(s) ({} lines) unreachable from {} entry point(s)",
+        dead_code.unreachable.len(),
+        dead_code.unreachable_lines,
+        dead_code.matched.len()
+    );
+    const SHOWN: usize = 20;
+    let shown: Vec<&str> = dead_code.unreachable.iter().take(SHOWN).map(|unit| unit.id.as_str()).collect();
+    if !shown.is_empty() {
+        let more = dead_code.unreachable.len().saturating_sub(SHOWN);
+        let suffix = if more > 0 { format!(" ... and {} more", more) } else { String::new() };
+        println!("  {}{}", shown.join(", "), suffix);
+    }
 }
 
-fn generate_refactoring_suggestions(size: usize, weak_edges: &[(String, String)]) -> Vec<String> {
+fn print_slice_summary(slice: &roots::RootSlice) {
+    let breakdown: Vec<String> = slice.included_by_kind.iter().map(|(kind, n)| format!("{} {}", n, kind)).collect();
+    println!(
+        "Mapper: {} root(s) require {} of {} units ({}), {} of {} lines ({:.1}%)",
+        slice.roots.len(),
+        slice.included.len(),
+        slice.total_units,
+        breakdown.join(", "),
+        slice.lines,
+        slice.total_lines,
+        slice.line_fraction() * 100.0
+    );
+
+    let external = slice.frontier.iter().filter(|dep| !dep.is_unit).count();
+    println!(
+        "Mapper: Frontier: {} external dependencies, {} units not followed by --edge-kinds",
+        external,
+        slice.frontier.len() - external
+    );
+    const SHOWN: usize = 20;
+    for dep in slice.frontier.iter().take(SHOWN) {
+        let kinds: Vec<&str> = dep.kinds.iter().map(|kind| kind.as_str()).collect();
+        println!(
+            "  {} {} ({}) needed by {}",
+            if dep.is_unit { "unit" } else { "external" },
+            dep.target,
+            kinds.join(", "),
+            dep.needed_by.join(", ")
+        );
+    }
+    if slice.frontier.len() > SHOWN {
+        println!("  ... and {} more", slice.frontier.len() - SHOWN);
+    }
+}
+
+fn generate_refactoring_suggestions(size: usize, fas: &FeedbackArcSet) -> Vec<String> {
     let mut suggestions = Vec::new();
 
-    // Add confidence notice - this is a heuristic, not an optimal solution
-    suggestions.push("NOTE: Suggestions are based on heuristic analysis (low in-degree edges).".to_string());
+    let method = match fas.method {
+        FasMethod::Exact => "a minimum feedback arc set [Confidence: High]",
+        FasMethod::EadesLinSmyth => "an Eades-Lin-Smyth approximation of the minimum feedback arc set [Confidence: Medium]",
+    };
+    suggestions.push(format!("NOTE: Edges below are {}.", method));
 
     if size > 20 {
         suggestions.push("CRITICAL: This Super Node is very large. Consider architectural refactoring.".to_string());
     }
 
-    if !weak_edges.is_empty() {
+    if !fas.arcs.is_empty() {
         suggestions.push(format!(
-            "Consider breaking {} weak edge(s) to simplify the cycle. [Confidence: Medium]",
-            weak_edges.len()
+            "Break {} edge(s) ({} call site(s)) to make the cycle acyclic, then port in order: {}",
+            fas.arcs.len(),
+            fas.total_call_sites,
+            fas.order.join(" -> ")
         ));
-        
-        for (from, to) in weak_edges.iter().take(3) {
+
+        let mut stubbed: Vec<&str> = fas.arcs.iter().map(|arc| arc.to.as_str()).collect();
+        stubbed.sort_unstable();
+        stubbed.dedup();
+        suggestions.push(format!(
+            "Stub {} first; the batch's decomposition plan in the build order gives the declarations and steps",
+            stubbed.join(", ")
+        ));
+
+        for arc in fas.arcs.iter().take(3) {
             suggestions.push(format!(
-                "  - Extract interface between '{}' and '{}'",
-                from, to
+                "  - Extract interface between '{}' and '{}' ({} call site(s))",
+                arc.from, arc.to, arc.call_sites
             ));
         }
     }
@@ -0,0 +1,347 @@
+use crate::DepEdge;
+use kernel_schema::FeedbackArcSet;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Graphs with more units than this get sampled betweenness
+pub const EXACT_BETWEENNESS_LIMIT: usize = 5000;
+/// Source units sampled for larger graphs
+const BETWEENNESS_SAMPLES: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct UnitMetrics {
+    pub id: String,
+    /// Distinct units that depend on this one
+    pub fan_in: usize,
+    /// Distinct units this one depends on
+    pub fan_out: usize,
+    /// Share of shortest dependency paths through this unit, 0 to 1
+    pub betweenness: f64,
+    /// Level of the unit's SCC: 0 for leaves, otherwise one above its deepest
+    /// dependency, or the level an override pins it to if that is higher
+    pub depth: usize,
+    pub scc_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuperNodeMetrics {
+    pub units: Vec<String>,
+    /// Dependency edges between members
+    pub internal_edges: usize,
+    /// Edges to break (the feedback arc set) and their call sites
+    pub feedback_arcs: usize,
+    pub feedback_call_sites: usize,
+    /// Units outside the super node that depend on it
+    pub dependents: usize,
+    pub difficulty: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BetweennessMethod {
+    Exact,
+    /// Brandes' algorithm from evenly spaced sample sources, scaled up
+    Sampled { sources: usize },
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphMetrics {
+    pub units: usize,
+    pub edges: usize,
+    pub sccs: usize,
+    /// SCCs on the longest dependency chain: the minimum number of sequential rounds
+    pub critical_path_length: usize,
+    /// That chain, dependencies first; each entry lists the members of one SCC
+    pub critical_path: Vec<Vec<String>>,
+    pub betweenness_method: BetweennessMethod,
+    /// Units with the highest betweenness, most central first
+    pub hubs: Vec<String>,
+    pub super_nodes: Vec<SuperNodeMetrics>,
+    pub unit_metrics: Vec<UnitMetrics>,
+}
+
+/// Difficulty of porting a super node, from the number of edges that must be
+/// broken (each one an interface to extract) and its size
+pub fn refactoring_difficulty(size: usize, fas: &FeedbackArcSet) -> &'static str {
+    match fas.arcs.len() {
+        _ if size > 20 => "Very High",
+        0..=1 => "Low",
+        2..=3 => "Medium",
+        4..=10 => "High",
+        _ => "Very High",
+    }
+}
+
+pub fn compute(
+    graph: &DiGraph<String, DepEdge>,
+    sccs: &[Vec<NodeIndex>],
+    node_to_scc: &HashMap<NodeIndex, usize>,
+    cond_graph: &DiGraph<usize, ()>,
+    levels: &[usize],
+    feedback_sets: &HashMap<usize, FeedbackArcSet>,
+) -> GraphMetrics {
+    let (betweenness, betweenness_method) = betweenness(graph);
+
+    let mut unit_metrics: Vec<UnitMetrics> = graph
+        .node_indices()
+        .map(|idx| {
+            let distinct = |direction| graph.neighbors_directed(idx, direction).filter(|n| *n != idx).collect::<HashSet<_>>().len();
+            let scc = node_to_scc[&idx];
+            UnitMetrics {
+                id: graph[idx].clone(),
+                fan_in: distinct(Direction::Incoming),
+                fan_out: distinct(Direction::Outgoing),
+                betweenness: betweenness[idx.index()],
+                depth: levels[scc],
+                scc_size: sccs[scc].len(),
+            }
+        })
+        .collect();
+    unit_metrics.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut by_centrality: Vec<&UnitMetrics> = unit_metrics.iter().filter(|m| m.betweenness > 0.0).collect();
+    by_centrality.sort_by(|a, b| b.betweenness.total_cmp(&a.betweenness).then_with(|| a.id.cmp(&b.id)));
+    let hubs = by_centrality.iter().take(10).map(|m| m.id.clone()).collect();
+
+    let mut super_nodes: Vec<SuperNodeMetrics> = feedback_sets
+        .iter()
+        .map(|(&scc, fas)| {
+            let members: HashSet<NodeIndex> = sccs[scc].iter().copied().collect();
+            let internal_edges = sccs[scc]
+                .iter()
+                .flat_map(|idx| graph.neighbors_directed(*idx, Direction::Outgoing))
+                .filter(|n| members.contains(n))
+                .count();
+            let dependents: HashSet<NodeIndex> = sccs[scc]
+                .iter()
+                .flat_map(|idx| graph.neighbors_directed(*idx, Direction::Incoming))
+                .filter(|n| !members.contains(n))
+                .collect();
+            SuperNodeMetrics {
+                units: fas.order.clone(),
+                internal_edges,
+                feedback_arcs: fas.arcs.len(),
+                feedback_call_sites: fas.total_call_sites,
+                dependents: dependents.len(),
+                difficulty: refactoring_difficulty(sccs[scc].len(), fas),
+            }
+        })
+        .collect();
+    super_nodes.sort_by(|a, b| b.units.len().cmp(&a.units.len()).then_with(|| a.units.cmp(&b.units)));
+
+    let critical_path = critical_path(graph, sccs, cond_graph, levels);
+    GraphMetrics {
+        units: graph.node_count(),
+        edges: graph.edge_count(),
+        sccs: sccs.len(),
+        critical_path_length: critical_path.len(),
+        critical_path,
+        betweenness_method,
+        hubs,
+        super_nodes,
+        unit_metrics,
+    }
+}
+
+/// Longest chain of SCCs, found by walking down from the highest level to the
+/// highest-level dependency (ties go to the smallest unit ID). Without pins that
+/// dependency is exactly one level lower; a pin can leave a larger gap.
+fn critical_path(
+    graph: &DiGraph<String, DepEdge>,
+    sccs: &[Vec<NodeIndex>],
+    cond_graph: &DiGraph<usize, ()>,
+    levels: &[usize],
+) -> Vec<Vec<String>> {
+    let members = |scc: usize| -> Vec<String> {
+        let mut units: Vec<String> = sccs[scc].iter().map(|idx| graph[*idx].clone()).collect();
+        units.sort();
+        units
+    };
+    let Some(top) = (0..sccs.len()).max_by(|a, b| levels[*a].cmp(&levels[*b]).then_with(|| members(*b).cmp(&members(*a))))
+    else {
+        return Vec::new();
+    };
+
+    let mut path = vec![members(top)];
+    let mut current = top;
+    while let Some(next) = cond_graph
+        .neighbors_directed(NodeIndex::new(current), Direction::Outgoing)
+        .map(|dep| dep.index())
+        .max_by(|a, b| levels[*a].cmp(&levels[*b]).then_with(|| members(*b).cmp(&members(*a))))
+    {
+        current = next;
+        path.push(members(current));
+    }
+    path.reverse();
+    path
+}
+
+/// Normalized betweenness centrality (Brandes), exact for graphs up to
+/// [`EXACT_BETWEENNESS_LIMIT`] units and sampled above that
+fn betweenness(graph: &DiGraph<String, DepEdge>) -> (Vec<f64>, BetweennessMethod) {
+    let n = graph.node_count();
+    let (sources, method): (Vec<NodeIndex>, _) = if n <= EXACT_BETWEENNESS_LIMIT {
+        (graph.node_indices().collect(), BetweennessMethod::Exact)
+    } else {
+        // Evenly spaced in ID order, so runs are reproducible
+        let mut ordered: Vec<NodeIndex> = graph.node_indices().collect();
+        ordered.sort_by(|a, b| graph[*a].cmp(&graph[*b]));
+        let step = n / BETWEENNESS_SAMPLES;
+        let sampled: Vec<NodeIndex> = ordered.into_iter().step_by(step).take(BETWEENNESS_SAMPLES).collect();
+        let count = sampled.len();
+        (sampled, BetweennessMethod::Sampled { sources: count })
+    };
+
+    // Summed in source order: a parallel reduce would add the floats in a
+    // different order from run to run
+    let parts: Vec<Vec<f64>> =
+        sources.par_iter().map(|&source| single_source_dependencies(graph, source)).collect();
+    let mut centrality = parts.into_iter().fold(vec![0.0; n], |mut total, part| {
+        total.iter_mut().zip(part).for_each(|(t, p)| *t += p);
+        total
+    });
+
+    // Scale samples up to the whole graph, then normalize by the number of
+    // ordered pairs a unit can lie between
+    let scale = n as f64 / sources.len().max(1) as f64;
+    let pairs = ((n.saturating_sub(1)) * (n.saturating_sub(2))) as f64;
+    for value in &mut centrality {
+        *value = if pairs > 0.0 { *value * scale / pairs } else { 0.0 };
+    }
+    (centrality, method)
+}
+
+/// One round of Brandes' algorithm: every unit's share of the shortest paths from `source`
+fn single_source_dependencies(graph: &DiGraph<String, DepEdge>, source: NodeIndex) -> Vec<f64> {
+    let n = graph.node_count();
+    let mut stack = Vec::with_capacity(n);
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut paths = vec![0.0f64; n];
+    let mut distance = vec![usize::MAX; n];
+    paths[source.index()] = 1.0;
+    distance[source.index()] = 0;
+
+    let mut queue = VecDeque::from([source]);
+    while let Some(v) = queue.pop_front() {
+        stack.push(v.index());
+        for w in graph.neighbors_directed(v, Direction::Outgoing) {
+            let (v, w) = (v.index(), w.index());
+            if distance[w] == usize::MAX {
+                distance[w] = distance[v] + 1;
+                queue.push_back(NodeIndex::new(w));
+            }
+            if distance[w] == distance[v] + 1 {
+                paths[w] += paths[v];
+                predecessors[w].push(v);
+            }
+        }
+    }
+
+    let mut dependency = vec![0.0f64; n];
+    let mut result = vec![0.0f64; n];
+    while let Some(w) = stack.pop() {
+        for &v in &predecessors[w] {
+            dependency[v] += paths[v] / paths[w] * (1.0 + dependency[w]);
+        }
+        if w != source.index() {
+            result[w] = dependency[w];
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batching;
+    use kernel_schema::DependencyKind;
+    use petgraph::algo::tarjan_scc;
+
+    /// Graph of `(dependent, dependency)` edges, nodes added in first-seen order
+    fn graph(edges: &[(&str, &str)]) -> DiGraph<String, DepEdge> {
+        let mut graph = DiGraph::new();
+        let mut nodes = HashMap::new();
+        for &(from, to) in edges {
+            let [from, to] = [from, to].map(|id| *nodes.entry(id).or_insert_with(|| graph.add_node(id.to_string())));
+            graph.add_edge(from, to, DepEdge { kind: DependencyKind::DirectCall, call_sites: 1 });
+        }
+        graph
+    }
+
+    fn centrality(graph: &DiGraph<String, DepEdge>, id: &str) -> f64 {
+        let (values, _) = betweenness(graph);
+        values[graph.node_indices().find(|idx| graph[*idx] == id).unwrap().index()]
+    }
+
+    fn path(graph: &DiGraph<String, DepEdge>) -> Vec<Vec<String>> {
+        let sccs = tarjan_scc(graph);
+        let (_, cond_graph) = batching::condense(graph, &sccs);
+        let levels = batching::scc_levels(&cond_graph, &HashMap::new()).unwrap();
+        critical_path(graph, &sccs, &cond_graph, &levels)
+    }
+
+    #[test]
+    fn betweenness_of_known_graphs() {
+        // Normalized by the (n - 1)(n - 2) ordered pairs of other units.
+        // b is on the only a -> c path, out of 2 * 1 pairs
+        let chain = graph(&[("a", "b"), ("b", "c")]);
+        assert_eq!(centrality(&chain, "b"), 0.5);
+        assert_eq!(centrality(&chain, "a"), 0.0);
+        assert_eq!(centrality(&chain, "c"), 0.0);
+
+        // b and c each carry half of a -> d, out of 3 * 2 pairs
+        let diamond = graph(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")]);
+        assert_eq!(centrality(&diamond, "b"), 0.5 / 6.0);
+        assert_eq!(centrality(&diamond, "c"), 0.5 / 6.0);
+
+        // Every path from {a, b} to {x, y} goes through h: 4 of 4 * 3 pairs
+        let hub = graph(&[("a", "h"), ("b", "h"), ("h", "x"), ("h", "y")]);
+        assert_eq!(centrality(&hub, "h"), 4.0 / 12.0);
+        assert!(matches!(betweenness(&hub).1, BetweennessMethod::Exact));
+    }
+
+    #[test]
+    fn betweenness_is_summed_in_source_order() {
+        let mut state = 7u64;
+        let mut next = |bound: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+        let names: Vec<String> = (0..200).map(|i| format!("u{}", i)).collect();
+        let edges: Vec<(&str, &str)> =
+            (0..800).map(|_| (names[next(200) as usize].as_str(), names[next(200) as usize].as_str())).collect();
+        let graph = graph(&edges);
+
+        let n = graph.node_count();
+        let pairs = ((n - 1) * (n - 2)) as f64;
+        let expected: Vec<f64> = graph
+            .node_indices()
+            .map(|source| single_source_dependencies(&graph, source))
+            .fold(vec![0.0; n], |mut total, part| {
+                total.iter_mut().zip(part).for_each(|(t, p)| *t += p);
+                total
+            })
+            .into_iter()
+            .map(|value| value / pairs)
+            .collect();
+        for _ in 0..3 {
+            let (values, _) = betweenness(&graph);
+            assert!(values.iter().zip(&expected).all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+
+    #[test]
+    fn critical_path_follows_the_longest_chain() {
+        let chain = graph(&[("main", "a"), ("a", "b"), ("b", "c"), ("main", "c"), ("d", "c")]);
+        assert_eq!(path(&chain), vec![vec!["c"], vec!["b"], vec!["a"], vec!["main"]]);
+
+        // A cycle counts as one step
+        let cyclic = graph(&[("main", "b"), ("b", "c"), ("c", "b"), ("c", "leaf")]);
+        assert_eq!(path(&cyclic), vec![vec!["leaf"], vec!["b", "c"], vec!["main"]]);
+
+        assert!(path(&DiGraph::new()).is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::reachability::matches_pattern;
+use crate::{batching, DepEdge};
+use anyhow::{bail, Context, Result};
+use kernel_schema::DependencyKind;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A dependency edge to remove or add
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EdgeOverride {
+    pub from: String,
+    pub to: String,
+    /// Kind of an added edge (default: direct_call)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<DependencyKind>,
+    /// Why, e.g. "sched calls yield through a callback after the port"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Keep a unit out of batches below `level`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pin {
+    pub unit: String,
+    pub level: usize,
+}
+
+/// Manual corrections to the dependency graph, read from JSON or YAML
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    #[serde(default)]
+    pub remove_edges: Vec<EdgeOverride>,
+    /// Dependencies the slicer missed
+    #[serde(default)]
+    pub add_edges: Vec<EdgeOverride>,
+    /// Unit IDs or `*` patterns to leave out of the plan
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub pin: Vec<Pin>,
+}
+
+impl Overrides {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read overrides {:?}", path))?;
+        // YAML is a superset of JSON, so this reads either
+        serde_yaml::from_str(&text).with_context(|| format!("Failed to parse overrides {:?}", path))
+    }
+
+    /// Minimum level of each SCC holding a pinned unit; pins on units outside
+    /// the graph are reported
+    pub fn min_levels(
+        &self,
+        graph: &DiGraph<String, DepEdge>,
+        node_to_scc: &HashMap<NodeIndex, usize>,
+    ) -> (HashMap<usize, usize>, Vec<String>) {
+        let nodes: HashMap<&str, NodeIndex> = graph.node_indices().map(|idx| (graph[idx].as_str(), idx)).collect();
+        let mut min_levels: HashMap<usize, usize> = HashMap::new();
+        let mut unplaced = Vec::new();
+        for pin in &self.pin {
+            match nodes.get(pin.unit.as_str()) {
+                Some(idx) => {
+                    let level = min_levels.entry(node_to_scc[idx]).or_insert(0);
+                    *level = (*level).max(pin.level);
+                }
+                None => unplaced.push(pin.unit.clone()),
+            }
+        }
+        (min_levels, unplaced)
+    }
+}
+
+/// What applying the overrides changed
+#[derive(Debug, Default, Serialize)]
+pub struct Applied {
+    pub removed_edges: Vec<(String, String)>,
+    /// Edges to remove that the graph did not have (perhaps filtered by --edge-kinds)
+    pub missing_edges: Vec<(String, String)>,
+    pub added_edges: Vec<(String, String)>,
+    pub excluded: Vec<String>,
+}
+
+/// Apply edge removals, additions and exclusions to `graph`. Naming a unit
+/// that does not exist is an error, so typos do not silently change nothing.
+pub fn apply(graph: &DiGraph<String, DepEdge>, overrides: &Overrides) -> Result<(DiGraph<String, DepEdge>, Applied)> {
+    let nodes: HashMap<&str, NodeIndex> = graph.node_indices().map(|idx| (graph[idx].as_str(), idx)).collect();
+    let mut unknown: Vec<&str> = overrides
+        .remove_edges
+        .iter()
+        .chain(&overrides.add_edges)
+        .flat_map(|edge| [edge.from.as_str(), edge.to.as_str()])
+        .chain(overrides.pin.iter().map(|pin| pin.unit.as_str()))
+        .filter(|id| !nodes.contains_key(id))
+        .collect();
+    unknown.extend(
+        overrides
+            .exclude
+            .iter()
+            .filter(|pattern| !graph.node_weights().any(|id| matches_pattern(pattern, id)))
+            .map(String::as_str),
+    );
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        unknown.dedup();
+        bail!("Overrides name unknown unit(s): {}", unknown.join(", "));
+    }
+
+    let mut graph = graph.clone();
+    let mut applied = Applied::default();
+    for edge in &overrides.remove_edges {
+        let pair = (edge.from.clone(), edge.to.clone());
+        match graph.find_edge(nodes[edge.from.as_str()], nodes[edge.to.as_str()]) {
+            Some(index) => {
+                graph.remove_edge(index);
+                applied.removed_edges.push(pair);
+            }
+            None => applied.missing_edges.push(pair),
+        }
+    }
+    for edge in &overrides.add_edges {
+        let kind = edge.kind.unwrap_or(DependencyKind::DirectCall);
+        graph.update_edge(nodes[edge.from.as_str()], nodes[edge.to.as_str()], DepEdge { kind, call_sites: 1 });
+        applied.added_edges.push((edge.from.clone(), edge.to.clone()));
+    }
+
+    let excluded = |id: &str| overrides.exclude.iter().any(|pattern| matches_pattern(pattern, id));
+    applied.excluded = graph.node_weights().filter(|id| excluded(id)).cloned().collect();
+    applied.excluded.sort();
+    let graph = graph.filter_map(|_, id| (!excluded(id)).then(|| id.clone()), |_, edge| Some(*edge));
+    Ok((graph, applied))
+}
+
+/// The numbers that decide how hard a plan is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PlanShape {
+    pub units: usize,
+    pub super_nodes: usize,
+    pub largest_scc: usize,
+    pub levels: usize,
+    pub batches: usize,
+}
+
+/// Shape of the plan for `graph`, batched as the build order would be
+pub fn shape(
+    graph: &DiGraph<String, DepEdge>,
+    overrides: Option<&Overrides>,
+    max_batch_size: Option<usize>,
+    token_budget: Option<u64>,
+    cost: impl Fn(&str) -> u64,
+) -> Result<PlanShape> {
+    let sccs = tarjan_scc(graph);
+    let (node_to_scc, cond_graph) = batching::condense(graph, &sccs);
+    let min_levels = overrides.map(|o| o.min_levels(graph, &node_to_scc).0).unwrap_or_default();
+    let levels = batching::scc_levels(&cond_graph, &min_levels)?;
+    let scc_units: Vec<Vec<String>> =
+        sccs.iter().map(|scc| scc.iter().map(|idx| graph[*idx].clone()).collect()).collect();
+    let batches = batching::level_batches(&scc_units, &levels, max_batch_size, token_budget, cost, |_| "");
+    Ok(PlanShape {
+        units: graph.node_count(),
+        super_nodes: sccs.iter().filter(|scc| scc.len() > 1).count(),
+        largest_scc: sccs.iter().map(Vec::len).max().unwrap_or(0),
+        levels: levels.iter().max().map_or(0, |max| max + 1),
+        batches: batches.len(),
+    })
+}
+
+/// Everything the overrides report contains
+#[derive(Debug, Serialize)]
+pub struct WhatIf<'a> {
+    pub overrides: &'a Overrides,
+    pub applied: &'a Applied,
+    pub before: PlanShape,
+    pub after: PlanShape,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `main -> sched <-> yield -> swtch`, and `main -> test_helper`
+    fn graph() -> DiGraph<String, DepEdge> {
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<&str, NodeIndex> = ["main", "sched", "yield", "swtch", "test_helper"]
+            .into_iter()
+            .map(|id| (id, graph.add_node(id.to_string())))
+            .collect();
+        let edges = [("main", "sched"), ("sched", "yield"), ("yield", "sched"), ("yield", "swtch"), ("main", "test_helper")];
+        for (from, to) in edges {
+            graph.add_edge(nodes[from], nodes[to], DepEdge { kind: DependencyKind::DirectCall, call_sites: 1 });
+        }
+        graph
+    }
+
+    fn overrides(yaml: &str) -> Overrides {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn edge(graph: &DiGraph<String, DepEdge>, from: &str, to: &str) -> Option<DepEdge> {
+        let find = |id: &str| graph.node_indices().find(|idx| graph[*idx] == id);
+        graph.find_edge(find(from)?, find(to)?).map(|edge| graph[edge])
+    }
+
+    fn shape_of(graph: &DiGraph<String, DepEdge>, overrides: Option<&Overrides>) -> PlanShape {
+        shape(graph, overrides, None, None, |_| 1).unwrap()
+    }
+
+    #[test]
+    fn removing_a_feedback_edge_breaks_the_cycle() {
+        let graph = graph();
+        let fix = overrides("remove_edges: [{from: yield, to: sched, reason: callback after the port}]");
+        let (after, applied) = apply(&graph, &fix).unwrap();
+
+        assert_eq!(applied.removed_edges, vec![("yield".to_string(), "sched".to_string())]);
+        assert!(edge(&after, "yield", "sched").is_none());
+        assert_eq!(tarjan_scc(&graph).len(), 4);
+        assert_eq!(tarjan_scc(&after).len(), 5);
+
+        let before = PlanShape { units: 5, super_nodes: 1, largest_scc: 2, levels: 3, batches: 3 };
+        assert_eq!(shape_of(&graph, None), before);
+        let expected = PlanShape { units: 5, super_nodes: 0, largest_scc: 1, levels: 4, batches: 4 };
+        assert_eq!(shape_of(&after, Some(&fix)), expected);
+    }
+
+    #[test]
+    fn added_edges_default_to_direct_calls() {
+        let graph = graph();
+        let missed =
+            overrides("add_edges: [{from: swtch, to: test_helper}, {from: swtch, to: sched, kind: indirect_call}]");
+        let (after, applied) = apply(&graph, &missed).unwrap();
+
+        assert_eq!(applied.added_edges.len(), 2);
+        assert_eq!(edge(&after, "swtch", "test_helper").unwrap().kind, DependencyKind::DirectCall);
+        assert_eq!(edge(&after, "swtch", "sched").unwrap().kind, DependencyKind::IndirectCall);
+        // swtch joins the cycle
+        let shape = shape_of(&after, Some(&missed));
+        assert_eq!((shape.super_nodes, shape.largest_scc, shape.levels), (1, 3, 3));
+    }
+
+    #[test]
+    fn excluded_units_take_their_edges_with_them() {
+        let graph = graph();
+        let (after, applied) = apply(&graph, &overrides("exclude: ['test_*', swtch]")).unwrap();
+
+        assert_eq!(applied.excluded, vec!["swtch", "test_helper"]);
+        assert_eq!(after.node_count(), 3);
+        assert_eq!(after.edge_count(), 3, "main -> sched and the cycle remain");
+        assert!(after.node_weights().all(|id| id != "swtch" && id != "test_helper"));
+        assert_eq!(shape_of(&after, None).levels, 2);
+    }
+
+    #[test]
+    fn pins_raise_the_unit_and_its_dependents() {
+        let graph = graph();
+        let pin = overrides("pin: [{unit: swtch, level: 2}]");
+        let (after, applied) = apply(&graph, &pin).unwrap();
+        assert_eq!(after.edge_count(), graph.edge_count());
+        assert!(applied.removed_edges.is_empty() && applied.added_edges.is_empty() && applied.excluded.is_empty());
+
+        // swtch at 2, the cycle at 3 and main at 4; test_helper stays at 0
+        let shape = shape_of(&after, Some(&pin));
+        assert_eq!(shape, PlanShape { units: 5, super_nodes: 1, largest_scc: 2, levels: 5, batches: 4 });
+    }
+
+    #[test]
+    fn unknown_units_are_an_error_and_missing_edges_are_reported() {
+        let graph = graph();
+        let typos = overrides(
+            "remove_edges: [{from: sched, to: yeild}]
+add_edges: [{from: mian, to: sched}]
+exclude: ['tset_*']
+pin: [{unit: swtch2, level: 1}]",
+        );
+        let error = apply(&graph, &typos).unwrap_err();
+        assert_eq!(error.to_string(), "Overrides name unknown unit(s): mian, swtch2, tset_*, yeild");
+
+        let (after, applied) = apply(&graph, &overrides("remove_edges: [{from: swtch, to: main}]")).unwrap();
+        assert_eq!(applied.missing_edges, vec![("swtch".to_string(), "main".to_string())]);
+        assert!(applied.removed_edges.is_empty());
+        assert_eq!(after.edge_count(), graph.edge_count());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::DepEdge;
+use kernel_schema::{AtomicUnit, Dependency, DependencyKind, SymbolKind, SymbolTable, UnitKind};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::Dfs;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Serialize)]
+pub struct Caller {
+    pub unit: String,
+    pub call_sites: usize,
+}
+
+/// A dependency target no unit defines: an external binding the port needs
+#[derive(Debug, Serialize)]
+pub struct UnresolvedDependency {
+    pub target: String,
+    pub kinds: BTreeSet<DependencyKind>,
+    /// Total references across all callers
+    pub references: usize,
+    /// The symbol table has a declaration for it (a prototype, for functions)
+    pub declared: bool,
+    pub callers: Vec<Caller>,
+}
+
+/// Collects dependencies the mapper could not turn into edges
+#[derive(Debug, Default)]
+pub struct UnresolvedCollector {
+    by_target: BTreeMap<String, UnresolvedDependency>,
+    /// Calls through function pointers (`p->fn()`, `handler()`), which name no function
+    pointer_calls: usize,
+}
+
+impl UnresolvedCollector {
+    /// Record `dep` of `unit`, which resolved to no unit; `is_unit` tells which IDs do exist
+    pub fn record(&mut self, unit: &str, dep: &Dependency, symbols: &SymbolTable, is_unit: impl Fn(&str) -> bool) {
+        let through_pointer = dep.kind == DependencyKind::IndirectCall
+            && (!dep.target.chars().all(|c| c == '_' || c.is_ascii_alphanumeric())
+                || is_unit(&UnitKind::GlobalData.unit_id(&dep.target))
+                || symbols.contains(&SymbolTable::symbol_id(SymbolKind::Global, &dep.target)));
+        if through_pointer {
+            self.pointer_calls += dep.call_sites;
+            return;
+        }
+        let entry = self.by_target.entry(dep.target.clone()).or_insert_with(|| UnresolvedDependency {
+            target: dep.target.clone(),
+            kinds: BTreeSet::new(),
+            references: 0,
+            declared: false,
+            callers: Vec::new(),
+        });
+        entry.kinds.insert(dep.kind);
+        entry.references += dep.call_sites;
+        entry.declared |= symbols.contains(&SymbolTable::symbol_id(dep.kind.symbol_kind(), &dep.target));
+        match entry.callers.last_mut() {
+            Some(caller) if caller.unit == unit => caller.call_sites += dep.call_sites,
+            _ => entry.callers.push(Caller { unit: unit.to_string(), call_sites: dep.call_sites }),
+        }
+    }
+
+    pub fn pointer_calls(&self) -> usize {
+        self.pointer_calls
+    }
+
+    /// Unresolved targets, most referenced first
+    pub fn finish(self) -> Vec<UnresolvedDependency> {
+        let mut unresolved: Vec<UnresolvedDependency> = self.by_target.into_values().collect();
+        unresolved.sort_by(|a, b| b.references.cmp(&a.references).then_with(|| a.target.cmp(&b.target)));
+        unresolved
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadUnit {
+    pub id: String,
+    pub kind: UnitKind,
+    pub lines: usize,
+}
+
+/// Units no entry point can reach
+#[derive(Debug, Serialize)]
+pub struct DeadCode {
+    /// Entry point IDs or `*` patterns, as configured
+    pub entry_points: Vec<String>,
+    /// Units the entry points matched
+    pub matched: Vec<String>,
+    /// Entry points that matched no unit
+    pub unmatched: Vec<String>,
+    /// No entry point matched, so nothing was checked and `unreachable` is empty
+    pub skipped: bool,
+    pub reachable: usize,
+    pub unreachable: Vec<DeadUnit>,
+    pub unreachable_lines: usize,
+}
+
+/// Everything the dependency report contains
+#[derive(Debug, Serialize)]
+pub struct DependencyReport<'a> {
+    pub unresolved: &'a [UnresolvedDependency],
+    pub pointer_calls: usize,
+    pub dead_code: &'a DeadCode,
+}
+
+/// True if `id` matches `pattern`, where `*` matches any run of characters
+pub fn matches_pattern(pattern: &str, id: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = id.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Units reachable from the entry points, and the dead code left over. If no
+/// entry point matches there is nothing to measure reachability from, and the
+/// result is marked `skipped` rather than calling every unit dead.
+pub fn dead_code(
+    graph: &DiGraph<String, DepEdge>,
+    units: &[AtomicUnit],
+    entry_points: &[String],
+) -> (HashSet<NodeIndex>, DeadCode) {
+    let mut unmatched = Vec::new();
+    let mut starts = Vec::new();
+    for pattern in entry_points {
+        let hits: Vec<NodeIndex> =
+            graph.node_indices().filter(|idx| matches_pattern(pattern, &graph[*idx])).collect();
+        if hits.is_empty() {
+            unmatched.push(pattern.clone());
+        }
+        starts.extend(hits);
+    }
+    starts.sort();
+    starts.dedup();
+    let mut matched: Vec<String> = starts.iter().map(|idx| graph[*idx].clone()).collect();
+    matched.sort();
+    if starts.is_empty() {
+        let dead = DeadCode {
+            entry_points: entry_points.to_vec(),
+            matched,
+            unmatched,
+            skipped: true,
+            reachable: 0,
+            unreachable: Vec::new(),
+            unreachable_lines: 0,
+        };
+        return (HashSet::new(), dead);
+    }
+
+    let mut reachable = HashSet::new();
+    let mut dfs = Dfs::empty(graph);
+    for start in starts {
+        dfs.move_to(start);
+        while let Some(node) = dfs.next(graph) {
+            reachable.insert(node);
+        }
+    }
+
+    let by_id: HashMap<&str, &AtomicUnit> = units.iter().map(|unit| (unit.id.as_str(), unit)).collect();
+    let mut unreachable: Vec<DeadUnit> = graph
+        .node_indices()
+        .filter(|idx| !reachable.contains(idx))
+        .map(|idx| {
+            let unit = by_id[graph[idx].as_str()];
+            DeadUnit { id: unit.id.clone(), kind: unit.kind, lines: unit.code.lines().count() }
+        })
+        .collect();
+    unreachable.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let dead = DeadCode {
+        entry_points: entry_points.to_vec(),
+        matched,
+        unmatched,
+        skipped: false,
+        reachable: reachable.len(),
+        unreachable_lines: unreachable.iter().map(|unit| unit.lines).sum(),
+        unreachable,
+    };
+    (reachable, dead)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::DependencyKind;
+
+    fn graph(edges: &[(&str, &str)], ids: &[&str]) -> (DiGraph<String, DepEdge>, Vec<AtomicUnit>) {
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<&str, NodeIndex> = ids.iter().map(|id| (*id, graph.add_node(id.to_string()))).collect();
+        for (from, to) in edges {
+            graph.add_edge(nodes[from], nodes[to], DepEdge { kind: DependencyKind::DirectCall, call_sites: 1 });
+        }
+        let units = ids.iter().map(|id| AtomicUnit::new(id.to_string(), "{\n}".into(), Vec::new(), Vec::new())).collect();
+        (graph, units)
+    }
+
+    #[test]
+    fn unmatched_entry_points_skip_detection() {
+        let (graph, units) = graph(&[("main", "a")], &["main", "a", "b"]);
+        let (reachable, dead) = dead_code(&graph, &units, &["nope".to_string()]);
+        assert!(dead.skipped);
+        assert!(reachable.is_empty());
+        assert!(dead.unreachable.is_empty());
+        assert_eq!(dead.unreachable_lines, 0);
+        assert_eq!(dead.unmatched, vec!["nope"]);
+    }
+
+    #[test]
+    fn units_no_entry_point_reaches_are_dead() {
+        let (graph, units) = graph(&[("main", "a")], &["main", "a", "b"]);
+        let (reachable, dead) = dead_code(&graph, &units, &["main".to_string(), "nope".to_string()]);
+        assert!(!dead.skipped);
+        assert_eq!(reachable.len(), 2);
+        assert_eq!(dead.unreachable.iter().map(|unit| unit.id.as_str()).collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(dead.unreachable_lines, 2);
+        assert_eq!(dead.unmatched, vec!["nope"]);
+    }
+
+    #[test]
+    fn patterns_match_anywhere_between_stars() {
+        assert!(matches_pattern("sys_*", "sys_fork"));
+        assert!(matches_pattern("*_intr", "uart_intr"));
+        assert!(matches_pattern("global:*calls", "global:syscalls"));
+        assert!(!matches_pattern("sys_*", "fork"));
+        assert!(!matches_pattern("main", "main2"));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use kernel_schema::PlanCost;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Encoding of the mapper's reports (the build order is encoded by its extension)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Compact JSON, one line per report
+    Json,
+    /// Indented JSON
+    Pretty,
+    Yaml,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Json | ReportFormat::Pretty => "json",
+            ReportFormat::Yaml => "yaml",
+        }
+    }
+
+    fn render<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            ReportFormat::Json => serde_json::to_string(value)? + "\n",
+            ReportFormat::Pretty => serde_json::to_string_pretty(value)? + "\n",
+            ReportFormat::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+/// Where reports go, and a record of every file written for the run summary
+#[derive(Debug)]
+pub struct Reports {
+    dir: Option<PathBuf>,
+    format: ReportFormat,
+    written: BTreeMap<String, PathBuf>,
+}
+
+impl Reports {
+    pub fn new(dir: Option<PathBuf>, format: ReportFormat) -> Result<Self> {
+        if let Some(dir) = &dir {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create report directory {:?}", dir))?;
+        }
+        Ok(Self { dir, format, written: BTreeMap::new() })
+    }
+
+    /// Path for report `name`: `explicit` if given, else `<report dir>/<name>.<ext>`,
+    /// else None (the report was not asked for)
+    pub fn path(&self, name: &str, explicit: Option<&Path>) -> Option<PathBuf> {
+        explicit.map(Path::to_path_buf).or_else(|| self.in_dir(&format!("{}.{}", name, self.format.extension())))
+    }
+
+    /// `file_name` inside the report directory, if there is one
+    pub fn in_dir(&self, file_name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(file_name))
+    }
+
+    /// Serialize `value` to `path` in the configured format
+    pub fn write<T: Serialize>(&mut self, name: &str, path: &Path, value: &T) -> Result<()> {
+        fs::write(path, self.format.render(value)?).with_context(|| format!("Failed to write {} {:?}", name, path))?;
+        println!("Mapper: Wrote {} to {:?}", name.replace('_', " "), path);
+        self.record(name, path);
+        Ok(())
+    }
+
+    /// Note a file written elsewhere (the build order, graph exports) for the summary
+    pub fn record(&mut self, name: &str, path: &Path) {
+        self.written.insert(name.to_string(), path.to_path_buf());
+    }
+
+    /// Write the run summary, listing every file this run produced
+    pub fn write_summary(&mut self, explicit: Option<&Path>, mut summary: RunSummary) -> Result<()> {
+        let Some(path) = self.path("summary", explicit) else { return Ok(()) };
+        summary.files = self.written.clone();
+        self.write("summary", &path, &summary)
+    }
+}
+
+/// Headline numbers of a mapper run, for CI
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub units_file: PathBuf,
+    pub total_units: usize,
+    pub scheduled_units: usize,
+    pub total_batches: usize,
+    pub levels: usize,
+    pub super_nodes: usize,
+    pub largest_super_node: usize,
+    pub feedback_arcs: usize,
+    pub unresolved_dependencies: usize,
+    pub pointer_calls: usize,
+    /// None when no entry point matched and dead code detection was skipped
+    pub unreachable_units: Option<usize>,
+    pub estimated_cost: PlanCost,
+    /// Every file the run wrote, by report name
+    pub files: BTreeMap<String, PathBuf>,
+}
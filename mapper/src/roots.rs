@@ -0,0 +1,222 @@
+use crate::DepEdge;
+use anyhow::{bail, Context, Result};
+use kernel_schema::{AtomicUnit, DependencyKind, UnitKind};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::Dfs;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Unit IDs given on the command line and in a file (one per line; blank lines
+/// and `#` comments are skipped), deduplicated in order of appearance
+pub fn collect_ids(ids: &[String], file: Option<&Path>) -> Result<Vec<String>> {
+    let mut all: Vec<String> = ids.iter().map(|id| id.trim().to_string()).collect();
+    if let Some(path) = file {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read unit IDs from {:?}", path))?;
+        all.extend(
+            text.lines()
+                .map(|line| line.split('#').next().unwrap_or("").trim())
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+    }
+    let mut seen = HashSet::new();
+    all.retain(|id| !id.is_empty() && seen.insert(id.clone()));
+    Ok(all)
+}
+
+/// A dependency of the slice on something outside it
+#[derive(Debug, Serialize)]
+pub struct FrontierDependency {
+    pub target: String,
+    pub kinds: BTreeSet<DependencyKind>,
+    /// The target is a unit, left out because --edge-kinds does not follow these
+    /// kinds; otherwise it is external (no unit defines it)
+    pub is_unit: bool,
+    pub needed_by: Vec<String>,
+}
+
+/// What a set of roots transitively requires
+#[derive(Debug, Serialize)]
+pub struct RootSlice {
+    pub roots: Vec<String>,
+    /// Units in the slice, by ID
+    pub included: Vec<String>,
+    pub included_by_kind: BTreeMap<UnitKind, usize>,
+    pub frontier: Vec<FrontierDependency>,
+    /// Lines of C code in the slice, and in the whole units file
+    pub lines: usize,
+    pub total_lines: usize,
+    pub total_units: usize,
+}
+
+impl RootSlice {
+    /// Share of the code base's lines the slice covers
+    pub fn line_fraction(&self) -> f64 {
+        if self.total_lines == 0 {
+            0.0
+        } else {
+            self.lines as f64 / self.total_lines as f64
+        }
+    }
+}
+
+/// Restrict `graph` to the dependency closure of `roots`, returning the
+/// restricted graph and a report of the slice
+pub fn restrict_to_roots(
+    graph: &DiGraph<String, DepEdge>,
+    units: &[AtomicUnit],
+    roots: &[String],
+) -> Result<(DiGraph<String, DepEdge>, RootSlice)> {
+    let nodes: HashMap<&str, NodeIndex> = graph.node_indices().map(|idx| (graph[idx].as_str(), idx)).collect();
+    let unknown: Vec<&str> = roots.iter().map(String::as_str).filter(|root| !nodes.contains_key(root)).collect();
+    if !unknown.is_empty() {
+        bail!("Unknown root unit(s): {}", unknown.join(", "));
+    }
+
+    // One traversal shared by all roots, so common dependencies are visited once
+    let mut keep: HashSet<NodeIndex> = HashSet::new();
+    let mut dfs = Dfs::empty(graph);
+    for root in roots {
+        dfs.move_to(nodes[root.as_str()]);
+        while let Some(node) = dfs.next(graph) {
+            keep.insert(node);
+        }
+    }
+
+    let by_id: HashMap<&str, &AtomicUnit> = units.iter().map(|unit| (unit.id.as_str(), unit)).collect();
+    let mut included: Vec<String> = keep.iter().map(|idx| graph[*idx].clone()).collect();
+    included.sort();
+
+    let mut included_by_kind = BTreeMap::new();
+    let mut frontier: BTreeMap<String, FrontierDependency> = BTreeMap::new();
+    let mut lines = 0;
+    for id in &included {
+        let unit = by_id[id.as_str()];
+        *included_by_kind.entry(unit.kind).or_insert(0) += 1;
+        lines += unit.code.lines().count();
+        for dep in &unit.dependencies {
+            let resolved = dep.resolve_with(|id| nodes.get(id).map(|idx| (id.to_string(), *idx)));
+            let (target, is_unit) = match resolved {
+                Some((_, idx)) if keep.contains(&idx) => continue,
+                // A unit reached only through a kind --edge-kinds does not follow
+                Some((target, _)) => (target, true),
+                None => (dep.target.clone(), false),
+            };
+            let entry = frontier.entry(target.clone()).or_insert_with(|| FrontierDependency {
+                target,
+                kinds: BTreeSet::new(),
+                is_unit,
+                needed_by: Vec::new(),
+            });
+            entry.kinds.insert(dep.kind);
+            if entry.needed_by.last() != Some(id) {
+                entry.needed_by.push(id.clone());
+            }
+        }
+    }
+
+    let restricted = graph.filter_map(
+        |idx, id| keep.contains(&idx).then(|| id.clone()),
+        |_, edge| Some(*edge),
+    );
+    let slice = RootSlice {
+        roots: roots.to_vec(),
+        included,
+        included_by_kind,
+        frontier: frontier.into_values().collect(),
+        lines,
+        total_lines: units.iter().map(|unit| unit.code.lines().count()).sum(),
+        total_units: units.len(),
+    };
+    Ok((restricted, slice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel_schema::Dependency;
+
+    /// `main -> a -> b`, plus `c` that nothing calls. `a` reads `ticks`, a data
+    /// unit the graph has no edge to (as if --edge-kinds left globals out), and
+    /// `b` calls `printf`, which no unit defines.
+    fn program() -> (DiGraph<String, DepEdge>, Vec<AtomicUnit>) {
+        let ticks = UnitKind::GlobalData.unit_id("ticks");
+        let units = vec![
+            AtomicUnit::new("main".into(), "int main(void)\n{\n  a();\n}".into(), vec![Dependency::call("a")], Vec::new()),
+            AtomicUnit::new(
+                "a".into(),
+                "void a(void)\n{\n  b(ticks);\n}".into(),
+                vec![Dependency::call("b"), Dependency::new("ticks", DependencyKind::GlobalRead)],
+                Vec::new(),
+            ),
+            AtomicUnit::new("b".into(), "void b(int n) { printf(\"%d\", n); }".into(), vec![Dependency::call("printf")], Vec::new()),
+            AtomicUnit::new("c".into(), "void c(void) { b(0); }".into(), vec![Dependency::call("b")], Vec::new()),
+            AtomicUnit::new(ticks, "int ticks;".into(), Vec::new(), Vec::new()).with_kind(UnitKind::GlobalData),
+        ];
+        let mut graph = DiGraph::new();
+        let nodes: HashMap<String, NodeIndex> = units.iter().map(|unit| (unit.id.clone(), graph.add_node(unit.id.clone()))).collect();
+        for (from, to) in [("main", "a"), ("a", "b"), ("c", "b")] {
+            graph.add_edge(nodes[from], nodes[to], DepEdge { kind: DependencyKind::DirectCall, call_sites: 1 });
+        }
+        (graph, units)
+    }
+
+    #[test]
+    fn slice_holds_the_dependency_closure_of_the_roots() {
+        let (graph, units) = program();
+        let (restricted, slice) = restrict_to_roots(&graph, &units, &["main".to_string()]).unwrap();
+
+        assert_eq!(slice.included, vec!["a", "b", "main"]);
+        assert_eq!(slice.included_by_kind, BTreeMap::from([(UnitKind::Function, 3)]));
+        let mut kept: Vec<&str> = restricted.node_indices().map(|idx| restricted[idx].as_str()).collect();
+        kept.sort_unstable();
+        assert_eq!(kept, slice.included);
+        assert_eq!(restricted.edge_count(), 2);
+        assert_eq!((slice.lines, slice.total_lines, slice.total_units), (9, 11, 5));
+        assert!((slice.line_fraction() - 9.0 / 11.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn frontier_separates_skipped_units_from_external_symbols() {
+        let (graph, units) = program();
+        let (_, slice) = restrict_to_roots(&graph, &units, &["main".to_string()]).unwrap();
+
+        let frontier: Vec<(&str, bool, Vec<&str>)> = slice
+            .frontier
+            .iter()
+            .map(|dep| (dep.target.as_str(), dep.is_unit, dep.needed_by.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(frontier, vec![("global:ticks", true, vec!["a"]), ("printf", false, vec!["b"])]);
+        assert_eq!(slice.frontier[0].kinds, BTreeSet::from([DependencyKind::GlobalRead]));
+    }
+
+    #[test]
+    fn roots_share_their_common_dependencies() {
+        let (graph, units) = program();
+        let (_, slice) = restrict_to_roots(&graph, &units, &["c".to_string(), "a".to_string()]).unwrap();
+        assert_eq!(slice.roots, vec!["c", "a"]);
+        assert_eq!(slice.included, vec!["a", "b", "c"]);
+        assert_eq!(slice.frontier.iter().find(|dep| dep.target == "printf").unwrap().needed_by, vec!["b"]);
+    }
+
+    #[test]
+    fn unknown_roots_are_an_error() {
+        let (graph, units) = program();
+        let error = restrict_to_roots(&graph, &units, &["main".to_string(), "sys_nope".to_string(), "x".to_string()])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unknown root unit(s): sys_nope, x");
+    }
+
+    #[test]
+    fn ids_from_the_command_line_and_a_file_are_deduplicated() {
+        let path = std::env::temp_dir().join(format!("mapper-roots-{}.txt", std::process::id()));
+        fs::write(&path, "# syscalls\nsys_open\n\n  sys_read  # trailing comment\nmain\n").unwrap();
+        let ids = collect_ids(&["main".to_string(), " sys_read".to_string(), String::new()], Some(&path));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ids.unwrap(), vec!["main", "sys_read", "sys_open"]);
+
+        assert!(collect_ids(&[], Some(Path::new("/nonexistent/roots.txt"))).is_err());
+    }
+}
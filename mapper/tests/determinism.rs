@@ -0,0 +1,143 @@
+use kernel_schema::{AtomicUnit, Dependency, DependencyKind, GeneratorInfo, SymbolTable, UnitKind, UnitsFile};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Deterministic pseudo-random numbers (a 64-bit LCG), so failures reproduce
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % bound as u64) as usize
+    }
+}
+
+/// A kernel-shaped graph: types, globals, call chains and several cycles
+fn units() -> Vec<AtomicUnit> {
+    let mut rng = Lcg(42);
+    let mut units = Vec::new();
+    for t in 0..6 {
+        units.push(
+            AtomicUnit::new(format!("type:t{}", t), format!("struct t{} {{ int a; }};", t), Vec::new(), Vec::new())
+                .with_kind(UnitKind::TypeDefinition),
+        );
+    }
+    for g in 0..4 {
+        units.push(
+            AtomicUnit::new(format!("global:g{}", g), format!("int g{};", g), Vec::new(), Vec::new())
+                .with_kind(UnitKind::GlobalData),
+        );
+    }
+    for f in 0..60 {
+        let mut deps = Vec::new();
+        // Mostly calls down the chain, with some calls back up to make cycles
+        for _ in 0..rng.next(4) {
+            let target = if rng.next(6) == 0 { rng.next(60) } else { rng.next(f.max(1)) };
+            deps.push(Dependency::call(format!("f{:02}", target)));
+        }
+        // Pairs of mutually recursive functions, and one larger cycle f10 -> f13 -> ... -> f10
+        match (f % 7, f) {
+            (0, _) => deps.push(Dependency::call(format!("f{:02}", f + 1))),
+            (1, _) => deps.push(Dependency::call(format!("f{:02}", f - 1))),
+            (_, 10) => deps.push(Dependency::call("f13")),
+            (_, 11..=13) => deps.push(Dependency::call(format!("f{:02}", f - 1))),
+            _ => {}
+        }
+        deps.push(Dependency::new(format!("t{}", rng.next(6)), DependencyKind::TypeUse));
+        if rng.next(3) == 0 {
+            deps.push(Dependency::new(format!("g{}", rng.next(4)), DependencyKind::GlobalWrite));
+        }
+        let body = "if (x && y) { x++; } ".repeat(rng.next(5));
+        units.push(AtomicUnit::new(format!("f{:02}", f), format!("void f{:02}(void) {{ {}}}", f, body), deps, Vec::new()));
+    }
+    units
+}
+
+fn shuffled(mut units: Vec<AtomicUnit>, seed: u64) -> Vec<AtomicUnit> {
+    let mut rng = Lcg(seed);
+    for i in (1..units.len()).rev() {
+        units.swap(i, rng.next(i + 1));
+    }
+    units
+}
+
+/// Run the mapper on `units` and return the build order and cycle analysis it wrote
+fn run_mapper(name: &str, units: Vec<AtomicUnit>, extra_args: &[&str]) -> (String, String) {
+    let dir: PathBuf = std::env::temp_dir().join(format!("mapper-determinism-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let units_path = dir.join("units.json");
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units)
+        .save(&units_path)
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mapper"))
+        .arg("--units")
+        .arg(&units_path)
+        .arg("--report-dir")
+        .arg(&dir)
+        .args(extra_args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "mapper failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let build_order = fs::read_to_string(dir.join("build_order.json")).unwrap();
+    let cycle_analysis = fs::read_to_string(dir.join("cycle_analysis.json")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    (build_order, cycle_analysis)
+}
+
+/// [`units`] plus a `static int helper(void)` defined in two files, which the
+/// slicer emits as two units with the same ID. Their sizes differ, so the
+/// token estimates show which one was taken.
+fn units_with_duplicate_static() -> Vec<AtomicUnit> {
+    let mut units = units();
+    for (file, body) in [("kernel/proc.c", "return 1;".to_string()), ("kernel/vm.c", "n += n * 3; ".repeat(40))] {
+        let code = format!("static int helper(void) {{ {} }}", body);
+        units.push(AtomicUnit::new("helper".into(), code, Vec::new(), Vec::new()).with_source_file(file));
+    }
+    units.push(AtomicUnit::new(
+        "uses_helper".into(),
+        "void uses_helper(void) { helper(); }".into(),
+        vec![Dependency::call("helper")],
+        Vec::new(),
+    ));
+    units
+}
+
+fn assert_order_independent(name: &str, units: fn() -> Vec<AtomicUnit>, extra_args: &[&str]) {
+    let (build_order, cycle_analysis) = run_mapper(&format!("{}-original", name), units(), extra_args);
+    assert!(cycle_analysis.contains("super_node"), "the test graph should have cycles");
+
+    let mut reversed = units();
+    reversed.reverse();
+    let mut variants = vec![("again", units()), ("reversed", reversed)];
+    variants.extend((1..=3).map(|seed| ("shuffled", shuffled(units(), seed))));
+    for (i, (label, variant)) in variants.into_iter().enumerate() {
+        let (other_order, other_analysis) = run_mapper(&format!("{}-{}-{}", name, label, i), variant, extra_args);
+        assert_eq!(other_order, build_order, "build order differs for the {} input", label);
+        assert_eq!(other_analysis, cycle_analysis, "cycle analysis differs for the {} input", label);
+    }
+}
+
+#[test]
+fn shuffled_units_give_identical_output() {
+    assert_order_independent("default", units, &[]);
+}
+
+#[test]
+fn batch_limits_do_not_depend_on_input_order() {
+    assert_order_independent("limits", units, &["--token-budget", "2000", "--max-batch-size", "5"]);
+}
+
+#[test]
+fn edge_kind_filters_do_not_depend_on_input_order() {
+    assert_order_independent("calls", units, &["--edge-kinds", "direct-call"]);
+}
+
+#[test]
+fn units_sharing_an_id_are_ordered_by_file() {
+    assert_order_independent("duplicates", units_with_duplicate_static, &[]);
+    let (build_order, _) = run_mapper("duplicates-count", units_with_duplicate_static(), &[]);
+    assert_eq!(build_order.matches("\"helper\"").count(), 2, "both definitions are scheduled");
+}
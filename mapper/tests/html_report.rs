@@ -0,0 +1,45 @@
+use kernel_schema::{AtomicUnit, Dependency, GeneratorInfo, SymbolTable, UnitsFile};
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn html_report_is_self_contained_and_escapes_code() {
+    let dir = std::env::temp_dir().join(format!("mapper-html-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let units_path = dir.join("units.json");
+    let units = vec![
+        AtomicUnit::new(
+            "a".into(),
+            "void a(void) { b(); /* </script><script>alert(1) */ }".into(),
+            vec![Dependency::call("b")],
+            Vec::new(),
+        ),
+        AtomicUnit::new(
+            "b".into(),
+            "void b(void) { a(); }".into(),
+            vec![Dependency::call("a")],
+            vec!["struct s { int x; };".into()],
+        ),
+    ];
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units).save(&units_path).unwrap();
+
+    let html_path = dir.join("plan.html");
+    let output = Command::new(env!("CARGO_BIN_EXE_mapper"))
+        .arg("--units")
+        .arg(&units_path)
+        .arg("--output")
+        .arg(dir.join("build_order.json"))
+        .arg("--html")
+        .arg(&html_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "mapper failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let page = fs::read_to_string(&html_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    // Only the page's own two script elements end; the C code stays inside the data
+    assert_eq!(page.matches("</script>").count(), 2);
+    assert!(page.contains(r"\u003c/script>\u003cscript>alert(1)"));
+    assert!(page.contains("struct s { int x; };"));
+    assert!(!page.contains("src=") && !page.contains("href="), "the page must not load anything");
+}
@@ -0,0 +1,93 @@
+use kernel_schema::{AtomicUnit, Dependency, GeneratorInfo, SymbolTable, UnitsFile};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn unit(id: &str, calls: &[&str]) -> AtomicUnit {
+    let deps = calls.iter().map(|callee| Dependency::call(*callee)).collect();
+    AtomicUnit::new(id.into(), format!("void {}(void) {{}}", id), deps, Vec::new())
+}
+
+fn save(path: &Path, units: Vec<AtomicUnit>) {
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units).save(path).unwrap();
+}
+
+fn mapper(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mapper"))
+        .arg("--units")
+        .arg(dir.join("units.json"))
+        .arg("--output")
+        .arg(dir.join("build_order.json"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Units of each batch, in plan order
+fn batches(dir: &Path) -> Vec<Vec<String>> {
+    let order: Value = serde_json::from_str(&fs::read_to_string(dir.join("build_order.json")).unwrap()).unwrap();
+    order["batches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|batch| batch["units"].as_array().unwrap().iter().map(|id| id.as_str().unwrap().to_string()).collect())
+        .collect()
+}
+
+/// `main -> sched <-> yield -> memmove`, `main -> exit`, and `ls -> printf`
+fn kernel() -> Vec<AtomicUnit> {
+    vec![
+        unit("main", &["sched", "exit"]),
+        unit("sched", &["yield"]),
+        unit("yield", &["sched", "memmove"]),
+        unit("memmove", &[]),
+        unit("exit", &[]),
+        unit("ls", &["printf"]),
+        unit("printf", &[]),
+    ]
+}
+
+#[test]
+fn only_changed_units_and_their_dependents_are_planned() {
+    let dir = std::env::temp_dir().join(format!("mapper-impact-plan-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    save(&dir.join("units.json"), kernel());
+
+    let output = mapper(&dir, &["--changed", "memmove"]);
+    assert!(output.status.success(), "mapper failed: {}", String::from_utf8_lossy(&output.stderr));
+    let plan = batches(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(plan, vec![vec!["memmove"], vec!["sched", "yield"], vec!["main"]]);
+}
+
+/// A changed unit outside a --roots slice is skipped the same way whether it
+/// was named with --changed or found by --changed-since; a typo still fails
+#[test]
+fn changed_units_outside_the_roots_are_ignored() {
+    let dir = std::env::temp_dir().join(format!("mapper-impact-roots-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut old = kernel();
+    old.retain(|unit| unit.id != "printf" && unit.id != "memmove");
+    old.extend([unit("printf", &["putc"]), unit("memmove", &["panic"])]);
+    save(&dir.join("old.json"), old);
+    save(&dir.join("units.json"), kernel());
+
+    let named = mapper(&dir, &["--roots", "main", "--changed", "memmove", "--changed", "printf"]);
+    assert!(named.status.success(), "mapper failed: {}", String::from_utf8_lossy(&named.stderr));
+    assert!(String::from_utf8_lossy(&named.stdout).contains("Ignoring 1 changed unit(s) outside this run"));
+    let named_plan = batches(&dir);
+
+    let since = mapper(&dir, &["--roots", "main", "--changed-since", "old.json"]);
+    assert!(since.status.success(), "mapper failed: {}", String::from_utf8_lossy(&since.stderr));
+    assert!(String::from_utf8_lossy(&since.stdout).contains("Ignoring 1 changed unit(s) outside this run"));
+    let since_plan = batches(&dir);
+
+    let typo = mapper(&dir, &["--roots", "main", "--changed", "memmov"]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(named_plan, vec![vec!["memmove"], vec!["sched", "yield"], vec!["main"]]);
+    assert_eq!(since_plan, named_plan);
+    assert!(!typo.status.success());
+    assert!(String::from_utf8_lossy(&typo.stderr).contains("Changed unit(s) not in the dependency graph: memmov"));
+}
@@ -0,0 +1,59 @@
+use kernel_schema::{AtomicUnit, Dependency, GeneratorInfo, SymbolTable, UnitsFile};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn unit(id: &str, file: &str, calls: &[&str]) -> AtomicUnit {
+    let deps = calls.iter().map(|callee| Dependency::call(*callee)).collect();
+    AtomicUnit::new(id.into(), format!("void {}(void) {{}}", id), deps, Vec::new()).with_source_file(file)
+}
+
+fn mapper(dir: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mapper"))
+        .arg("--units")
+        .arg(dir.join("units.json"))
+        .arg("--output")
+        .arg(dir.join("build_order.json"))
+        .arg("--layering")
+        .arg(dir.join("rules.yaml"))
+        .args(extra_args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// The run fails only while a violation is missing from the baseline
+#[test]
+fn new_layering_violations_fail_the_run() {
+    let dir = std::env::temp_dir().join(format!("mapper-layering-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("rules.yaml"), "modules: {lib: [lib/], kernel: [kernel/]}\nlayers: [lib, kernel]\n").unwrap();
+    let save = |units| {
+        UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units)
+            .save(&dir.join("units.json"))
+            .unwrap()
+    };
+
+    save(vec![unit("fork", "kernel/proc.c", &["memmove"]), unit("memmove", "lib/string.c", &[])]);
+    let clean = mapper(&dir, &[]);
+    assert!(clean.status.success(), "mapper failed: {}", String::from_utf8_lossy(&clean.stderr));
+
+    save(vec![
+        unit("fork", "kernel/proc.c", &["memmove"]),
+        unit("memmove", "lib/string.c", &["panic"]),
+        unit("panic", "kernel/printf.c", &[]),
+    ]);
+    let broken = mapper(&dir, &["--layering-report", "layering.json"]);
+    assert!(!broken.status.success());
+    assert!(String::from_utf8_lossy(&broken.stderr).contains("1 new layering violation(s)"));
+    let report: Value = serde_json::from_str(&fs::read_to_string(dir.join("layering.json")).unwrap()).unwrap();
+    let violation = &report["violations"][0];
+    assert_eq!((violation["caller"].as_str(), violation["callee"].as_str()), (Some("memmove"), Some("panic")));
+    assert!(dir.join("build_order.json").exists(), "the plan is still written");
+
+    // Accepted through the baseline, the same violation no longer fails the run
+    let accepted = mapper(&dir, &["--layering-baseline", "layering.json"]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(accepted.status.success(), "mapper failed: {}", String::from_utf8_lossy(&accepted.stderr));
+}
@@ -0,0 +1,40 @@
+use kernel_schema::{AtomicUnit, Dependency, GeneratorInfo, SymbolTable, UnitsFile};
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+
+fn unit(id: &str, calls: &[&str]) -> AtomicUnit {
+    let deps = calls.iter().map(|callee| Dependency::call(*callee)).collect();
+    AtomicUnit::new(id.into(), format!("void {}(void) {{}}", id), deps, Vec::new())
+}
+
+/// A pin that lifts a unit more than one level above its dependencies leaves a
+/// gap in the levels; metrics must still find the critical path
+#[test]
+fn pins_with_gaps_work_with_metrics() {
+    let dir = std::env::temp_dir().join(format!("mapper-overrides-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let units_path = dir.join("units.json");
+    let units = vec![unit("main", &["a"]), unit("a", &["b"]), unit("b", &[])];
+    UnitsFile::new(GeneratorInfo::new("test", "0"), "src", "", SymbolTable::new(), units).save(&units_path).unwrap();
+    fs::write(dir.join("ov.yaml"), "pin: [{unit: b, level: 2}]\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mapper"))
+        .arg("--units")
+        .arg(&units_path)
+        .arg("--overrides")
+        .arg(dir.join("ov.yaml"))
+        .arg("--report-dir")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "mapper failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let metrics: Value = serde_json::from_str(&fs::read_to_string(dir.join("metrics.json")).unwrap()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(metrics["critical_path"], serde_json::json!([["b"], ["a"], ["main"]]));
+    let depth = |id: &str| {
+        metrics["unit_metrics"].as_array().unwrap().iter().find(|m| m["id"] == id).unwrap()["depth"].as_u64().unwrap()
+    };
+    assert_eq!((depth("b"), depth("a"), depth("main")), (2, 3, 4));
+}
@@ -0,0 +1,750 @@
+use crate::{Diagnostic, SliceConfig, TypeRegistry};
+use anyhow::{Context, Result};
+use kernel_schema::{AtomicUnit, Dependency, DependencyKind, SourceLocation, Symbol, SymbolKind, SymbolTable, UnitKind};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use tree_sitter::{Parser as TSParser, Query, QueryCursor, Tree};
+
+/// Parse a C file, reporting (rather than failing on) files tree-sitter cannot handle
+pub(crate) fn parse_file(path: &Path, code_raw: &str, diagnostics: &mut Vec<Diagnostic>) -> Result<Option<Tree>> {
+    let mut parser = TSParser::new();
+    parser.set_language(tree_sitter_c::language())
+        .context("Error loading C grammar")?;
+
+    let tree = parser.parse(code_raw, None);
+    if tree.is_none() {
+        diagnostics.push(Diagnostic::warning(path, "Failed to parse, skipping"));
+    }
+    Ok(tree)
+}
+
+/// The first node tree-sitter could not parse, in source order
+fn first_syntax_error(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    if node.is_error() || node.is_missing() {
+        return Some(node);
+    }
+    let mut cursor = node.walk();
+    node.children(&mut cursor).filter(|child| child.has_error()).find_map(first_syntax_error)
+}
+
+/// First pass: collect type, macro, global and prototype definitions from a file
+pub(crate) fn collect_types_from_file(
+    path: &Path,
+    registry: &mut TypeRegistry,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let code_raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read file {:?}", path))?;
+    let code = code_raw.as_bytes();
+
+    let tree = match parse_file(path, &code_raw, diagnostics)? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    let root_node = tree.root_node();
+
+    // Query for type definitions (struct, union, enum, typedef)
+    let type_query = Query::new(tree_sitter_c::language(), "
+        (struct_specifier) @type
+        (union_specifier) @type
+        (enum_specifier) @type
+        (type_definition) @type
+    ").context("Error creating type query")?;
+
+    // Query for #include directives
+    let include_query = Query::new(tree_sitter_c::language(),
+        "(preproc_include path: (_) @path)"
+    ).context("Error creating include query")?;
+
+    let mut cursor = QueryCursor::new();
+
+    // Extract #include directives
+    let matches = cursor.matches(&include_query, root_node, code);
+    for m in matches {
+        for capture in m.captures {
+            if let Ok(text) = capture.node.utf8_text(code) {
+                registry.register_include(path.to_path_buf(), text.to_string());
+            }
+        }
+    }
+
+    // Extract type definitions
+    let mut cursor = QueryCursor::new();
+    let matches = cursor.matches(&type_query, root_node, code);
+    for m in matches {
+        for capture in m.captures {
+            let node = capture.node;
+            if let Some(name) = extract_type_name(node, code)
+                && let Ok(def_text) = node.utf8_text(code)
+            {
+                registry.register_type(name, def_text.to_string(), path.to_path_buf());
+            }
+        }
+    }
+
+    // Query for #define macros, both object-like and function-like
+    let macro_query = Query::new(tree_sitter_c::language(), "
+        (preproc_def name: (identifier) @name) @def
+        (preproc_function_def name: (identifier) @name) @def
+    ").context("Error creating macro query")?;
+
+    let mut cursor = QueryCursor::new();
+    let matches = cursor.matches(&macro_query, root_node, code);
+    for m in matches {
+        let mut name_text: Option<String> = None;
+        let mut def_text: Option<String> = None;
+        for capture in m.captures {
+            let node = capture.node;
+            if node.kind() == "identifier" {
+                if let Ok(text) = node.utf8_text(code) {
+                    name_text = Some(text.to_string());
+                }
+            } else if (node.kind() == "preproc_def" || node.kind() == "preproc_function_def")
+                && let Ok(text) = node.utf8_text(code)
+            {
+                def_text = Some(text.to_string());
+            }
+        }
+        if let (Some(name), Some(def)) = (name_text, def_text) {
+            registry.register_macro(name, def, path.to_path_buf());
+        }
+    }
+
+    // File-scope declarations: globals and prototypes, plus the names of defined functions
+    for i in 0..root_node.named_child_count() {
+        let Some(decl) = root_node.named_child(i) else { continue };
+        if decl.kind() == "function_definition" {
+            if let Some(name) = extract_function_name(decl, code) {
+                registry.register_function(name);
+            }
+            continue;
+        }
+        if decl.kind() != "declaration" {
+            continue;
+        }
+        let Ok(decl_text) = decl.utf8_text(code) else { continue };
+
+        let mut cursor = decl.walk();
+        for declarator in decl.children_by_field_name("declarator", &mut cursor) {
+            let Some(name) = find_identifier_safe(declarator, code) else { continue };
+            if is_prototype_declarator(declarator) {
+                registry.register_prototype(name, decl_text.to_string(), path.to_path_buf());
+            } else {
+                registry.register_global(name, decl_text.to_string(), path.to_path_buf());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// True if a top-level declarator declares a function rather than a variable.
+/// Function pointers (`void (*handler)(void)`) are variables.
+pub(crate) fn is_prototype_declarator(node: tree_sitter::Node) -> bool {
+    match node.kind() {
+        "function_declarator" => node
+            .child_by_field_name("declarator")
+            .is_some_and(|inner| inner.kind() != "parenthesized_declarator"),
+        "pointer_declarator" => node
+            .child_by_field_name("declarator")
+            .is_some_and(is_prototype_declarator),
+        _ => false,
+    }
+}
+
+/// Extract the name from a type definition node
+fn extract_type_name(node: tree_sitter::Node, code: &[u8]) -> Option<String> {
+    match node.kind() {
+        "struct_specifier" | "union_specifier" | "enum_specifier" => {
+            node.child_by_field_name("name")
+                .and_then(|n| n.utf8_text(code).ok())
+                .map(|s| s.to_string())
+        }
+        "type_definition" => {
+            // For typedef, the name is in the declarator
+            node.child_by_field_name("declarator")
+                .and_then(|n| extract_identifier_text(n, code))
+        }
+        _ => None
+    }
+}
+
+/// Recursively find an identifier's text
+fn extract_identifier_text(node: tree_sitter::Node, code: &[u8]) -> Option<String> {
+    if node.kind() == "identifier" || node.kind() == "type_identifier" {
+        return node.utf8_text(code).ok().map(|s| s.to_string());
+    }
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i)
+            && let Some(text) = extract_identifier_text(child, code)
+        {
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// Second pass: extract units from a file using the global type registry.
+/// Context is recorded as references into `symbols` rather than copied per unit.
+pub(crate) fn extract_units_from_file(
+    path: &Path,
+    config: &SliceConfig,
+    units: &mut Vec<AtomicUnit>,
+    type_registry: &TypeRegistry,
+    symbols: &mut SymbolTable,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let code_raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read file {:?}", path))?;
+    let code = code_raw.as_bytes();
+
+    let tree = match parse_file(path, &code_raw, diagnostics)? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    let root_node = tree.root_node();
+    // tree-sitter recovers around syntax errors, so the rest of the file is still sliced
+    if let Some(error) = first_syntax_error(root_node) {
+        diagnostics.push(Diagnostic::warning(
+            path,
+            format!("Syntax error at line {}, definitions around it may be missing", error.start_position().row + 1),
+        ));
+    }
+
+    let mut out = FileUnits {
+        path,
+        source_root: &config.source,
+        file: crate::relative_source(&config.source, path),
+        registry: type_registry,
+        symbols,
+        units,
+    };
+
+    if config.emits(UnitKind::Function) {
+        extract_functions(&mut out, root_node, code, diagnostics)?;
+    }
+    if config.emits(UnitKind::TypeDefinition) {
+        extract_type_definitions(&mut out, root_node, code)?;
+    }
+    if config.emits(UnitKind::GlobalData) {
+        extract_global_data(&mut out, root_node, code);
+    }
+    if config.emits(UnitKind::FunctionMacro) {
+        extract_function_macros(&mut out, root_node, code)?;
+    }
+    if config.emits(UnitKind::InlineAsm) {
+        extract_inline_asm(&mut out, root_node, code);
+    }
+
+    Ok(())
+}
+
+/// Units extracted from one file, plus what is needed to resolve their context
+struct FileUnits<'a> {
+    path: &'a Path,
+    source_root: &'a Path,
+    /// `path` relative to the slicing root
+    file: String,
+    registry: &'a TypeRegistry,
+    symbols: &'a mut SymbolTable,
+    units: &'a mut Vec<AtomicUnit>,
+}
+
+impl FileUnits<'_> {
+    /// Typed dependencies of `node`
+    fn dependencies(&self, node: tree_sitter::Node, code: &[u8]) -> Vec<Dependency> {
+        let mut collector = DependencyCollector::new(self.registry, &self.file);
+        collector.visit(node, code);
+        collector.finish()
+    }
+
+    /// True if the registry chose the definition at `node` in this file for `name`.
+    /// Headers seen by several files and forward declarations produce one unit, not many.
+    fn is_registered(&self, definition: Option<&String>, source: Option<&Path>, node: tree_sitter::Node, code: &[u8]) -> bool {
+        source == Some(self.path) && definition.map(String::as_bytes) == Some(&code[node.byte_range()])
+    }
+
+    fn push(&mut self, kind: UnitKind, id: String, code: String, mut dependencies: Vec<Dependency>) {
+        // A definition mentioning its own name (`struct proc { struct proc *parent; }`)
+        // is not a dependency; recursive functions keep their self-calls
+        if kind != UnitKind::Function {
+            dependencies.retain(|dep| dep.unit_id() != id);
+        }
+        if self.units.iter().any(|unit| unit.id == id && unit.kind == kind && kind != UnitKind::Function) {
+            return;
+        }
+
+        let symbol_refs = self.symbol_refs(&dependencies, &id);
+        let mut unit = AtomicUnit::new(id, code, dependencies, Vec::new())
+            .with_kind(kind)
+            .with_source_file(self.file.clone());
+        unit.symbol_refs = symbol_refs;
+        self.units.push(unit);
+    }
+
+    /// Resolve context against the global registry: types, then macros and
+    /// globals the unit mentions, then prototypes of the callees
+    fn symbol_refs(&mut self, dependencies: &[Dependency], own_id: &str) -> Vec<String> {
+        let registry = self.registry;
+        let source_root = self.source_root;
+        let symbols = &mut *self.symbols;
+        let mut symbol_refs = Vec::new();
+        let mut add_ref = |kind: SymbolKind, name: &str, definition: Option<&String>, source: Option<&Path>| {
+            if let Some(definition) = definition {
+                let id = symbols.insert(Symbol {
+                    kind,
+                    name: name.to_string(),
+                    definition: definition.clone(),
+                    source_file: source.map(|s| crate::relative_source(source_root, s)),
+                });
+                // A type or global unit does not need its own definition as context
+                if id != own_id && !symbol_refs.contains(&id) {
+                    symbol_refs.push(id);
+                }
+            }
+        };
+        let targets = |kinds: &'static [DependencyKind]| {
+            dependencies.iter().filter(move |d| kinds.contains(&d.kind)).map(|d| d.target.as_str())
+        };
+        for type_name in targets(&[DependencyKind::TypeUse]) {
+            add_ref(SymbolKind::Type, type_name, registry.get_type(type_name), registry.type_source(type_name));
+        }
+        for macro_name in targets(&[DependencyKind::MacroUse]) {
+            add_ref(SymbolKind::Macro, macro_name, registry.get_macro(macro_name), registry.macro_source(macro_name));
+        }
+        for global in targets(&[DependencyKind::GlobalRead, DependencyKind::GlobalWrite, DependencyKind::IndirectCall]) {
+            add_ref(SymbolKind::Global, global, registry.get_global(global), registry.global_source(global));
+        }
+        for callee in targets(&[DependencyKind::DirectCall, DependencyKind::IndirectCall]) {
+            add_ref(SymbolKind::Prototype, callee, registry.get_prototype(callee), registry.prototype_source(callee));
+        }
+        symbol_refs
+    }
+}
+
+fn extract_functions(out: &mut FileUnits, root_node: tree_sitter::Node, code: &[u8], diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    let func_query = Query::new(tree_sitter_c::language(), "(function_definition) @func")
+        .context("Error creating func query")?;
+
+    let mut cursor = QueryCursor::new();
+    let matches = cursor.matches(&func_query, root_node, code);
+
+    for m in matches {
+        for capture in m.captures {
+            let node = capture.node;
+
+            // Extract function name safely (no unwrap)
+            let name = match extract_function_name(node, code) {
+                Some(name) => name,
+                None => {
+                    diagnostics.push(Diagnostic::warning(
+                        out.path,
+                        format!("Could not resolve function name at line {}", node.start_position().row + 1),
+                    ));
+                    "unknown_fn".to_string()
+                }
+            };
+
+            let func_code = node.utf8_text(code)
+                .with_context(|| format!("Failed to extract function code for {}", name))?
+                .to_string();
+
+            // Trace typed dependencies (no unwrap)
+            let dependencies = out.dependencies(node, code);
+            out.push(UnitKind::Function, name, func_code, dependencies);
+        }
+    }
+
+    Ok(())
+}
+
+/// Struct, union and enum definitions with a body, and typedefs
+fn extract_type_definitions(out: &mut FileUnits, root_node: tree_sitter::Node, code: &[u8]) -> Result<()> {
+    let type_query = Query::new(tree_sitter_c::language(), "
+        (struct_specifier body: (_)) @type
+        (union_specifier body: (_)) @type
+        (enum_specifier body: (_)) @type
+        (type_definition) @type
+    ").context("Error creating type query")?;
+
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&type_query, root_node, code) {
+        for capture in m.captures {
+            let node = capture.node;
+            let Some(name) = extract_type_name(node, code) else { continue };
+            if !out.is_registered(out.registry.get_type(&name), out.registry.type_source(&name), node, code) {
+                continue;
+            }
+            let Ok(text) = node.utf8_text(code) else { continue };
+            let dependencies = out.dependencies(node, code);
+            out.push(UnitKind::TypeDefinition, UnitKind::TypeDefinition.unit_id(&name), text.to_string(), dependencies);
+        }
+    }
+    Ok(())
+}
+
+/// Defining (non-`extern`) declarations of file-scope variables
+fn extract_global_data(out: &mut FileUnits, root_node: tree_sitter::Node, code: &[u8]) {
+    for i in 0..root_node.named_child_count() {
+        let Some(decl) = root_node.named_child(i) else { continue };
+        if decl.kind() != "declaration" || is_extern(decl, code) {
+            continue;
+        }
+        let Ok(decl_text) = decl.utf8_text(code) else { continue };
+
+        let mut cursor = decl.walk();
+        let declarators: Vec<_> = decl.children_by_field_name("declarator", &mut cursor).collect();
+        for declarator in declarators {
+            if is_prototype_declarator(declarator) {
+                continue;
+            }
+            let Some(name) = find_identifier_safe(declarator, code) else { continue };
+            if !out.is_registered(out.registry.get_global(&name), out.registry.global_source(&name), decl, code) {
+                continue;
+            }
+            let dependencies = out.dependencies(decl, code);
+            out.push(UnitKind::GlobalData, UnitKind::GlobalData.unit_id(&name), decl_text.to_string(), dependencies);
+        }
+    }
+}
+
+/// `#define NAME(args) body`; object-like macros stay symbol-table context
+fn extract_function_macros(out: &mut FileUnits, root_node: tree_sitter::Node, code: &[u8]) -> Result<()> {
+    let macro_query = Query::new(tree_sitter_c::language(), "(preproc_function_def) @def")
+        .context("Error creating macro query")?;
+
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&macro_query, root_node, code) {
+        for capture in m.captures {
+            let node = capture.node;
+            let Some(name) = node.child_by_field_name("name").and_then(|n| n.utf8_text(code).ok()) else { continue };
+            if !out.is_registered(out.registry.get_macro(name), out.registry.macro_source(name), node, code) {
+                continue;
+            }
+            let Ok(text) = node.utf8_text(code) else { continue };
+
+            // The body is unparsed text; scan it for identifiers other than the parameters
+            let params = macro_params(node, code);
+            let mut collector = DependencyCollector::new(out.registry, &out.file);
+            if let Some(body) = node.child_by_field_name("value")
+                && let Ok(body_text) = body.utf8_text(code)
+            {
+                collector.visit_text(body_text, &params, body);
+            }
+            out.push(UnitKind::FunctionMacro, UnitKind::FunctionMacro.unit_id(name), text.trim_end().to_string(), collector.finish());
+        }
+    }
+    Ok(())
+}
+
+/// Parameter names of a `#define NAME(args)`, empty for object-like macros
+pub(crate) fn macro_params<'a>(node: tree_sitter::Node, code: &'a [u8]) -> Vec<&'a str> {
+    node.child_by_field_name("parameters")
+        .and_then(|p| p.utf8_text(code).ok())
+        .map(|p| p.trim_matches(|c| c == '(' || c == ')').split(',').map(str::trim).collect())
+        .unwrap_or_default()
+}
+
+/// File-scope `asm(...)` blocks, identified by file and line
+fn extract_inline_asm(out: &mut FileUnits, root_node: tree_sitter::Node, code: &[u8]) {
+    for i in 0..root_node.named_child_count() {
+        let Some(stmt) = root_node.named_child(i) else { continue };
+        if stmt.kind() != "expression_statement"
+            || stmt.named_child(0).map(|n| n.kind()) != Some("gnu_asm_expression")
+        {
+            continue;
+        }
+        let Ok(text) = stmt.utf8_text(code) else { continue };
+        let name = format!("{}:{}", out.file, stmt.start_position().row + 1);
+        let dependencies = out.dependencies(stmt, code);
+        out.push(UnitKind::InlineAsm, UnitKind::InlineAsm.unit_id(&name), text.to_string(), dependencies);
+    }
+}
+
+/// True if a declaration has an `extern` storage class
+fn is_extern(decl: tree_sitter::Node, code: &[u8]) -> bool {
+    (0..decl.named_child_count())
+        .filter_map(|i| decl.named_child(i))
+        .any(|child| child.kind() == "storage_class_specifier" && child.utf8_text(code) == Ok("extern"))
+}
+
+/// Safely extract function name without unwrap
+fn extract_function_name(node: tree_sitter::Node, code: &[u8]) -> Option<String> {
+    if node.kind() == "function_definition"
+        && let Some(decl) = node.child_by_field_name("declarator")
+    {
+        return find_identifier_safe(decl, code);
+    }
+    find_identifier_safe(node, code)
+}
+
+/// Safely find identifier without unwrap
+fn find_identifier_safe(node: tree_sitter::Node, code: &[u8]) -> Option<String> {
+    if node.kind() == "identifier" {
+        return node.utf8_text(code).ok().map(|s| s.to_string());
+    }
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i)
+            && let Some(name) = find_identifier_safe(child, code)
+        {
+            return Some(name);
+        }
+    }
+    None
+}
+
+/// One reference seen by a [`DependencyCollector`] that keeps its sites
+pub(crate) struct Reference {
+    pub name: String,
+    /// None for an identifier the registry does not classify (an enum constant,
+    /// or a name defined nowhere)
+    pub kind: Option<DependencyKind>,
+    pub location: SourceLocation,
+}
+
+/// Walks a function body and classifies every reference into a typed [`Dependency`]
+pub(crate) struct DependencyCollector<'a> {
+    registry: &'a TypeRegistry,
+    file: &'a str,
+    dependencies: Vec<Dependency>,
+    /// (target, kind) -> position in `dependencies`
+    seen: HashMap<(String, DependencyKind), usize>,
+    /// Parameters and local variables, which shadow globals and functions of the same name
+    locals: HashSet<String>,
+    /// Every reference, for the symbol index; None when only dependencies are wanted
+    sites: Option<Vec<Reference>>,
+}
+
+impl<'a> DependencyCollector<'a> {
+    pub(crate) fn new(registry: &'a TypeRegistry, file: &'a str) -> Self {
+        Self {
+            registry,
+            file,
+            dependencies: Vec::new(),
+            seen: HashMap::new(),
+            locals: HashSet::new(),
+            sites: None,
+        }
+    }
+
+    /// Also keep the location of every reference, not just the first per dependency
+    pub(crate) fn with_sites(mut self) -> Self {
+        self.sites = Some(Vec::new());
+        self
+    }
+
+    /// Dependencies in order of first reference
+    pub(crate) fn finish(self) -> Vec<Dependency> {
+        self.dependencies
+    }
+
+    /// Every reference in source order (empty unless built [`with_sites`](Self::with_sites))
+    pub(crate) fn into_sites(self) -> Vec<Reference> {
+        self.sites.unwrap_or_default()
+    }
+
+    fn location(&self, node: tree_sitter::Node) -> SourceLocation {
+        let pos = node.start_position();
+        SourceLocation {
+            file: self.file.to_string(),
+            line: pos.row + 1,
+            column: pos.column + 1,
+        }
+    }
+
+    fn record(&mut self, target: String, kind: DependencyKind, node: tree_sitter::Node) {
+        let location = self.location(node);
+        if let Some(sites) = &mut self.sites {
+            sites.push(Reference { name: target.clone(), kind: Some(kind), location: location.clone() });
+        }
+        if let Some(&i) = self.seen.get(&(target.clone(), kind)) {
+            self.dependencies[i].call_sites += 1;
+            return;
+        }
+        self.seen.insert((target.clone(), kind), self.dependencies.len());
+        self.dependencies.push(Dependency {
+            target,
+            kind,
+            call_sites: 1,
+            location: Some(location),
+        });
+    }
+
+    /// Note an identifier the registry does not know, for the symbol index
+    fn record_unclassified(&mut self, name: &str, node: tree_sitter::Node) {
+        let location = self.location(node);
+        if let Some(sites) = &mut self.sites {
+            sites.push(Reference { name: name.to_string(), kind: None, location });
+        }
+    }
+
+    /// Names declared by parameters and local declarations anywhere in `node`.
+    /// Scopes are not tracked: a name declared in one block shadows it in the whole function.
+    fn declare_locals(&mut self, node: tree_sitter::Node, code: &[u8]) {
+        if matches!(node.kind(), "parameter_declaration" | "declaration") {
+            let mut cursor = node.walk();
+            for declarator in node.children_by_field_name("declarator", &mut cursor) {
+                if let Some(name) = find_identifier_safe(declarator, code) {
+                    self.locals.insert(name);
+                }
+            }
+        }
+        for i in 0..node.child_count() {
+            if let Some(child) = node.child(i) {
+                self.declare_locals(child, code);
+            }
+        }
+    }
+
+    /// Classify identifiers in unparsed text (a macro body), skipping `params`.
+    /// Every reference is located at `node`.
+    pub(crate) fn visit_text(&mut self, text: &str, params: &[&str], node: tree_sitter::Node) {
+        let bytes = text.as_bytes();
+        let mut start = None;
+        for (i, &b) in bytes.iter().chain(std::iter::once(&b' ')).enumerate() {
+            let ident_char = b == b'_' || b.is_ascii_alphanumeric();
+            match (start, ident_char) {
+                (None, true) if !b.is_ascii_digit() => start = Some(i),
+                (Some(s), false) => {
+                    start = None;
+                    let word = &text[s..i];
+                    if params.contains(&word) {
+                        continue;
+                    }
+                    let called = text[i..].trim_start().starts_with('(');
+                    let kind = if self.registry.get_macro(word).is_some() {
+                        DependencyKind::MacroUse
+                    } else if self.registry.get_global(word).is_some() {
+                        DependencyKind::GlobalRead
+                    } else if self.registry.get_type(word).is_some() {
+                        DependencyKind::TypeUse
+                    } else if called && self.registry.get_prototype(word).is_some() {
+                        DependencyKind::DirectCall
+                    } else {
+                        continue;
+                    };
+                    self.record(word.to_string(), kind, node);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Extract dependencies safely (no unwrap)
+    pub(crate) fn visit(&mut self, node: tree_sitter::Node, code: &[u8]) {
+        match node.kind() {
+            "function_definition" => {
+                // The function's own name is not a local; its declarator holds the parameters
+                if let Some(declarator) = node.child_by_field_name("declarator") {
+                    self.declare_locals(declarator, code);
+                }
+                if let Some(body) = node.child_by_field_name("body") {
+                    self.declare_locals(body, code);
+                }
+            }
+            // Parameters of a prototype or function pointer (`int helper(int ticks);`)
+            "parameter_list" => self.declare_locals(node, code),
+            "call_expression" => {
+                if let Some(func_node) = node.child_by_field_name("function")
+                    && let Ok(text) = func_node.utf8_text(code)
+                {
+                    // A call through a local function pointer depends on nothing outside
+                    let local = func_node.kind() == "identifier" && self.locals.contains(text);
+                    let kind = if local {
+                        None
+                    } else if func_node.kind() != "identifier" {
+                        Some(DependencyKind::IndirectCall)
+                    } else if self.registry.get_macro(text).is_some() {
+                        Some(DependencyKind::MacroUse)
+                    } else if self.registry.get_global(text).is_some() {
+                        // Calling through a file-scope function pointer also reads it
+                        self.record(text.to_string(), DependencyKind::GlobalRead, func_node);
+                        Some(DependencyKind::IndirectCall)
+                    } else {
+                        Some(DependencyKind::DirectCall)
+                    };
+                    if let Some(kind) = kind {
+                        self.record(text.to_string(), kind, func_node);
+                    }
+
+                    // The callee name itself is not a separate read
+                    if func_node.kind() == "identifier" {
+                        if let Some(args) = node.child_by_field_name("arguments") {
+                            self.visit(args, code);
+                        }
+                        return;
+                    }
+                }
+            }
+            "type_identifier" => {
+                if let Ok(text) = node.utf8_text(code) {
+                    self.record(text.to_string(), DependencyKind::TypeUse, node);
+                }
+            }
+            "identifier" => {
+                if let Ok(text) = node.utf8_text(code) {
+                    if self.registry.get_macro(text).is_some() {
+                        self.record(text.to_string(), DependencyKind::MacroUse, node);
+                    } else if self.locals.contains(text) {
+                        // A parameter or local variable, whatever else shares its name
+                    } else if self.registry.get_global(text).is_some() {
+                        let kind = if is_write_target(node) {
+                            DependencyKind::GlobalWrite
+                        } else {
+                            DependencyKind::GlobalRead
+                        };
+                        self.record(text.to_string(), kind, node);
+                    } else if self.registry.is_function(text) && !is_declarator_name(node) {
+                        // Taking a function's address (`handler = foo`, `{ sys_fork }`)
+                        // means it can be called through the pointer
+                        self.record(text.to_string(), DependencyKind::IndirectCall, node);
+                    } else if !is_declarator_name(node) {
+                        self.record_unclassified(text, node);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for i in 0..node.child_count() {
+            if let Some(child) = node.child(i) {
+                self.visit(child, code);
+            }
+        }
+    }
+}
+
+/// True if the identifier is the name being declared (a function, parameter,
+/// variable or enum constant), not a reference
+fn is_declarator_name(node: tree_sitter::Node) -> bool {
+    node.parent()
+        .and_then(|parent| {
+            let field = if parent.kind() == "enumerator" { "name" } else { "declarator" };
+            parent.child_by_field_name(field)
+        })
+        .is_some_and(|declarator| declarator.id() == node.id())
+}
+
+/// True if the identifier is (the base of) the left side of an assignment or the
+/// operand of `++`/`--`, e.g. `ticks++` or `procs[i].state = RUNNABLE`
+fn is_write_target(node: tree_sitter::Node) -> bool {
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        match parent.kind() {
+            "assignment_expression" => {
+                return parent.child_by_field_name("left").map(|n| n.id()) == Some(current.id());
+            }
+            "update_expression" => return true,
+            "subscript_expression" | "field_expression" => {
+                if parent.child_by_field_name("argument").map(|n| n.id()) != Some(current.id()) {
+                    return false;
+                }
+            }
+            "parenthesized_expression" => {}
+            _ => return false,
+        }
+        current = parent;
+    }
+    false
+}
@@ -0,0 +1,331 @@
+//! Symbol index: where every function, type, macro, global and enum constant is
+//! defined, and which units use it.
+
+use crate::extract::{self, is_prototype_declarator, DependencyCollector};
+use crate::{collect_source_files, relative_source, Diagnostic, SliceConfig, TypeRegistry};
+use anyhow::{Context, Result};
+use kernel_schema::{DependencyKind, SourceLocation, UnitKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use tree_sitter::Node;
+
+/// What an [`IndexedSymbol`] names
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexKind {
+    Function,
+    Type,
+    Macro,
+    Global,
+    EnumConstant,
+}
+
+impl IndexKind {
+    /// Kind of symbol a dependency of `kind` refers to
+    fn of_dependency(kind: DependencyKind) -> Self {
+        match kind {
+            DependencyKind::DirectCall | DependencyKind::IndirectCall => IndexKind::Function,
+            DependencyKind::TypeUse => IndexKind::Type,
+            DependencyKind::GlobalRead | DependencyKind::GlobalWrite => IndexKind::Global,
+            DependencyKind::MacroUse => IndexKind::Macro,
+        }
+    }
+
+    /// Prefix used in symbol IDs; matches the symbol table where the kinds overlap
+    pub fn prefix(self) -> &'static str {
+        match self {
+            IndexKind::Function => "fn",
+            IndexKind::Type => "type",
+            IndexKind::Macro => "macro",
+            IndexKind::Global => "global",
+            IndexKind::EnumConstant => "enum_const",
+        }
+    }
+}
+
+/// A reference to a symbol from inside a unit
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UseSite {
+    pub unit: String,
+    #[serde(flatten)]
+    pub location: SourceLocation,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IndexedSymbol {
+    pub id: String,
+    pub kind: IndexKind,
+    pub name: String,
+    /// Every place the symbol is defined or declared, prototypes and `extern`
+    /// declarations included; empty for symbols defined outside the sources (`printf`)
+    pub definitions: Vec<SourceLocation>,
+    /// Every reference from a unit, in file/line order. References in a
+    /// declaration count toward the unit declared: the parameter types of a
+    /// prototype toward the function, an initializer toward its global.
+    pub uses: Vec<UseSite>,
+}
+
+impl IndexedSymbol {
+    /// Distinct units that reference this symbol, sorted by name
+    pub fn users(&self) -> Vec<&str> {
+        let users: BTreeSet<&str> = self.uses.iter().map(|u| u.unit.as_str()).collect();
+        users.into_iter().collect()
+    }
+}
+
+/// All indexed symbols, sorted by ID
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SymbolIndex {
+    pub symbols: Vec<IndexedSymbol>,
+    /// Non-fatal problems encountered while indexing (not serialized)
+    #[serde(skip)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl SymbolIndex {
+    pub fn get(&self, id: &str) -> Option<&IndexedSymbol> {
+        self.symbols
+            .binary_search_by(|s| s.id.as_str().cmp(id))
+            .ok()
+            .map(|i| &self.symbols[i])
+    }
+
+    /// All symbols called `name`, of any kind (`buf` matches both a struct and a global)
+    pub fn find_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a IndexedSymbol> + 'a {
+        self.symbols.iter().filter(move |s| s.name == name)
+    }
+}
+
+#[derive(Default)]
+struct IndexBuilder {
+    definitions: BTreeMap<(IndexKind, String), BTreeSet<SourceLocation>>,
+    uses: BTreeMap<(IndexKind, String), BTreeSet<UseSite>>,
+}
+
+/// Build a symbol index for every C source file under `config.source`.
+///
+/// Uses are found the way the slicer finds dependencies, with the same type
+/// registry, so the index and the units agree on what refers to what.
+pub fn build_index(config: &SliceConfig) -> Result<SymbolIndex> {
+    let mut files = Vec::new();
+    collect_source_files(&config.source, &mut files)?;
+
+    let mut registry = TypeRegistry::new();
+    let mut diagnostics = Vec::new();
+    for path in &files {
+        if let Err(e) = extract::collect_types_from_file(path, &mut registry, &mut diagnostics) {
+            diagnostics.push(Diagnostic::error(path, format!("{:#}", e)));
+        }
+    }
+
+    let mut builder = IndexBuilder::default();
+    for path in &files {
+        if let Err(e) = index_file(path, &config.source, &registry, &mut builder, &mut diagnostics) {
+            diagnostics.push(Diagnostic::error(path, format!("{:#}", e)));
+        }
+    }
+
+    let mut index = builder.finish();
+    index.diagnostics = diagnostics;
+    Ok(index)
+}
+
+fn index_file(
+    path: &Path,
+    source_root: &Path,
+    registry: &TypeRegistry,
+    builder: &mut IndexBuilder,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let code_raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read file {:?}", path))?;
+    let Some(tree) = extract::parse_file(path, &code_raw, diagnostics)? else { return Ok(()) };
+
+    let file = relative_source(source_root, path);
+    let mut walker = Walker { code: code_raw.as_bytes(), file: &file, registry, builder };
+    walker.collect_definitions(tree.root_node(), false);
+    Ok(())
+}
+
+fn location(node: Node, file: &str) -> SourceLocation {
+    let pos = node.start_position();
+    SourceLocation {
+        file: file.to_string(),
+        line: pos.row + 1,
+        column: pos.column + 1,
+    }
+}
+
+fn text(node: Node, code: &[u8]) -> Option<String> {
+    node.utf8_text(code).ok().map(|s| s.to_string())
+}
+
+/// One file's definitions and uses, going into `builder`
+struct Walker<'a> {
+    code: &'a [u8],
+    file: &'a str,
+    registry: &'a TypeRegistry,
+    builder: &'a mut IndexBuilder,
+}
+
+impl Walker<'_> {
+    fn define(&mut self, kind: IndexKind, name_node: Option<Node>) {
+        if let Some(name_node) = name_node
+            && let Some(name) = text(name_node, self.code)
+        {
+            self.builder.definitions.entry((kind, name)).or_default().insert(location(name_node, self.file));
+        }
+    }
+
+    /// Record a definition of `name_node` and the references `node` makes as
+    /// part of that unit
+    fn define_unit(&mut self, kind: IndexKind, name_node: Option<Node>, node: Node) {
+        self.define(kind, name_node);
+        if let Some(name) = name_node.and_then(|n| text(n, self.code)) {
+            self.collect_uses(node, kind, &name);
+        }
+    }
+
+    /// Walk the tree recording definitions and, through `collect_uses`, the
+    /// references made by functions, declarations, types and macros
+    fn collect_definitions(&mut self, node: Node, top_level: bool) {
+        match node.kind() {
+            "function_definition" => {
+                let name_node = node.child_by_field_name("declarator").and_then(find_identifier_node);
+                self.define_unit(IndexKind::Function, name_node, node);
+                return;
+            }
+            // Only bodies define a type; `struct proc *p` is a use
+            "struct_specifier" | "union_specifier" | "enum_specifier" if node.child_by_field_name("body").is_some() => {
+                self.define_unit(IndexKind::Type, node.child_by_field_name("name"), node);
+            }
+            "type_definition" => {
+                let mut cursor = node.walk();
+                let declarators: Vec<Node> = node.children_by_field_name("declarator", &mut cursor).collect();
+                for declarator in declarators {
+                    self.define_unit(IndexKind::Type, find_identifier_node(declarator), node);
+                }
+            }
+            "enumerator" => self.define(IndexKind::EnumConstant, node.child_by_field_name("name")),
+            "preproc_def" | "preproc_function_def" => {
+                let name_node = node.child_by_field_name("name");
+                self.define(IndexKind::Macro, name_node);
+                if let Some(name) = name_node.and_then(|n| text(n, self.code)) {
+                    self.collect_macro_uses(node, &name);
+                }
+                return;
+            }
+            "declaration" if top_level => {
+                let mut cursor = node.walk();
+                let declarators: Vec<Node> = node.children_by_field_name("declarator", &mut cursor).collect();
+                for declarator in declarators {
+                    let kind = if is_prototype_declarator(declarator) { IndexKind::Function } else { IndexKind::Global };
+                    self.define_unit(kind, find_identifier_node(declarator), node);
+                }
+            }
+            _ => {}
+        }
+
+        // Declarations nested in preprocessor conditionals are still file scope
+        let children_top_level = matches!(
+            node.kind(),
+            "translation_unit" | "preproc_if" | "preproc_ifdef" | "preproc_else" | "preproc_elif"
+        );
+        for i in 0..node.child_count() {
+            if let Some(child) = node.child(i) {
+                self.collect_definitions(child, children_top_level);
+            }
+        }
+    }
+
+    /// Record the references the slicer would turn into dependencies of the
+    /// `kind` unit `name`, at every site rather than once per dependency
+    fn collect_uses(&mut self, node: Node, kind: IndexKind, name: &str) {
+        let mut collector = DependencyCollector::new(self.registry, self.file).with_sites();
+        collector.visit(node, self.code);
+        self.add_uses(collector, kind, name);
+    }
+
+    /// Macro bodies are unparsed text, scanned the way the slicer scans them
+    fn collect_macro_uses(&mut self, node: Node, name: &str) {
+        let Some(body) = node.child_by_field_name("value") else { return };
+        let Ok(body_text) = body.utf8_text(self.code) else { return };
+        let mut collector = DependencyCollector::new(self.registry, self.file).with_sites();
+        collector.visit_text(body_text, &extract::macro_params(node, self.code), body);
+        self.add_uses(collector, IndexKind::Macro, name);
+    }
+
+    fn add_uses(&mut self, collector: DependencyCollector, kind: IndexKind, name: &str) {
+        let unit = match kind {
+            IndexKind::Function => name.to_string(),
+            IndexKind::Type => UnitKind::TypeDefinition.unit_id(name),
+            IndexKind::Macro => UnitKind::FunctionMacro.unit_id(name),
+            IndexKind::Global | IndexKind::EnumConstant => UnitKind::GlobalData.unit_id(name),
+        };
+        for reference in collector.into_sites() {
+            // The registry does not know enum constants; any other unknown name is defined nowhere
+            let used = reference.kind.map_or(IndexKind::EnumConstant, IndexKind::of_dependency);
+            // As in the slicer, only functions use themselves (recursion);
+            // `struct proc { struct proc *parent; }` does not
+            if used == kind && reference.name == name && kind != IndexKind::Function {
+                continue;
+            }
+            let site = UseSite { unit: unit.clone(), location: reference.location };
+            self.builder.uses.entry((used, reference.name)).or_default().insert(site);
+        }
+    }
+}
+
+fn find_identifier_node(node: Node) -> Option<Node> {
+    if node.kind() == "identifier" || node.kind() == "type_identifier" {
+        return Some(node);
+    }
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i)
+            && let Some(found) = find_identifier_node(child)
+        {
+            return Some(found);
+        }
+    }
+    None
+}
+
+impl IndexBuilder {
+    fn finish(mut self) -> SymbolIndex {
+        // Symbols used but defined outside the sources are kept with no
+        // definitions, except names the registry could not classify: those
+        // were only guessed to be enum constants
+        let undefined: Vec<(IndexKind, String)> = self
+            .uses
+            .keys()
+            .filter(|key| key.0 != IndexKind::EnumConstant && !self.definitions.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in undefined {
+            self.definitions.insert(key, BTreeSet::new());
+        }
+
+        let mut symbols: Vec<IndexedSymbol> = self
+            .definitions
+            .into_iter()
+            .map(|((kind, name), definitions)| {
+                let uses = self.uses.remove(&(kind, name.clone())).unwrap_or_default();
+                IndexedSymbol {
+                    id: format!("{}:{}", kind.prefix(), name),
+                    kind,
+                    name,
+                    definitions: definitions.into_iter().collect(),
+                    uses: uses.into_iter().collect(),
+                }
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.id.cmp(&b.id));
+
+        SymbolIndex {
+            symbols,
+            diagnostics: Vec::new(),
+        }
+    }
+}
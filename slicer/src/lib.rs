@@ -0,0 +1,240 @@
+//! Slicer: extracts Atomic Units from C source trees.
+//!
+//! The `slicer` binary is a thin wrapper around [`slice`]; other crates (language
+//! server plugins, test harnesses) can call it directly instead of round-tripping
+//! through `units.json`.
+
+mod extract;
+mod index;
+mod registry;
+
+pub use index::{build_index, IndexKind, IndexedSymbol, SymbolIndex, UseSite};
+pub use registry::TypeRegistry;
+
+use anyhow::{Context, Result};
+use kernel_schema::{digest, AtomicUnit, GeneratorInfo, SymbolTable, UnitKind, UnitsFile, UnitsHeader};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How shared context (types, macros, globals, prototypes) is stored in units.json
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// One symbol table; units reference entries by ID
+    #[default]
+    Table,
+    /// Definitions copied into each unit's `required_headers`
+    Inline,
+}
+
+/// Options controlling a slicing run
+#[derive(Debug, Clone)]
+pub struct SliceConfig {
+    /// C source file or directory to slice
+    pub source: PathBuf,
+    /// Layout of the units file produced by [`SliceResult::to_units_file`]
+    pub layout: Layout,
+    /// Kinds of unit to extract (empty means all)
+    pub unit_kinds: Vec<UnitKind>,
+}
+
+impl SliceConfig {
+    pub fn new(source: impl Into<PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            layout: Layout::default(),
+            unit_kinds: Vec::new(),
+        }
+    }
+
+    /// True if units of `kind` are extracted
+    pub fn emits(&self, kind: UnitKind) -> bool {
+        self.unit_kinds.is_empty() || self.unit_kinds.contains(&kind)
+    }
+
+    /// Digest of every option that affects the output, recorded in units.json
+    pub fn digest(&self) -> String {
+        let mut kinds: Vec<&str> = self.unit_kinds.iter().map(|k| k.as_str()).collect();
+        kinds.sort_unstable();
+        let canonical = format!(
+            "source={}\nlayout={:?}\nunit_kinds={}",
+            self.source.display(),
+            self.layout,
+            kinds.join(",")
+        );
+        digest(canonical.as_bytes())
+    }
+
+    /// Envelope header for a units file produced with these options
+    pub fn units_header(&self) -> UnitsHeader {
+        UnitsHeader::new(
+            GeneratorInfo::new("slicer", env!("CARGO_PKG_VERSION")),
+            self.source.display().to_string(),
+            self.digest(),
+        )
+    }
+}
+
+/// Everything produced by a slicing run
+#[derive(Debug, Default)]
+pub struct SliceResult {
+    /// Source files that were analyzed, in processing order
+    pub files: Vec<PathBuf>,
+    /// Extracted units; context is referenced through `symbols`
+    pub units: Vec<AtomicUnit>,
+    /// Deduplicated definitions referenced by `units`
+    pub symbols: SymbolTable,
+    /// Non-fatal problems encountered while slicing
+    pub diagnostics: Vec<Diagnostic>,
+    /// Type and macro tables collected across all files
+    pub registry: TypeRegistry,
+}
+
+impl SliceResult {
+    /// Versioned units.json envelope in the layout requested by `config`
+    pub fn to_units_file(&self, config: &SliceConfig) -> UnitsFile {
+        let (symbols, units) = match config.layout {
+            Layout::Table => (self.symbols.clone(), self.units.clone()),
+            Layout::Inline => (SymbolTable::new(), self.inline_units()),
+        };
+        UnitsFile::from_parts(config.units_header(), symbols, units)
+    }
+
+    /// Units with their referenced definitions copied into `required_headers`
+    pub fn inline_units(&self) -> Vec<AtomicUnit> {
+        self.units.iter().map(|unit| self.symbols.inline_unit(unit)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A non-fatal problem tied to a source file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(path: &Path, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            path: path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+
+    pub fn error(path: &Path, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        write!(f, "{}: {:?}: {}", level, self.path, self.message)
+    }
+}
+
+/// Slice every C source file under `config.source` into Atomic Units.
+///
+/// Files that cannot be read or parsed are reported in
+/// [`SliceResult::diagnostics`] rather than aborting the run.
+pub fn slice(config: &SliceConfig) -> Result<SliceResult> {
+    let mut units = Vec::new();
+    let mut result = slice_each(config, |unit, _| {
+        units.push(unit);
+        Ok(())
+    })?;
+    result.units = units;
+    Ok(result)
+}
+
+/// Like [`slice`], but hands each unit to `sink` as soon as its file has been
+/// processed instead of collecting them, so output can be streamed.
+///
+/// `sink` also receives the symbol table as built so far, which covers every
+/// reference of the unit it is given. The returned [`SliceResult::units`] is empty.
+/// An error from `sink` aborts the run.
+pub fn slice_each<F>(config: &SliceConfig, mut sink: F) -> Result<SliceResult>
+where
+    F: FnMut(AtomicUnit, &SymbolTable) -> Result<()>,
+{
+    let mut result = SliceResult::default();
+
+    // Collect all files to process
+    collect_source_files(&config.source, &mut result.files)?;
+
+    // First pass: collect all type definitions across all files
+    let mut unreadable = HashSet::new();
+    for path in &result.files {
+        if let Err(e) = extract::collect_types_from_file(path, &mut result.registry, &mut result.diagnostics) {
+            result.diagnostics.push(Diagnostic::error(path, format!("{:#}", e)));
+            unreadable.insert(path);
+        }
+    }
+
+    // Second pass: extract functions with cross-file type resolution; files
+    // the first pass could not read were already reported
+    let mut file_units = Vec::new();
+    for path in result.files.iter().filter(|path| !unreadable.contains(path)) {
+        if let Err(e) = extract::extract_units_from_file(
+            path,
+            config,
+            &mut file_units,
+            &result.registry,
+            &mut result.symbols,
+            &mut result.diagnostics,
+        ) {
+            result.diagnostics.push(Diagnostic::error(path, format!("{:#}", e)));
+        }
+        for unit in file_units.drain(..) {
+            sink(unit, &result.symbols)?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Path of `file` relative to the slicing root, as recorded in symbol tables
+pub(crate) fn relative_source(root: &Path, file: &Path) -> String {
+    let relative = match file.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel,
+        _ => file.file_name().map(Path::new).unwrap_or(file),
+    };
+    relative.to_string_lossy().into_owned()
+}
+
+/// Recursively collect all .c and .h files from a path, in path order (not
+/// `read_dir` order, which varies by filesystem) so output is reproducible
+pub fn collect_source_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_file() {
+        let ext = path.extension().and_then(|s| s.to_str());
+        if ext == Some("c") || ext == Some("h") {
+            files.push(path.to_path_buf());
+        }
+    } else if path.is_dir() {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path).with_context(|| format!("Failed to read directory {:?}", path))? {
+            entries.push(entry?.path());
+        }
+        entries.sort();
+        for entry_path in entries {
+            collect_source_files(&entry_path, files)?;
+        }
+    }
+    Ok(())
+}
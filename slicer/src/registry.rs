@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Global type registry for cross-file type resolution
+#[derive(Debug, Default)]
+pub struct TypeRegistry {
+    /// Maps type name -> full definition text
+    types: HashMap<String, String>,
+    /// Maps type name -> file where it was defined (for debugging)
+    type_sources: HashMap<String, PathBuf>,
+    /// Tracks #include directives per file
+    includes: HashMap<PathBuf, Vec<String>>,
+    /// Macro definitions (#define)
+    macros: HashMap<String, String>,
+    /// Maps macro name -> file where it was defined
+    macro_sources: HashMap<String, PathBuf>,
+    /// File-scope variable declarations
+    globals: HashMap<String, String>,
+    /// Maps global name -> file where it was declared
+    global_sources: HashMap<String, PathBuf>,
+    /// Function declarations without a body
+    prototypes: HashMap<String, String>,
+    /// Maps prototype name -> file where it was declared
+    prototype_sources: HashMap<String, PathBuf>,
+    /// Names of functions defined with a body
+    functions: HashSet<String>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_type(&mut self, name: String, definition: String, source_file: PathBuf) {
+        // Prefer longer definitions (full struct body over forward declaration)
+        // Forward declarations like "struct proc;" are shorter than full definitions
+        let should_insert = match self.types.get(&name) {
+            None => true,
+            Some(existing) => {
+                // Replace if the existing entry is only a forward declaration (no '{')
+                // and the new definition is either a full body or simply more complete
+                let new_has_body = definition.contains('{');
+                let old_has_body = existing.contains('{');
+                !old_has_body && (new_has_body || definition.len() > existing.len())
+            }
+        };
+
+        if should_insert {
+            self.types.insert(name.clone(), definition);
+            self.type_sources.insert(name, source_file);
+        }
+    }
+
+    pub fn get_type(&self, name: &str) -> Option<&String> {
+        self.types.get(name)
+    }
+
+    /// File the registered definition of `name` was taken from
+    pub fn type_source(&self, name: &str) -> Option<&Path> {
+        self.type_sources.get(name).map(PathBuf::as_path)
+    }
+
+    pub fn register_include(&mut self, file: PathBuf, include: String) {
+        self.includes.entry(file).or_default().push(include);
+    }
+
+    /// `#include` directives seen in `file`, in source order
+    pub fn includes(&self, file: &Path) -> &[String] {
+        self.includes.get(file).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn register_macro(&mut self, name: String, definition: String, source_file: PathBuf) {
+        if !self.macros.contains_key(&name) {
+            self.macros.insert(name.clone(), definition);
+            self.macro_sources.insert(name, source_file);
+        }
+    }
+
+    pub fn get_macro(&self, name: &str) -> Option<&String> {
+        self.macros.get(name)
+    }
+
+    /// File the registered definition of macro `name` was taken from
+    pub fn macro_source(&self, name: &str) -> Option<&Path> {
+        self.macro_sources.get(name).map(PathBuf::as_path)
+    }
+
+    pub fn register_global(&mut self, name: String, declaration: String, source_file: PathBuf) {
+        // Prefer the defining declaration over an `extern` one seen in a header
+        let should_insert = match self.globals.get(&name) {
+            None => true,
+            Some(existing) => existing.trim_start().starts_with("extern") && !declaration.trim_start().starts_with("extern"),
+        };
+
+        if should_insert {
+            self.globals.insert(name.clone(), declaration);
+            self.global_sources.insert(name, source_file);
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&String> {
+        self.globals.get(name)
+    }
+
+    /// File the registered declaration of global `name` was taken from
+    pub fn global_source(&self, name: &str) -> Option<&Path> {
+        self.global_sources.get(name).map(PathBuf::as_path)
+    }
+
+    pub fn register_prototype(&mut self, name: String, declaration: String, source_file: PathBuf) {
+        if !self.prototypes.contains_key(&name) {
+            self.prototypes.insert(name.clone(), declaration);
+            self.prototype_sources.insert(name, source_file);
+        }
+    }
+
+    pub fn get_prototype(&self, name: &str) -> Option<&String> {
+        self.prototypes.get(name)
+    }
+
+    /// File the registered prototype of `name` was taken from
+    pub fn prototype_source(&self, name: &str) -> Option<&Path> {
+        self.prototype_sources.get(name).map(PathBuf::as_path)
+    }
+
+    pub fn register_function(&mut self, name: String) {
+        self.functions.insert(name);
+    }
+
+    /// True if `name` is a function, defined or only declared
+    pub fn is_function(&self, name: &str) -> bool {
+        self.functions.contains(name) || self.prototypes.contains_key(name)
+    }
+
+    /// All registered type definitions, keyed by type name
+    pub fn types(&self) -> &HashMap<String, String> {
+        &self.types
+    }
+
+    /// All registered macro definitions, keyed by macro name
+    pub fn macros(&self) -> &HashMap<String, String> {
+        &self.macros
+    }
+
+    /// All registered global declarations, keyed by variable name
+    pub fn globals(&self) -> &HashMap<String, String> {
+        &self.globals
+    }
+
+    /// All registered function prototypes, keyed by function name
+    pub fn prototypes(&self) -> &HashMap<String, String> {
+        &self.prototypes
+    }
+}
@@ -0,0 +1,122 @@
+use kernel_schema::{AtomicUnit, DependencyKind, UnitKind};
+use slicer::{slice, SliceConfig, SliceResult};
+use std::fs;
+
+const PROC: &str = r#"#define NPROC 4
+#define MAX(a, b) ((a) > (b) ? (a) : (b))
+struct proc { int pid; int state; };
+typedef int (*handler_t)(int);
+int ticks;
+struct proc procs[NPROC];
+int nextpid;
+int helper(int x) { return x + 1; }
+int apply(handler_t h, int v) { return h(v); }
+void tick(void) {
+    ticks++;
+    helper(ticks);
+    helper(1);
+    nextpid = MAX(nextpid, ticks);
+}
+int count(void) {
+    struct proc *p;
+    int n = 0;
+    for (p = procs; p < &procs[NPROC]; p++)
+        if (p->pid)
+            n++;
+    return apply(helper, n) + printf("%d", n);
+}
+"#;
+
+fn slice_files(name: &str, files: &[(&str, &str)]) -> SliceResult {
+    let dir = std::env::temp_dir().join(format!("slicer-deps-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, code) in files {
+        fs::write(dir.join(file), code).unwrap();
+    }
+    let result = slice(&SliceConfig::new(&dir)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    result
+}
+
+fn unit<'a>(result: &'a SliceResult, id: &str) -> &'a AtomicUnit {
+    result.units.iter().find(|unit| unit.id == id).unwrap()
+}
+
+/// `(target, kind, call_sites)` of every dependency, in order of first use
+fn deps(unit: &AtomicUnit) -> Vec<(&str, DependencyKind, usize)> {
+    unit.dependencies.iter().map(|dep| (dep.target.as_str(), dep.kind, dep.call_sites)).collect()
+}
+
+#[test]
+fn dependencies_are_typed_and_counted() {
+    use DependencyKind::*;
+    let result = slice_files("typed", &[("proc.c", PROC)]);
+
+    assert_eq!(
+        deps(unit(&result, "tick")),
+        vec![
+            ("ticks", GlobalWrite, 1),
+            ("helper", DirectCall, 2),
+            ("ticks", GlobalRead, 2),
+            ("nextpid", GlobalWrite, 1),
+            ("MAX", MacroUse, 1),
+            ("nextpid", GlobalRead, 1),
+        ]
+    );
+    // Passing `helper` as a value makes it an indirect call; the call through
+    // the parameter `h` in `apply` is not a dependency on anything
+    assert_eq!(
+        deps(unit(&result, "count")),
+        vec![
+            ("proc", TypeUse, 1),
+            ("procs", GlobalRead, 2),
+            ("NPROC", MacroUse, 1),
+            ("apply", DirectCall, 1),
+            ("helper", IndirectCall, 1),
+            ("printf", DirectCall, 1),
+        ]
+    );
+    assert_eq!(deps(unit(&result, "apply")), vec![("handler_t", TypeUse, 1)]);
+    assert!(deps(unit(&result, "helper")).is_empty());
+    assert_eq!(deps(unit(&result, "global:procs")), vec![("proc", TypeUse, 1), ("NPROC", MacroUse, 1)]);
+
+    // Each dependency points at its first use
+    let first = unit(&result, "tick").dependencies.iter().find(|dep| dep.kind == GlobalRead).unwrap();
+    let location = first.location.as_ref().unwrap();
+    assert_eq!((location.file.as_str(), location.line, location.column), ("proc.c", 12, 12));
+}
+
+#[test]
+fn every_definition_becomes_a_unit_of_its_kind() {
+    let result = slice_files("kinds", &[("proc.c", PROC)]);
+    let kinds: Vec<(&str, UnitKind)> = result.units.iter().map(|unit| (unit.id.as_str(), unit.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("helper", UnitKind::Function),
+            ("apply", UnitKind::Function),
+            ("tick", UnitKind::Function),
+            ("count", UnitKind::Function),
+            ("type:proc", UnitKind::TypeDefinition),
+            ("type:handler_t", UnitKind::TypeDefinition),
+            ("global:ticks", UnitKind::GlobalData),
+            ("global:procs", UnitKind::GlobalData),
+            ("global:nextpid", UnitKind::GlobalData),
+            ("macro:MAX", UnitKind::FunctionMacro),
+        ]
+    );
+}
+
+#[test]
+fn declarations_in_headers_classify_uses_in_other_files() {
+    use DependencyKind::*;
+    let header = "struct buf { int dev; };\nextern struct buf bcache[8];\nextern int nbuf;\nvoid brelse(struct buf *b);\n";
+    let code = "#include \"buf.h\"\nvoid binit(void) {\n    struct buf *b = &bcache[0];\n    nbuf = 8;\n    brelse(b);\n    brelse(b);\n}\n";
+    let result = slice_files("headers", &[("buf.h", header), ("bio.c", code)]);
+
+    assert_eq!(
+        deps(unit(&result, "binit")),
+        vec![("buf", TypeUse, 1), ("bcache", GlobalRead, 1), ("nbuf", GlobalWrite, 1), ("brelse", DirectCall, 2)]
+    );
+}
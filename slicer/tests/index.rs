@@ -0,0 +1,110 @@
+use kernel_schema::DependencyKind;
+use slicer::{build_index, slice, SliceConfig};
+use std::fs;
+use std::path::PathBuf;
+
+/// A parameter, a local and a struct field all share the global `ticks`'s name
+const DEFS: &str = r#"#define NPROC 4
+enum state { UNUSED, RUNNING };
+struct proc { int ticks; enum state state; };
+int ticks;
+struct proc procs[NPROC];
+int helper(int ticks);
+"#;
+
+const CODE: &str = r#"#include "defs.h"
+int helper(int ticks) { return ticks + 1; }
+void tick(void) { ticks++; helper(ticks); }
+void scan(void) {
+    struct proc *p;
+    int ticks = 0;
+    for (p = procs; p < &procs[NPROC]; p++) {
+        if (p->state == RUNNING)
+            ticks += p->ticks;
+    }
+}
+"#;
+
+fn source_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("slicer-index-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("defs.h"), DEFS).unwrap();
+    fs::write(dir.join("proc.c"), CODE).unwrap();
+    dir
+}
+
+#[test]
+fn shadowing_names_are_not_uses() {
+    let dir = source_dir("shadowing");
+    let index = build_index(&SliceConfig::new(&dir)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let ticks = index.get("global:ticks").unwrap();
+    assert_eq!(ticks.users(), vec!["tick"]);
+    let lines: Vec<usize> = ticks.uses.iter().map(|site| site.location.line).collect();
+    assert_eq!(lines, vec![3, 3], "ticks++ and helper(ticks)");
+    assert_eq!(ticks.definitions.len(), 1);
+
+    assert_eq!(index.get("fn:helper").unwrap().users(), vec!["tick"]);
+    assert_eq!(index.get("enum_const:RUNNING").unwrap().users(), vec!["scan"]);
+    assert!(index.get("enum_const:UNUSED").unwrap().uses.is_empty());
+    // `struct proc procs[NPROC];` uses both as part of the global's unit
+    assert_eq!(index.get("macro:NPROC").unwrap().users(), vec!["global:procs", "scan"]);
+    assert_eq!(index.get("type:proc").unwrap().users(), vec!["global:procs", "scan"]);
+    assert_eq!(index.get("global:procs").unwrap().users(), vec!["scan"]);
+}
+
+#[test]
+fn index_agrees_with_unit_dependencies() {
+    let dir = source_dir("agree");
+    let config = SliceConfig::new(&dir);
+    let index = build_index(&config).unwrap();
+    let result = slice(&config).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    for (target, kinds, symbol) in [
+        ("ticks", &[DependencyKind::GlobalRead, DependencyKind::GlobalWrite][..], "global:ticks"),
+        ("procs", &[DependencyKind::GlobalRead, DependencyKind::GlobalWrite][..], "global:procs"),
+        ("helper", &[DependencyKind::DirectCall][..], "fn:helper"),
+        ("NPROC", &[DependencyKind::MacroUse][..], "macro:NPROC"),
+    ] {
+        let mut dependents: Vec<&str> = result
+            .units
+            .iter()
+            .filter(|unit| unit.dependencies.iter().any(|dep| dep.target == target && kinds.contains(&dep.kind)))
+            .map(|unit| unit.id.as_str())
+            .collect();
+        dependents.sort_unstable();
+        assert_eq!(dependents, index.get(symbol).unwrap().users(), "users of {}", symbol);
+    }
+}
+
+#[test]
+fn declarations_and_initializers_are_indexed() {
+    let dir = std::env::temp_dir().join(format!("slicer-index-{}-buf", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let header = "#define NBUF 8\n#define BSIZE(b) (sizeof(struct buf) * NBUF)\nstruct buf { int dev; struct buf *next; };\nvoid brelse(struct buf *b);\n";
+    let code = "#include \"buf.h\"\nstruct buf *head = 0;\nstatic struct buf *cache[NBUF] = { 0 };\nvoid brelse(struct buf *b) { head = b; }\nint bsize(void) { return printf(\"%d\", NBUF); }\n";
+    fs::write(dir.join("buf.h"), header).unwrap();
+    fs::write(dir.join("bio.c"), code).unwrap();
+    let index = build_index(&SliceConfig::new(&dir)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The prototype, both globals and the macro body use it; the struct's own
+    // `next` field does not count as a use of itself
+    let buf = index.get("type:buf").unwrap();
+    assert_eq!(buf.users(), vec!["brelse", "global:cache", "global:head", "macro:BSIZE"]);
+    let header_use = buf.uses.iter().find(|site| site.location.file == "buf.h" && site.unit == "brelse").unwrap();
+    assert_eq!(header_use.location.line, 4);
+
+    // The prototype is a declaration of brelse, alongside its definition
+    let brelse: Vec<(&str, usize)> =
+        index.get("fn:brelse").unwrap().definitions.iter().map(|def| (def.file.as_str(), def.line)).collect();
+    assert_eq!(brelse, vec![("bio.c", 4), ("buf.h", 4)]);
+    assert_eq!(index.get("macro:NBUF").unwrap().users(), vec!["bsize", "global:cache", "macro:BSIZE"]);
+
+    // Used but defined outside the sources
+    let printf = index.get("fn:printf").unwrap();
+    assert!(printf.definitions.is_empty());
+    assert_eq!(printf.users(), vec!["bsize"]);
+}
@@ -0,0 +1,76 @@
+use slicer::{slice, slice_each, Severity, SliceConfig};
+use std::fs;
+use std::path::PathBuf;
+
+const PROC: &str = "#define NPROC 64\n#define MIN(a, b) ((a) < (b) ? (a) : (b))\nstruct proc { int pid; };\nint nextpid = 1;\nint fork(void);\nint fork(void) { return nextpid++; }\n";
+const BROKEN: &str = "int ok(void) { return 1; }\nint broken(void) { return 1 +; }\nint after(void) { return 2; }\n";
+
+fn source_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("slicer-library-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, code) in files {
+        fs::write(dir.join(file), code).unwrap();
+    }
+    dir
+}
+
+/// Messages of the diagnostics for `file`, as `(is_error, message)`
+fn diagnostics_for(diagnostics: &[slicer::Diagnostic], file: &str) -> Vec<(bool, String)> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.path.ends_with(file))
+        .map(|diagnostic| (matches!(diagnostic.severity, Severity::Error), diagnostic.message.clone()))
+        .collect()
+}
+
+#[test]
+fn bad_files_are_reported_and_the_rest_is_sliced() {
+    let dir = source_dir("bad", &[("a_broken.c", BROKEN.as_bytes()), ("b_binary.c", b"int x = 1;\xff\xfe\n"), ("c_proc.c", PROC.as_bytes())]);
+    let result = slice(&SliceConfig::new(&dir)).unwrap();
+
+    let mut streamed = Vec::new();
+    let each = slice_each(&SliceConfig::new(&dir), |unit, _| {
+        streamed.push(unit.id);
+        Ok(())
+    })
+    .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        diagnostics_for(&result.diagnostics, "a_broken.c"),
+        vec![(false, "Syntax error at line 2, definitions around it may be missing".to_string())]
+    );
+    let unreadable = diagnostics_for(&result.diagnostics, "b_binary.c");
+    assert_eq!(unreadable.len(), 1, "reported once, not once per pass: {:?}", unreadable);
+    assert!(unreadable[0].0 && unreadable[0].1.starts_with("Failed to read file"), "{:?}", unreadable);
+    assert_eq!(result.diagnostics.len(), 2, "{:?}", result.diagnostics);
+
+    // Definitions on either side of the syntax error and the files after both are still sliced
+    let ids: Vec<&str> = result.units.iter().map(|unit| unit.id.as_str()).collect();
+    for id in ["ok", "after", "fork", "global:nextpid"] {
+        assert!(ids.contains(&id), "{} missing from {:?}", id, ids);
+    }
+    assert_eq!(streamed, ids);
+    assert_eq!(each.diagnostics.len(), 2);
+    assert!(each.units.is_empty(), "streamed units are not kept");
+}
+
+#[test]
+fn type_and_macro_tables_are_exposed() {
+    let dir = source_dir("registry", &[("proc.c", PROC.as_bytes())]);
+    let result = slice(&SliceConfig::new(&dir)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let registry = &result.registry;
+
+    assert_eq!(registry.get_type("proc").map(String::as_str), Some("struct proc { int pid; }"));
+    assert_eq!(registry.type_source("proc"), Some(dir.join("proc.c").as_path()));
+    assert_eq!(registry.get_macro("NPROC").map(|text| text.trim_end()), Some("#define NPROC 64"));
+    assert!(registry.get_macro("MIN").unwrap().starts_with("#define MIN(a, b)"));
+    let mut macros: Vec<&str> = registry.macros().keys().map(String::as_str).collect();
+    macros.sort();
+    assert_eq!(macros, vec!["MIN", "NPROC"]);
+    assert_eq!(registry.get_global("nextpid").map(String::as_str), Some("int nextpid = 1;"));
+    assert_eq!(registry.get_prototype("fork").map(String::as_str), Some("int fork(void);"));
+    assert!(registry.is_function("fork"));
+    assert!(registry.get_type("nextpid").is_none());
+}